@@ -0,0 +1,479 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand};
+use serde_json::json;
+use shared::{
+    ifc::IfcImport,
+    jobs::{Outcome, Progress, UpdateOptions},
+    lifecycle::Module,
+    material_db,
+    project::Project,
+    uncertainty::Simulation,
+    Material,
+};
+
+use crate::output::{Format, Table};
+
+/// Carbon: search EC3 materials and calculate the embodied carbon of projects.
+/// Without a subcommand the graphical interface is started.
+#[derive(Parser)]
+#[command(name = "carbon", version)]
+pub struct Cli {
+    /// Output format of the headless commands
+    #[arg(long, short, value_enum, global = true, default_value = "table")]
+    pub format: Format,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Download a new copy of the EC3 materials into the local database.
+    /// An interrupted update continues with the categories it did not finish.
+    UpdateDb {
+        /// Skip categories refreshed within this many hours
+        #[arg(long, default_value = "24")]
+        max_age_hours: u64,
+        /// Fetch every category, even if it was refreshed recently
+        #[arg(long)]
+        force: bool,
+    },
+    /// Search materials in the local database, e.g. `concrete gwp<250 country:DE sort:gwp`
+    Search {
+        /// Search query, bare words match material and category names.
+        /// Quote it as one argument, e.g. `'manufacturer:"Holcim AG" gwp<250'`
+        #[arg(default_value_t)]
+        query: String,
+        /// Country of the manufacturer
+        #[arg(long)]
+        country: Option<String>,
+        /// Part of the manufacturer name
+        #[arg(long)]
+        manufacturer: Option<String>,
+        /// Category, including its subcategories
+        #[arg(long)]
+        category: Option<String>,
+        #[arg(long)]
+        gwp_min: Option<f64>,
+        #[arg(long)]
+        gwp_max: Option<f64>,
+        /// Maximum number of results, 200 by default
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// List the materials of a category
+    Category {
+        name: String,
+        /// Print the category statistics instead of its materials
+        #[arg(long)]
+        stats: bool,
+    },
+    /// List materials whose GWP changed by more than a threshold the last time it was updated
+    Changes {
+        /// Minimum change of the GWP per declared unit, in percent
+        #[arg(long, default_value = "10")]
+        threshold: f64,
+        /// Only changes recorded within this many days
+        #[arg(long)]
+        since_days: Option<u64>,
+    },
+    /// Work with saved project files
+    #[command(subcommand)]
+    Project(ProjectCommand),
+}
+
+#[derive(Subcommand)]
+pub enum ProjectCommand {
+    /// Calculate the GWP of every component and the project total
+    Compute { file: PathBuf },
+    /// Write the calculated project to a .csv, .json, .xlsx, .html or .pdf file, by its extension
+    Export { file: PathBuf, output: PathBuf },
+    /// Add the materials of an IFC model to a project, which is created if it does not exist.
+    /// Only materials mapped to a category, by a previous import or with --map, are added.
+    ImportIfc {
+        model: PathBuf,
+        file: PathBuf,
+        /// Save a mapping first, e.g. `--map "Concrete C30/37=Concrete"`
+        #[arg(long = "map", value_name = "MATERIAL=CATEGORY")]
+        mappings: Vec<String>,
+    },
+    /// Run a Monte Carlo simulation of the project total. Options not given are those saved
+    /// with the project, the same seed always gives the same results.
+    Simulate {
+        file: PathBuf,
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long)]
+        iterations: Option<usize>,
+        /// ± range of the GWP of specific EPDs in percent
+        #[arg(long)]
+        epd_range: Option<f64>,
+        /// Print the histogram of the totals with this many bins instead of the percentiles
+        #[arg(long)]
+        histogram: Option<usize>,
+    },
+}
+
+/// Column names of [Module::ALL] in KgCO2e
+const MODULE_COLUMNS: [&str; 6] = ["a1_a3", "a4", "a5", "b4", "c1_c4", "d"];
+
+/// Runs a headless command, returning the error message to print on failure
+pub fn run(
+    command: Command,
+    format: Format,
+    api_key: impl FnOnce() -> Option<String>,
+) -> Result<(), String> {
+    match command {
+        Command::UpdateDb {
+            max_age_hours,
+            force,
+        } => update_db(
+            api_key(),
+            UpdateOptions {
+                max_age: Duration::from_secs(max_age_hours * 60 * 60),
+                force,
+            },
+        ),
+        Command::Search {
+            query,
+            country,
+            manufacturer,
+            category,
+            gwp_min,
+            gwp_max,
+            limit,
+        } => {
+            let query = shared::query::parse(&query)
+                .map_err(|e| query_error(&query, &e))?
+                .to_search()
+                .country(country.as_deref().unwrap_or_default())
+                .manufacturer(manufacturer.as_deref().unwrap_or_default())
+                .category(category.as_deref().unwrap_or_default())
+                .gwp_range(gwp_min, gwp_max);
+            let query = match limit {
+                Some(limit) => query.limit(limit),
+                None => query,
+            };
+            let materials = material_db::search(&query).map_err(|e| e.to_string())?;
+            materials_table(&materials).print(format);
+            Ok(())
+        }
+        Command::Category { name, stats } => {
+            if stats {
+                category_stats(&name, format)
+            } else {
+                let materials = material_db::load_category(&name).map_err(|e| e.to_string())?;
+                materials_table(&materials).print(format);
+                Ok(())
+            }
+        }
+        Command::Changes {
+            threshold,
+            since_days,
+        } => gwp_changes(threshold, since_days, format),
+        Command::Project(ProjectCommand::Compute { file }) => compute_project(&file, format),
+        Command::Project(ProjectCommand::Export { file, output }) => {
+            let mut project = Project::load(&file).map_err(|e| e.to_string())?;
+            let format =
+                shared::export::export(&mut project, &output).map_err(|e| e.to_string())?;
+            println!("Exported {} as {format}", output.display());
+            Ok(())
+        }
+        Command::Project(ProjectCommand::ImportIfc {
+            model,
+            file,
+            mappings,
+        }) => import_ifc(model, &file, &mappings, format),
+        Command::Project(ProjectCommand::Simulate {
+            file,
+            seed,
+            iterations,
+            epd_range,
+            histogram,
+        }) => {
+            let mut project = Project::load(&file).map_err(|e| e.to_string())?;
+            let settings = &mut project.uncertainty;
+            settings.seed = seed.unwrap_or(settings.seed);
+            settings.iterations = iterations.unwrap_or(settings.iterations);
+            settings.epd_range = epd_range.unwrap_or(settings.epd_range);
+            simulate(&project, histogram, format)
+        }
+    }
+}
+
+/// Formats a query error with a marker under the offending token
+fn query_error(input: &str, error: &shared::query::ParseError) -> String {
+    let offset = input[..error.span.start.min(input.len())].chars().count();
+    let width = input
+        .get(error.span.clone())
+        .map_or(1, |token| token.chars().count().max(1));
+    format!(
+        "{message}\n  {input}\n  {pad}{marker}",
+        message = error.message,
+        pad = " ".repeat(offset),
+        marker = "^".repeat(width)
+    )
+}
+
+fn update_db(api_key: Option<String>, options: UpdateOptions) -> Result<(), String> {
+    let api_key = api_key.ok_or(format!(
+        "API key not found. Save it from the app or write it to {}",
+        shared::settings::SettingsProvider::api_key_path().display()
+    ))?;
+    let mut job = shared::jobs::Runner::update_db(&api_key, options)
+        .map_err(|e| format!("could not start the update: {e}"))?;
+    let summary = loop {
+        match job.recv() {
+            Some(Progress::Category {
+                category,
+                outcome: Outcome::Failed(reason),
+                ..
+            }) => eprintln!("WARNING: {category} was not updated: {reason}"),
+            Some(Progress::Finished(summary)) => break summary,
+            Some(Progress::Error(e)) => return Err(format!("the update failed: {e}")),
+            Some(_) => {}
+            None => return Err("the update stopped before finishing".to_string()),
+        }
+    };
+    println!("DB Update finished! {summary}");
+    match summary.failed.len() {
+        0 => Ok(()),
+        n => Err(format!(
+            "{n} categories failed, run update-db again to retry them"
+        )),
+    }
+}
+
+fn materials_table(materials: &[Material]) -> Table {
+    let mut table = Table::new(vec![
+        "id",
+        "name",
+        "category",
+        "manufacturer",
+        "country",
+        "gwp",
+        "gwp_unit",
+        "declared_value",
+        "declared_unit",
+    ]);
+    for m in materials {
+        table.push(vec![
+            json!(m.id),
+            json!(m.name),
+            json!(m.category.name),
+            json!(m.manufacturer.name),
+            json!(m.manufacturer.country),
+            json!(m.gwp.value),
+            json!(format!("{:?}", m.gwp.unit)),
+            json!(m.declared_unit.value),
+            json!(format!("{:?}", m.declared_unit.unit)),
+        ]);
+    }
+    table
+}
+
+fn category_stats(name: &str, format: Format) -> Result<(), String> {
+    let stats = material_db::get_category_stats(name).map_err(|e| e.to_string())?;
+    let unit = material_db::get_category_unit(name).map_err(|e| e.to_string())?;
+
+    let mut table = Table::new(vec![
+        "category",
+        "materials",
+        "min",
+        "p10",
+        "p25",
+        "median",
+        "mean",
+        "p75",
+        "p90",
+        "max",
+        "declared_unit",
+    ]);
+    table.push(vec![
+        json!(name),
+        json!(stats.count),
+        json!(stats.min),
+        json!(stats.p10),
+        json!(stats.p25),
+        json!(stats.median),
+        json!(stats.mean),
+        json!(stats.p75),
+        json!(stats.p90),
+        json!(stats.max),
+        json!(format!("{} {:?}", unit.value, unit.unit)),
+    ]);
+    table.print(format);
+    Ok(())
+}
+
+fn gwp_changes(threshold: f64, since_days: Option<u64>, format: Format) -> Result<(), String> {
+    let since = since_days.map(|days| material_db::now() - days as i64 * 24 * 60 * 60);
+    let changes = material_db::gwp_changes(threshold, since).map_err(|e| e.to_string())?;
+
+    let mut table = Table::new(vec![
+        "id",
+        "name",
+        "category",
+        "previous",
+        "current",
+        "declared_unit",
+        "change_pct",
+        "changed_at",
+    ]);
+    for change in &changes {
+        table.push(vec![
+            json!(change.material_id),
+            json!(change.name),
+            json!(change.category),
+            json!(change.previous),
+            json!(change.current),
+            json!(change.declared_unit),
+            json!(change.percent()),
+            json!(material_db::format_date(change.changed_at)),
+        ]);
+    }
+    table.print(format);
+    Ok(())
+}
+
+fn compute_project(file: &Path, format: Format) -> Result<(), String> {
+    let mut project = Project::load(file).map_err(|e| e.to_string())?;
+    project.calculate();
+
+    let mut headers = vec!["material", "quantity", "unit"];
+    headers.extend(MODULE_COLUMNS);
+    headers.extend(["total_a_c", "percentile"]);
+    let mut table = Table::new(headers);
+    for (assembly, comp) in project.all_components() {
+        let name = match assembly {
+            Some(assembly) => format!("{assembly}: {}", comp.material.get_name()),
+            None => comp.material.get_name().to_string(),
+        };
+        let mut row = vec![
+            json!(name),
+            json!(comp.quantity),
+            json!(comp
+                .unit
+                .map_or(format!("{:?}", comp.material.get_unit().unit), |u| u
+                    .to_string())),
+        ];
+        if let Some(e) = &comp.conversion_error {
+            eprintln!("WARNING: {name} not calculated: {e}");
+        }
+        row.extend(Module::ALL.iter().map(|m| json!(comp.modules.get(*m))));
+        row.push(json!(comp.calculated));
+        row.push(json!(comp.percentile_rank()));
+        table.push(row);
+    }
+    let mut total = vec![json!("Total"), json!(null), json!(null)];
+    total.extend(Module::ALL.iter().map(|m| json!(project.modules.get(*m))));
+    total.extend([json!(project.calculated_gwp), json!(null)]);
+    table.push(total);
+    table.print(format);
+    Ok(())
+}
+
+fn simulate(project: &Project, histogram: Option<usize>, format: Format) -> Result<(), String> {
+    if project.uncertainty.iterations == 0 {
+        return Err("the number of iterations must be greater than 0".to_string());
+    }
+    let simulation = Simulation::run(project, project.uncertainty);
+    let table = match histogram {
+        Some(bins) => {
+            let mut table = Table::new(vec!["start", "end", "count"]);
+            for bin in simulation.histogram(bins) {
+                table.push(vec![json!(bin.start), json!(bin.end), json!(bin.count)]);
+            }
+            table
+        }
+        None => {
+            let mut table = Table::new(vec![
+                "seed",
+                "iterations",
+                "epd_range",
+                "total_a_c",
+                "mean",
+                "p10",
+                "p50",
+                "p90",
+            ]);
+            table.push(vec![
+                json!(simulation.settings.seed),
+                json!(simulation.settings.iterations),
+                json!(simulation.settings.epd_range),
+                json!(project.calculated_gwp),
+                json!(simulation.mean),
+                json!(simulation.p10),
+                json!(simulation.p50),
+                json!(simulation.p90),
+            ]);
+            table
+        }
+    };
+    table.print(format);
+    Ok(())
+}
+
+fn import_ifc(
+    model: PathBuf,
+    file: &Path,
+    mappings: &[String],
+    format: Format,
+) -> Result<(), String> {
+    for mapping in mappings {
+        let (material, category) = mapping.split_once('=').ok_or(format!(
+            "invalid mapping '{mapping}', expected MATERIAL=CATEGORY"
+        ))?;
+        let category = category.trim();
+        material_db::get_category(category)
+            .map_err(|_| format!("unknown category '{category}' in mapping '{mapping}'"))?;
+        material_db::set_ifc_mapping(material.trim(), category).map_err(|e| e.to_string())?;
+    }
+    let import = IfcImport::open(model).map_err(|e| e.to_string())?;
+    let mut project = match file.exists() {
+        true => Project::load(file).map_err(|e| e.to_string())?,
+        false => Project::new(),
+    };
+    let added = import
+        .add_to_project(&mut project)
+        .map_err(|e| e.to_string())?;
+    project.save(file).map_err(|e| e.to_string())?;
+
+    let mut table = Table::new(vec![
+        "ifc_material",
+        "category",
+        "elements",
+        "volume_m3",
+        "area_m2",
+        "added",
+    ]);
+    for row in &import.rows {
+        table.push(vec![
+            json!(row.takeoff.material),
+            json!(row.category.as_ref().filter(|_| row.mapped)),
+            json!(row.takeoff.elements.len()),
+            json!(row.takeoff.volume),
+            json!(row.takeoff.area),
+            json!(row.include),
+        ]);
+        if !row.include {
+            eprintln!(
+                "WARNING: {} is not mapped to a category, add it with --map",
+                row.takeoff.material
+            );
+        }
+    }
+    table.print(format);
+    if !import.takeoff.unassigned.is_empty() {
+        eprintln!(
+            "WARNING: {} elements with quantities have no material",
+            import.takeoff.unassigned.len()
+        );
+    }
+    println!("Added {added} components to {}", file.display());
+    Ok(())
+}
@@ -2,7 +2,12 @@
 extern crate shared;
 extern crate view;
 
+mod cli;
+mod output;
+
+use clap::Parser;
 use eframe::egui::{self, ViewportBuilder};
+use shared::migrations::MigrationError;
 
 struct Application {
     state: shared::State,
@@ -28,6 +33,28 @@ impl eframe::App for Application {
 // TODO: try this example https://github.com/rksm/hot-lib-reloader-rs/blob/master/examples/hot-egui/Cargo.toml
 fn main() -> Result<(), eframe::Error> {
     env_logger::init();
+    let args = cli::Cli::parse();
+    setup_cache().unwrap_or_else(|e| {
+        eprintln!("ERROR: unable to set up cache directory: {e}");
+    });
+    match shared::material_db::migrate() {
+        Ok(()) => {}
+        // opening it would corrupt the data of the newer version
+        Err(e @ MigrationError::NewerVersion(_)) => {
+            eprintln!("ERROR: {e}");
+            std::process::exit(1);
+        }
+        Err(e) => eprintln!("ERROR: unable to migrate the database: {e}"),
+    }
+    if let Some(command) = args.command {
+        #[cfg(windows)]
+        attach_console();
+        if let Err(e) = cli::run(command, args.format, get_api_key) {
+            eprintln!("ERROR: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     // init egui
     let viewport = ViewportBuilder::default()
         .with_decorations(true)
@@ -38,9 +65,6 @@ fn main() -> Result<(), eframe::Error> {
         run_and_return: true,
         ..Default::default()
     };
-    setup_cache().unwrap_or_else(|e| {
-        eprintln!("ERROR: unable to set up cache directory: {e}");
-    });
     let api_key = get_api_key();
     eframe::run_native(
         "Carbon",
@@ -49,6 +73,21 @@ fn main() -> Result<(), eframe::Error> {
     )
 }
 
+/// The binary is built for the windows subsystem so the interface opens without a console,
+/// the headless commands write to the console of the shell which started them
+#[cfg(windows)]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // fails without a parent console, e.g. when started from the explorer
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
 fn get_api_key() -> Option<String> {
     match std::fs::read_to_string(shared::settings::SettingsProvider::api_key_path()) {
         Ok(api_key) => Some(api_key.trim().to_string()),
@@ -0,0 +1,124 @@
+use clap::ValueEnum;
+use serde_json::Value;
+use shared::export::csv_escape;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Rows printed by the headless commands in any of the supported [Format]s
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Self {
+        Self {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Value>) {
+        self.rows.push(row);
+    }
+
+    pub fn print(&self, format: Format) {
+        match format {
+            Format::Table => self.print_table(),
+            Format::Json => self.print_json(),
+            Format::Csv => self.print_csv(),
+        }
+    }
+
+    fn print_table(&self) {
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|c| cell_to_string(c, true)).collect())
+            .collect();
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+        let line = |cells: Vec<&str>| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        println!("{}", line(self.headers.clone()));
+        println!(
+            "{}",
+            widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<_>>()
+                .join("  ")
+        );
+        for row in &rows {
+            println!("{}", line(row.iter().map(|c| c.as_str()).collect()));
+        }
+    }
+
+    fn print_json(&self) {
+        let objects: Vec<serde_json::Map<String, Value>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                self.headers
+                    .iter()
+                    .map(|h| h.to_string())
+                    .zip(row.iter().cloned())
+                    .collect()
+            })
+            .collect();
+        match serde_json::to_string_pretty(&objects) {
+            Ok(json) => println!("{json}"),
+            Err(e) => eprintln!("ERROR: could not serialize output: {e}"),
+        }
+    }
+
+    fn print_csv(&self) {
+        println!(
+            "{}",
+            self.headers
+                .iter()
+                .map(|h| csv_escape(h))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        for row in &self.rows {
+            println!(
+                "{}",
+                row.iter()
+                    .map(|c| csv_escape(&cell_to_string(c, false)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+        }
+    }
+}
+
+/// Converts a cell to text, floats are rounded to two decimals when `round` is set
+fn cell_to_string(value: &Value, round: bool) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if round && n.is_f64() => format!("{f:.2}"),
+            _ => n.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
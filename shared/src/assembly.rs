@@ -0,0 +1,213 @@
+//! Assemblies: layered build-ups specified per m², e.g. a wall of 200 mm concrete,
+//! 120 mm mineral wool and 15 mm gypsum.
+//!
+//! An [Assembly] is a reusable definition stored in `carbon.db`. Added to a project with an
+//! area it becomes a [ProjectAssembly], whose layers are components with a quantity that
+//! follows the area. Exports and reports list the layers as components of their assembly.
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{lifecycle::ModuleBreakdown, material_db, project::Component, units::QuantityUnit};
+
+/// Amount of material in one m² of assembly
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayerAmount {
+    /// Thickness in mm, a volume of material
+    Thickness { mm: f64 },
+    /// Quantity per m² of assembly, e.g. 1 m² of membrane or 4 kg of fixings
+    PerArea { quantity: f64, unit: QuantityUnit },
+}
+
+impl Default for LayerAmount {
+    fn default() -> Self {
+        LayerAmount::Thickness { mm: 100. }
+    }
+}
+
+impl LayerAmount {
+    /// Quantity of material in an area of assembly
+    pub fn quantity(&self, area: f64) -> (f64, QuantityUnit) {
+        match *self {
+            LayerAmount::Thickness { mm } => (mm / 1000. * area, QuantityUnit::M3),
+            LayerAmount::PerArea { quantity, unit } => (quantity * area, unit),
+        }
+    }
+}
+
+impl Display for LayerAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayerAmount::Thickness { mm } => write!(f, "{mm} mm"),
+            LayerAmount::PerArea { quantity, unit } => write!(f, "{quantity} {unit}/m²"),
+        }
+    }
+}
+
+/// Material of a layer, looked up in `carbon.db` when the assembly is added to a project
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayerMaterial {
+    Ec3 {
+        id: String,
+        name: String,
+    },
+    /// The generic material of a category, see [crate::project::UMaterial]
+    Generic(String),
+}
+
+impl LayerMaterial {
+    pub fn name(&self) -> String {
+        match self {
+            LayerMaterial::Ec3 { name, .. } => name.clone(),
+            LayerMaterial::Generic(category) => format!("{category} (Generic)"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub material: LayerMaterial,
+    pub amount: LayerAmount,
+}
+
+/// A build-up of layers, from the outside in
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Assembly {
+    /// Row id in `carbon.db`, `None` until the assembly is saved
+    pub id: Option<i64>,
+    pub name: String,
+    pub description: String,
+    pub layers: Vec<Layer>,
+}
+
+impl Assembly {
+    /// The assembly with an area, its layers made components of the materials of `carbon.db`
+    pub fn instantiate(
+        &self,
+        area: f64,
+        benchmark_country: Option<&str>,
+    ) -> rusqlite::Result<ProjectAssembly> {
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let mut component = match &layer.material {
+                LayerMaterial::Ec3 { id, .. } => {
+                    let material = material_db::get_material(id)?;
+                    let avg = material_db::get_category_avg(&material.category.name)?;
+                    Component::from_ec3(material, avg, benchmark_country)
+                }
+                LayerMaterial::Generic(category) => Component::generic(category, benchmark_country),
+            };
+            // the layer knows the thickness, which converts its volume to an area
+            if let LayerAmount::Thickness { mm } = layer.amount {
+                component.factors.thickness = Some(mm / 1000.);
+            }
+            layers.push(AssemblyLayer {
+                amount: layer.amount,
+                component,
+            });
+        }
+        Ok(ProjectAssembly {
+            name: self.name.clone(),
+            area,
+            layers,
+            calculated: 0.,
+            modules: ModuleBreakdown::default(),
+        })
+    }
+
+    /// Total of modules A to C per m² with the default life-cycle assumptions
+    pub fn gwp_per_m2(&self, study_period: f64) -> rusqlite::Result<f64> {
+        let mut assembly = self.instantiate(1., None)?;
+        assembly.calculate(study_period);
+        Ok(assembly.calculated)
+    }
+}
+
+pub struct AssemblyLayer {
+    pub amount: LayerAmount,
+    /// Its quantity is set from the area of the assembly by [ProjectAssembly::calculate]
+    pub component: Component,
+}
+
+/// An assembly in a project, with a snapshot of the materials of its layers
+pub struct ProjectAssembly {
+    pub name: String,
+    /// m²
+    pub area: f64,
+    pub layers: Vec<AssemblyLayer>,
+    /// Total of modules A to C in KgCO2e
+    pub calculated: f64,
+    pub modules: ModuleBreakdown,
+}
+
+impl ProjectAssembly {
+    /// Sets the quantity of every layer from the area and calculates them
+    pub fn calculate(&mut self, study_period: f64) {
+        let mut modules = ModuleBreakdown::default();
+        for layer in self.layers.iter_mut() {
+            let (quantity, unit) = layer.amount.quantity(self.area);
+            layer.component.quantity = quantity;
+            layer.component.unit = Some(unit);
+            layer.component.calculate(study_period);
+            modules += layer.component.modules;
+        }
+        self.modules = modules;
+        self.calculated = modules.total();
+    }
+
+    /// `None` without an area
+    pub fn gwp_per_m2(&self) -> Option<f64> {
+        (self.area > 0.).then(|| self.calculated / self.area)
+    }
+}
+
+/// The assemblies of `carbon.db` and the one being edited
+pub struct AssemblyLibrary {
+    pub assemblies: Vec<Assembly>,
+    /// Copy of a saved assembly, or a new one until it is saved
+    pub draft: Assembly,
+    /// Of the draft, `None` until calculated or when a material is missing from `carbon.db`
+    pub gwp_per_m2: Option<f64>,
+    /// Area the draft is added to a project with, in m²
+    pub area: f64,
+}
+
+impl AssemblyLibrary {
+    pub fn open() -> rusqlite::Result<Self> {
+        Ok(Self {
+            assemblies: material_db::load_assemblies()?,
+            draft: Assembly::default(),
+            gwp_per_m2: None,
+            area: 1.,
+        })
+    }
+
+    pub fn edit(&mut self, index: usize, study_period: f64) {
+        if let Some(assembly) = self.assemblies.get(index) {
+            self.draft = assembly.clone();
+            self.calculate(study_period);
+        }
+    }
+
+    /// Recalculates [AssemblyLibrary::gwp_per_m2] after the draft changed
+    pub fn calculate(&mut self, study_period: f64) {
+        self.gwp_per_m2 = self.draft.gwp_per_m2(study_period).ok();
+    }
+
+    /// Saves the draft, which keeps being edited, and reloads the library
+    pub fn save(&mut self) -> rusqlite::Result<()> {
+        self.draft.id = Some(material_db::save_assembly(&self.draft)?);
+        self.assemblies = material_db::load_assemblies()?;
+        Ok(())
+    }
+
+    /// Deletes the saved version of the draft, which becomes a new assembly
+    pub fn delete(&mut self) -> rusqlite::Result<()> {
+        if let Some(id) = self.draft.id.take() {
+            material_db::delete_assembly(id)?;
+        }
+        self.assemblies = material_db::load_assemblies()?;
+        Ok(())
+    }
+}
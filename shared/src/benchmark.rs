@@ -0,0 +1,196 @@
+//! Benchmarking of materials against the GWP distribution of their category,
+//! e.g. "better than 80% of ReadyMix in DE".
+use serde::{Deserialize, Serialize};
+
+use crate::{material_db, uncertainty};
+
+/// Number of quantiles kept of a distribution, P0 to P100
+pub const QUANTILES: usize = 101;
+
+/// A country is only used as scope when it has at least this many materials in the category
+pub const MIN_SAMPLE: usize = 5;
+
+/// GWP distribution of the materials of a category, stored as its percentiles
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Distribution {
+    pub category: String,
+    /// Country of the manufacturers the materials were limited to, `None` for all countries
+    pub country: Option<String>,
+    /// Number of materials
+    pub count: usize,
+    /// GWP at P0, P1, ... P100, interpolated linearly between materials
+    pub quantiles: Vec<f64>,
+}
+
+impl Distribution {
+    /// Computes the distribution of GWP values sorted ascending, `None` when there are none
+    pub fn from_sorted(category: &str, country: Option<&str>, values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let last = values.len() - 1;
+        let quantiles = (0..QUANTILES)
+            .map(|i| {
+                let h = i as f64 / (QUANTILES - 1) as f64 * last as f64;
+                let lo = h.floor() as usize;
+                let hi = (lo + 1).min(last);
+                values[lo] + (h - lo as f64) * (values[hi] - values[lo])
+            })
+            .collect();
+        Some(Self {
+            category: category.to_string(),
+            country: country.map(str::to_string),
+            count: values.len(),
+            quantiles,
+        })
+    }
+
+    /// Loads the distribution of a category, limited to a country when it has enough materials
+    pub fn load(category: &str, country: Option<&str>) -> Option<Self> {
+        let load = |country| {
+            material_db::get_category_gwps(category, country)
+                .map_err(|e| eprintln!("ERROR: loading the GWP of {category}: {e}"))
+                .ok()
+                .and_then(|values| Self::from_sorted(category, country, &values))
+        };
+        match country.and_then(|c| load(Some(c))) {
+            Some(distribution) if distribution.count >= MIN_SAMPLE => Some(distribution),
+            _ => load(None),
+        }
+    }
+
+    /// GWP at percentile `p` from 0 to 100, interpolated between the quantiles
+    pub fn percentile(&self, p: f64) -> f64 {
+        uncertainty::percentile(&self.quantiles, p)
+    }
+
+    /// Percentage of the materials of the category with a lower GWP
+    pub fn rank(&self, gwp: f64) -> f64 {
+        let q = &self.quantiles;
+        let below = q.partition_point(|v| *v < gwp);
+        let up_to = q.partition_point(|v| *v <= gwp);
+        let step = 100. / (q.len() - 1) as f64;
+        if below < up_to {
+            // several percentiles have exactly this value, take the middle one
+            return (below + up_to - 1) as f64 / 2. * step;
+        }
+        match below {
+            0 => 0.,
+            i if i == q.len() => 100.,
+            i => (i - 1) as f64 * step + (gwp - q[i - 1]) / (q[i] - q[i - 1]) * step,
+        }
+    }
+
+    /// Human readable scope of the distribution, e.g. `ReadyMix in DE`
+    pub fn scope(&self) -> String {
+        match &self.country {
+            Some(country) => format!("{} in {country}", self.category),
+            None => self.category.clone(),
+        }
+    }
+
+    /// Describes where a GWP value ranks, e.g. `better than 80% of ReadyMix in DE`
+    pub fn describe(&self, gwp: f64) -> String {
+        format!(
+            "better than {better:.0}% of {scope} ({count} materials)",
+            better = 100. - self.rank(gwp),
+            scope = self.scope(),
+            count = self.count
+        )
+    }
+}
+
+/// Percentile ranks which make a material better or worse than its category
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Materials ranked below this percentile are better than the category
+    pub better: f64,
+    /// Materials ranked above this percentile are worse than the category
+    pub worse: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            better: 25.,
+            worse: 75.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distribution(values: &[f64]) -> Distribution {
+        Distribution::from_sorted("ReadyMix", Some("DE"), values).unwrap()
+    }
+
+    #[test]
+    fn ranks_against_the_quantiles() {
+        let spread = distribution(&[100., 200., 300., 400., 500.]);
+        let tied = distribution(&[100., 200., 200., 200., 300.]);
+        let single = distribution(&[200.]);
+        let cases = [
+            ("below the minimum", &spread, 50., 0.),
+            ("on the minimum", &spread, 100., 0.),
+            ("above the maximum", &spread, 600., 100.),
+            ("on the maximum", &spread, 500., 100.),
+            ("on a quantile", &spread, 300., 50.),
+            // between P37 = 248 and P38 = 252
+            ("between quantiles", &spread, 250., 37.5),
+            // P25 to P75 are all 200
+            ("on a tie", &tied, 200., 50.),
+            ("below a tie", &tied, 150., 12.5),
+            ("single value", &single, 200., 50.),
+            ("below a single value", &single, 100., 0.),
+            ("above a single value", &single, 300., 100.),
+        ];
+        for (case, distribution, gwp, expected) in cases {
+            let rank = distribution.rank(gwp);
+            assert!((rank - expected).abs() < 1e-9, "{case}: {rank}");
+        }
+    }
+
+    #[test]
+    fn percentiles_are_the_inverse_of_ranks() {
+        let spread = distribution(&[100., 200., 300., 400., 500.]);
+        for (p, gwp) in [
+            (0., 100.),
+            (25., 200.),
+            (37.5, 250.),
+            (75., 400.),
+            (100., 500.),
+        ] {
+            assert!((spread.percentile(p) - gwp).abs() < 1e-9, "P{p}");
+            assert!((spread.rank(gwp) - p).abs() < 1e-9, "{gwp}");
+        }
+    }
+
+    #[test]
+    fn empty_categories_have_no_distribution() {
+        assert_eq!(Distribution::from_sorted("ReadyMix", None, &[]), None);
+        let single = distribution(&[200.]);
+        assert_eq!(single.count, 1);
+        assert_eq!(single.quantiles, [200.; QUANTILES]);
+    }
+
+    #[test]
+    fn describes_the_rank_in_its_scope() {
+        let spread = distribution(&[100., 200., 300., 400., 500.]);
+        let cases = [
+            (50., "better than 100% of ReadyMix in DE (5 materials)"),
+            (300., "better than 50% of ReadyMix in DE (5 materials)"),
+            (600., "better than 0% of ReadyMix in DE (5 materials)"),
+        ];
+        for (gwp, expected) in cases {
+            assert_eq!(spread.describe(gwp), expected);
+        }
+        let single = Distribution::from_sorted("ReadyMix", None, &[200.]).unwrap();
+        assert_eq!(
+            single.describe(200.),
+            "better than 50% of ReadyMix (1 materials)"
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Import of bills of quantities from CSV or TSV schedules, e.g. exported from Revit or Excel.
+//!
+//! A file is read into a [BoqTable] and its columns are assigned with a [ColumnMapping].
+//! [BoqImport::match_rows] then ranks the materials of `carbon.db`, and the generic material
+//! of a category, for every row. Rows are only added to a project once they were reviewed:
+//! rows matched with [Confidence::Low] or not at all start excluded.
+use std::{collections::HashSet, path::PathBuf};
+
+use ec3api::models::Ec3Material;
+
+use crate::{material_db, project::Project, units::QuantityUnit};
+
+/// Similarity from which a match is accepted without review
+pub const HIGH_CONFIDENCE: f64 = 0.6;
+/// Similarity below which a candidate is not considered a match
+pub const LOW_CONFIDENCE: f64 = 0.3;
+
+/// Number of materials kept per row to choose from during review, besides generic materials
+const CANDIDATES: usize = 5;
+
+/// Rows of a delimited file, the first line being the headers
+pub struct BoqTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// Line in the file each row starts on, the headers being line 1
+    pub lines: Vec<usize>,
+}
+
+impl BoqTable {
+    /// Parses comma, semicolon or tab separated text, using the separator most
+    /// frequent in the header line. Fields may be quoted with `"`.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim_start_matches('\u{feff}');
+        let header = text.lines().next().unwrap_or_default();
+        let delimiter = ['\t', ';', ',']
+            .into_iter()
+            .max_by_key(|d| header.matches(*d).count())
+            .unwrap_or(',');
+        let mut records = parse_records(text, delimiter).into_iter();
+        let headers = records.next().map(|(_, row)| row).unwrap_or_default();
+        let (lines, rows) = records
+            .filter(|(_, row)| row.iter().any(|cell| !cell.trim().is_empty()))
+            .unzip();
+        Self {
+            headers,
+            rows,
+            lines,
+        }
+    }
+
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        // schedules saved by Excel are often not UTF-8
+        Ok(Self::parse(&String::from_utf8_lossy(&bytes)))
+    }
+
+    fn cell(&self, row: usize, column: Option<usize>) -> &str {
+        column
+            .and_then(|c| self.rows[row].get(c))
+            .map_or("", |cell| cell.trim())
+    }
+}
+
+/// Splits delimited text into records of fields with the line they start on,
+/// quotes may span lines
+fn parse_records(text: &str, delimiter: char) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = line;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter && !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push((start, std::mem::take(&mut record)));
+                start = line;
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((start, record));
+    }
+    records
+}
+
+/// Columns of a [BoqTable] holding each value of a component
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ColumnMapping {
+    pub description: Option<usize>,
+    pub quantity: Option<usize>,
+    pub unit: Option<usize>,
+    pub category: Option<usize>,
+}
+
+impl ColumnMapping {
+    /// Assigns columns by common header names of schedules, each column to one value at most
+    pub fn guess(headers: &[String]) -> Self {
+        let mut used = HashSet::new();
+        let mut find = |names: &[&str]| {
+            let column = headers.iter().enumerate().position(|(i, header)| {
+                let header = header.to_lowercase();
+                !used.contains(&i) && names.iter().any(|name| header.contains(name))
+            })?;
+            used.insert(column);
+            Some(column)
+        };
+        // the most specific names first, `Material Volume` is a quantity
+        let quantity = find(&[
+            "quantity", "qty", "volume", "area", "length", "count", "amount",
+        ]);
+        let unit = find(&["unit", "uom"]);
+        let category = find(&["category", "class", "group"]);
+        let description = find(&["description", "material", "name", "family", "type", "item"]);
+        Self {
+            description,
+            quantity,
+            unit,
+            category,
+        }
+    }
+
+    /// A row can only be matched with a description and a quantity
+    pub fn is_complete(&self) -> bool {
+        self.description.is_some() && self.quantity.is_some()
+    }
+}
+
+/// What a row is added to the project as
+#[derive(Clone)]
+pub enum Candidate {
+    Material(Box<Ec3Material>),
+    /// The generic material of a category, see [crate::project::UMaterial]
+    Generic(String),
+}
+
+impl Candidate {
+    pub fn name(&self) -> String {
+        match self {
+            Candidate::Material(material) => material.name.clone(),
+            Candidate::Generic(category) => format!("{category} (Generic)"),
+        }
+    }
+
+    pub fn category(&self) -> &str {
+        match self {
+            Candidate::Material(material) => &material.category.name,
+            Candidate::Generic(category) => category,
+        }
+    }
+}
+
+/// A candidate with its similarity to the row, from 0 to 1
+#[derive(Clone)]
+pub struct Scored {
+    pub candidate: Candidate,
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Confidence {
+    High,
+    Low,
+    Unmatched,
+}
+
+/// A row of the bill of quantities and the materials it may be
+pub struct ImportRow {
+    /// Line in the file, the headers being line 1
+    pub line: usize,
+    pub description: String,
+    /// `None` when the cell is not a number
+    pub quantity: Option<f64>,
+    /// `None` for the declared unit of the material, see [ImportRow::unit_text]
+    pub unit: Option<QuantityUnit>,
+    /// Unit as written in the file
+    pub unit_text: String,
+    /// Category of the database the category cell matched
+    pub category: Option<String>,
+    /// Best first
+    pub candidates: Vec<Scored>,
+    /// Index in [ImportRow::candidates] of the material to add
+    pub selected: Option<usize>,
+    /// Whether the row is added to the project
+    pub include: bool,
+}
+
+impl ImportRow {
+    pub fn selected(&self) -> Option<&Scored> {
+        self.candidates.get(self.selected?)
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        match self.selected() {
+            Some(s) if s.score >= HIGH_CONFIDENCE => Confidence::High,
+            Some(s) if s.score >= LOW_CONFIDENCE => Confidence::Low,
+            _ => Confidence::Unmatched,
+        }
+    }
+
+    /// Rows are only added with a quantity and a material
+    pub fn can_include(&self) -> bool {
+        self.quantity.is_some() && self.selected.is_some()
+    }
+}
+
+/// A bill of quantities being mapped, matched and reviewed
+pub struct BoqImport {
+    pub path: PathBuf,
+    pub table: BoqTable,
+    pub mapping: ColumnMapping,
+    /// `None` until the rows are matched with the mapping
+    pub rows: Option<Vec<ImportRow>>,
+}
+
+impl BoqImport {
+    pub fn open(path: PathBuf) -> std::io::Result<Self> {
+        let table = BoqTable::load(&path)?;
+        Ok(Self {
+            mapping: ColumnMapping::guess(&table.headers),
+            path,
+            table,
+            rows: None,
+        })
+    }
+
+    /// Matches every row of the table to the materials of the database
+    pub fn match_rows(&mut self) -> rusqlite::Result<()> {
+        let categories: Vec<String> = material_db::get_category_counts()?.into_keys().collect();
+        let mut rows = Vec::with_capacity(self.table.rows.len());
+        for i in 0..self.table.rows.len() {
+            let description = self.table.cell(i, self.mapping.description);
+            let (quantity, rest) = parse_quantity(self.table.cell(i, self.mapping.quantity));
+            // units are often written next to the quantity instead of in their own column
+            let unit_text = match self.table.cell(i, self.mapping.unit) {
+                "" => rest,
+                unit => unit,
+            };
+            let category = best_category(self.table.cell(i, self.mapping.category), &categories);
+            let candidates = rank_candidates(description, category.as_deref(), &categories)?;
+            let mut row = ImportRow {
+                line: self.table.lines[i],
+                description: description.to_string(),
+                quantity,
+                unit: unit_text.parse().ok(),
+                unit_text: unit_text.to_string(),
+                category,
+                selected: (!candidates.is_empty()).then_some(0),
+                candidates,
+                include: false,
+            };
+            row.include = row.can_include() && row.confidence() == Confidence::High;
+            rows.push(row);
+        }
+        self.rows = Some(rows);
+        Ok(())
+    }
+
+    /// Adds the included rows to the project as components, returning how many were added
+    pub fn add_to_project(&self, project: &mut Project) -> usize {
+        let mut added = 0;
+        for row in self.rows.iter().flatten().filter(|r| r.include) {
+            let (Some(scored), Some(quantity)) = (row.selected(), row.quantity) else {
+                continue;
+            };
+            match &scored.candidate {
+                Candidate::Material(material) => {
+                    let avg =
+                        material_db::get_category_avg(&material.category.name).unwrap_or_default();
+                    project.add_component(material.as_ref().clone(), avg);
+                }
+                Candidate::Generic(category) => project.add_generic_comp(category),
+            }
+            if let Some(component) = project.components.last_mut() {
+                component.quantity = quantity;
+                component.unit = row.unit;
+            }
+            added += 1;
+        }
+        project.calculate();
+        added
+    }
+}
+
+/// Reads a number written with either `.` or `,` as decimal separator,
+/// returning the text after it, e.g. the unit of `12,5 m3`.
+///
+/// When both appear the last one is the decimal separator. A single separator is
+/// ambiguous and read asymmetrically: `.` is always decimal, since tools exporting
+/// unformatted numbers write them with a dot, so `1.250` is 1.25. A single `,` is
+/// decimal unless exactly three digits follow it, so `1,250` is 1250 but `1,25` is 1.25.
+fn parse_quantity(cell: &str) -> (Option<f64>, &str) {
+    let end = cell
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+' | ' ' | '\'')))
+        .unwrap_or(cell.len());
+    let (number, rest) = cell.split_at(end);
+    let mut number: String = number
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\''))
+        .collect();
+    let decimal = match (number.rfind('.'), number.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        // a single comma followed by three digits groups thousands, e.g. 1,250
+        (None, Some(comma)) if number.matches(',').count() == 1 && number.len() - comma != 4 => {
+            Some(',')
+        }
+        (Some(_), None) if number.matches('.').count() == 1 => Some('.'),
+        _ => None,
+    };
+    number = number
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+') || Some(*c) == decimal)
+        .map(|c| if Some(c) == decimal { '.' } else { c })
+        .collect();
+    (number.parse().ok(), rest.trim())
+}
+
+/// The category most similar to the cell, if similar enough
+pub(crate) fn best_category(cell: &str, categories: &[String]) -> Option<String> {
+    if cell.is_empty() {
+        return None;
+    }
+    categories
+        .iter()
+        .map(|category| (category, similarity(cell, category)))
+        .filter(|(_, score)| *score >= HIGH_CONFIDENCE)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(category, _)| category.clone())
+}
+
+/// Ranks the materials similar to the description and the generic materials of the
+/// categories it names, best first
+fn rank_candidates(
+    description: &str,
+    category: Option<&str>,
+    categories: &[String],
+) -> rusqlite::Result<Vec<Scored>> {
+    let mut candidates: Vec<Scored> = material_db::similar_materials(description, category, 50)?
+        .into_iter()
+        .map(|material| Scored {
+            score: similarity(description, &material.name),
+            candidate: Candidate::Material(Box::new(material)),
+        })
+        .filter(|s| s.score >= LOW_CONFIDENCE)
+        .collect();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(CANDIDATES);
+
+    // a generic material is never certain, it is only proposed when nothing else matches well
+    let words: HashSet<String> = words(description).collect();
+    let mut generic: Vec<&str> = categories
+        .iter()
+        .filter(|c| words.contains(&c.to_lowercase()))
+        .map(String::as_str)
+        .collect();
+    generic.extend(category);
+    generic.sort_unstable();
+    generic.dedup();
+    candidates.extend(generic.into_iter().map(|category| Scored {
+        candidate: Candidate::Generic(category.to_string()),
+        score: (HIGH_CONFIDENCE + LOW_CONFIDENCE) / 2.,
+    }));
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(candidates)
+}
+
+/// Lowercase words of a text
+pub(crate) fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Dice coefficient of the letter pairs of the words of two texts, from 0 to 1.
+/// Tolerates typos, abbreviations and a different word order.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let pairs = |text: &str| -> Vec<(char, char)> {
+        words(text)
+            .flat_map(|word| {
+                let chars: Vec<char> = format!(" {word} ").chars().collect();
+                chars.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>()
+            })
+            .collect()
+    };
+    let a = pairs(a);
+    let mut b = pairs(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.;
+    }
+    let total = a.len() + b.len();
+    let mut shared = 0;
+    for pair in &a {
+        if let Some(i) = b.iter().position(|other| other == pair) {
+            b.swap_remove(i);
+            shared += 1;
+        }
+    }
+    2. * shared as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_fields_spanning_lines() {
+        let text =
+            "Description,Quantity\r\n\"Wall, \"\"type A\"\"\",12\n\"Slab\nlevel 1\",3\n\nlast,";
+        let (lines, records): (Vec<usize>, Vec<Vec<String>>) =
+            parse_records(text, ',').into_iter().unzip();
+        assert_eq!(
+            records,
+            [
+                vec!["Description", "Quantity"],
+                vec!["Wall, \"type A\"", "12"],
+                vec!["Slab\nlevel 1", "3"],
+                vec![""],
+                vec!["last", ""],
+            ]
+        );
+        assert_eq!(lines, [1, 2, 3, 5, 6]);
+    }
+
+    #[test]
+    fn guesses_the_delimiter_and_skips_empty_rows() {
+        let table = BoqTable::parse("\u{feff}Item;Qty;Unit\nConcrete;12,5;m3\n;;\nSteel;2;t\n");
+        assert_eq!(table.headers, ["Item", "Qty", "Unit"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[1], ["Steel", "2", "t"]);
+    }
+
+    #[test]
+    fn numbers_rows_by_the_line_they_start_on() {
+        let text = "Item,Qty\r\n\r\nSlab,3\r\n\"Wall\r\nlevel 2\",4\n,\nBeam,5\n";
+        let table = BoqTable::parse(text);
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.rows[1], ["Wall\r\nlevel 2", "4"]);
+        assert_eq!(table.rows[2], ["Beam", "5"]);
+        assert_eq!(table.lines, [3, 4, 7]);
+    }
+
+    #[test]
+    fn parses_quantities() {
+        let cases = [
+            ("12", Some(12.), ""),
+            ("12,5 m3", Some(12.5), "m3"),
+            ("12.5m²", Some(12.5), "m²"),
+            ("-3", Some(-3.), ""),
+            ("1.250", Some(1.25), ""),
+            ("1,250", Some(1250.), ""),
+            ("1,25", Some(1.25), ""),
+            ("1,2500", Some(1.25), ""),
+            ("1,250,000", Some(1_250_000.), ""),
+            ("1.250.000", Some(1_250_000.), ""),
+            ("1,250.75", Some(1250.75), ""),
+            ("1.250,75 kg", Some(1250.75), "kg"),
+            ("1 250,5", Some(1250.5), ""),
+            ("1'250.5", Some(1250.5), ""),
+            ("approx. 3", None, "approx. 3"),
+            ("", None, ""),
+        ];
+        for (cell, quantity, rest) in cases {
+            assert_eq!(parse_quantity(cell), (quantity, rest), "{cell}");
+        }
+    }
+
+    #[test]
+    fn scores_similar_texts() {
+        assert_eq!(similarity("Concrete", "concrete"), 1.);
+        assert_eq!(similarity("ready mix concrete", "Concrete, ready-mix"), 1.);
+        assert_eq!(similarity("", "concrete"), 0.);
+        assert_eq!(similarity("--", "concrete"), 0.);
+        assert_eq!(similarity("steel", "glass"), 0.);
+        let typo = similarity("concrte", "concrete");
+        assert!(typo > HIGH_CONFIDENCE, "{typo}");
+        let unrelated = similarity("timber", "concrete");
+        assert!(unrelated < LOW_CONFIDENCE, "{unrelated}");
+        assert_eq!(
+            similarity("brick wall", "wall brick"),
+            similarity("wall brick", "brick wall")
+        );
+    }
+}
@@ -0,0 +1,501 @@
+//! Export of a calculated [Project] to CSV, JSON, XLSX and the printable reports of [crate::report].
+//!
+//! Every format holds one row per component, the layers of assemblies being components of
+//! their assembly. JSON and XLSX also hold a summary of the project, of its assemblies, of
+//! its scenarios compared to the baseline and of its sensitivity analysis.
+use std::{fmt::Display, path::Path};
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use serde::Serialize;
+
+use crate::{
+    lifecycle::{Module, ModuleBreakdown},
+    project::{CmpResult, Component, Project},
+    report::CarbonReport,
+    scenario::ScenarioResult,
+    sensitivity::Sensitivity,
+};
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Xlsx(XlsxError),
+    /// The file extension is not one of [ExportFormat::ALL]
+    UnknownFormat(String),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "could not write the export: {e}"),
+            ExportError::Json(e) => write!(f, "could not serialize the export: {e}"),
+            ExportError::Xlsx(e) => write!(f, "could not write the workbook: {e}"),
+            ExportError::UnknownFormat(ext) => write!(
+                f,
+                "unknown export format '{ext}', expected one of: {}",
+                ExportFormat::ALL.map(|f| f.extension()).join(", ")
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Json(e)
+    }
+}
+
+impl From<XlsxError> for ExportError {
+    fn from(e: XlsxError) -> Self {
+        ExportError::Xlsx(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Xlsx,
+    Html,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Xlsx,
+        ExportFormat::Csv,
+        ExportFormat::Json,
+        ExportFormat::Html,
+        ExportFormat::Pdf,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Html => "html",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+
+    /// Format matching the extension of `path`, ignoring case
+    pub fn from_path(path: &Path) -> Result<Self, ExportError> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        Self::ALL
+            .into_iter()
+            .find(|f| f.extension().eq_ignore_ascii_case(ext))
+            .ok_or_else(|| ExportError::UnknownFormat(ext.to_string()))
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "CSV"),
+            ExportFormat::Json => write!(f, "JSON"),
+            ExportFormat::Xlsx => write!(f, "Excel workbook"),
+            ExportFormat::Html => write!(f, "HTML report"),
+            ExportFormat::Pdf => write!(f, "PDF report"),
+        }
+    }
+}
+
+/// A component as it is exported
+#[derive(Serialize)]
+pub struct ReportRow {
+    pub material: String,
+    /// EC3 id, generic materials have none
+    pub ec3_id: Option<String>,
+    pub category: String,
+    pub manufacturer: Option<String>,
+    pub country: Option<String>,
+    pub quantity: f64,
+    pub unit: String,
+    /// KgCO2e per declared unit
+    pub gwp: f64,
+    pub declared_unit: String,
+    pub modules: ModuleBreakdown,
+    /// Total of modules A to C in KgCO2e
+    pub total: f64,
+    /// Percentile rank in the distribution of the category
+    pub percentile: Option<f64>,
+    /// `better`, `typical` or `worse` than the category
+    pub benchmark: &'static str,
+    /// GlobalIds of the IFC elements the quantity was taken from
+    pub ifc_elements: Vec<String>,
+    /// Assembly the component is a layer of
+    pub assembly: Option<String>,
+}
+
+impl ReportRow {
+    fn new(component: &Component, project: &Project, assembly: Option<&str>) -> Self {
+        let material = &component.material;
+        let declared = material.get_unit();
+        Self {
+            material: material.get_name().to_string(),
+            ec3_id: material.get_id().map(str::to_string),
+            category: material.get_category().to_string(),
+            manufacturer: material.get_manufacturer().map(|m| m.name.clone()),
+            country: material.get_manufacturer().and_then(|m| m.country.clone()),
+            quantity: component.quantity,
+            unit: component
+                .unit
+                .map_or(format!("{:?}", declared.unit), |u| u.to_string()),
+            gwp: material.get_gwp().value,
+            declared_unit: format!("{} {:?}", declared.value, declared.unit),
+            modules: component.modules,
+            total: component.calculated,
+            percentile: component.percentile_rank(),
+            benchmark: component.cmp_to_benchmark(&project.thresholds).label(),
+            ifc_elements: component.ifc_elements.clone(),
+            assembly: assembly.map(str::to_string),
+        }
+    }
+
+    /// Material name, after the name of its assembly for layers
+    pub fn label(&self) -> String {
+        match &self.assembly {
+            Some(assembly) => format!("{assembly}: {}", self.material),
+            None => self.material.clone(),
+        }
+    }
+}
+
+/// An assembly of the project, its layers are in [Report::components]
+#[derive(Serialize)]
+pub struct AssemblyRow {
+    pub name: String,
+    /// m²
+    pub area: f64,
+    /// Amount and material of every layer, e.g. `200 mm Concrete (Generic)`
+    pub layers: Vec<String>,
+    /// Total of modules A to C in KgCO2e per m²
+    pub gwp_per_m2: Option<f64>,
+    /// Total of modules A to C in KgCO2e
+    pub total: f64,
+}
+
+/// Totals of the project
+#[derive(Serialize)]
+pub struct Summary {
+    pub components: usize,
+    /// Total of modules A to C in KgCO2e
+    pub total: f64,
+    pub modules: ModuleBreakdown,
+    pub study_period: f64,
+    pub benchmark_country: Option<String>,
+    /// Number of components better, typical and worse than their category
+    pub better: usize,
+    pub typical: usize,
+    pub worse: usize,
+}
+
+/// Exported content of a calculated [Project]
+#[derive(Serialize)]
+pub struct Report {
+    pub summary: Summary,
+    pub components: Vec<ReportRow>,
+    pub assemblies: Vec<AssemblyRow>,
+    /// The baseline followed by every scenario, empty without scenarios
+    pub scenarios: Vec<ScenarioResult>,
+    pub sensitivity: Sensitivity,
+}
+
+impl Report {
+    /// Collects the rows of a project, which must have been calculated
+    pub fn new(project: &Project) -> Self {
+        let components: Vec<ReportRow> = project
+            .all_components()
+            .map(|(assembly, c)| ReportRow::new(c, project, assembly))
+            .collect();
+        let assemblies = project
+            .assemblies
+            .iter()
+            .map(|a| AssemblyRow {
+                name: a.name.clone(),
+                area: a.area,
+                layers: a
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        format!("{} {}", layer.amount, layer.component.material.get_name())
+                    })
+                    .collect(),
+                gwp_per_m2: a.gwp_per_m2(),
+                total: a.calculated,
+            })
+            .collect();
+        let count = |label| components.iter().filter(|c| c.benchmark == label).count();
+        Self {
+            summary: Summary {
+                components: components.len(),
+                total: project.calculated_gwp,
+                modules: project.modules,
+                study_period: project.study_period,
+                benchmark_country: project.benchmark_country.clone(),
+                better: count(CmpResult::Smaller.label()),
+                typical: count(CmpResult::AlmostEqual.label()),
+                worse: count(CmpResult::Greater.label()),
+            },
+            components,
+            assemblies,
+            scenarios: match project.scenarios.is_empty() {
+                true => Vec::new(),
+                false => project.compare_scenarios(),
+            },
+            sensitivity: Sensitivity::analyze(project, project.sensitivity),
+        }
+    }
+}
+
+/// Column names of the component rows before the modules
+const HEADERS: [&str; 9] = [
+    "material",
+    "ec3_id",
+    "category",
+    "manufacturer",
+    "country",
+    "quantity",
+    "unit",
+    "gwp_per_declared_unit",
+    "declared_unit",
+];
+
+/// Column names of [Module::ALL] in KgCO2e
+const MODULE_HEADERS: [&str; 6] = ["a1_a3", "a4", "a5", "b4", "c1_c4", "d"];
+
+/// Column names after the modules
+const RESULT_HEADERS: [&str; 5] = [
+    "total_a_c",
+    "percentile",
+    "benchmark",
+    "ifc_global_ids",
+    "assembly",
+];
+
+/// Calculates the project and writes it to `path` in the format of its extension.
+/// Reports are titled with the file name.
+pub fn export(project: &mut Project, path: &Path) -> Result<ExportFormat, ExportError> {
+    let format = ExportFormat::from_path(path)?;
+    project.calculate();
+    let title = || {
+        path.file_stem()
+            .map_or("Project".into(), |stem| stem.to_string_lossy())
+            .to_string()
+    };
+    match format {
+        ExportFormat::Csv => std::fs::write(path, to_csv(&Report::new(project)))?,
+        ExportFormat::Json => {
+            std::fs::write(path, serde_json::to_string_pretty(&Report::new(project))?)?
+        }
+        ExportFormat::Xlsx => write_xlsx(&Report::new(project), path)?,
+        ExportFormat::Html => std::fs::write(path, CarbonReport::new(project, &title()).to_html())?,
+        ExportFormat::Pdf => std::fs::write(path, CarbonReport::new(project, &title()).to_pdf())?,
+    }
+    Ok(format)
+}
+
+/// Component rows with a header, without the summary
+pub fn to_csv(report: &Report) -> String {
+    let headers = HEADERS.iter().chain(&MODULE_HEADERS).chain(&RESULT_HEADERS);
+    let mut csv = headers.copied().collect::<Vec<_>>().join(",");
+    csv.push('\n');
+    for row in &report.components {
+        let mut fields = vec![
+            csv_escape(&row.material),
+            csv_escape(row.ec3_id.as_deref().unwrap_or_default()),
+            csv_escape(&row.category),
+            csv_escape(row.manufacturer.as_deref().unwrap_or_default()),
+            csv_escape(row.country.as_deref().unwrap_or_default()),
+            row.quantity.to_string(),
+            csv_escape(&row.unit),
+            row.gwp.to_string(),
+            csv_escape(&row.declared_unit),
+        ];
+        fields.extend(Module::ALL.iter().map(|m| row.modules.get(*m).to_string()));
+        fields.push(row.total.to_string());
+        fields.push(row.percentile.map(|p| p.to_string()).unwrap_or_default());
+        fields.push(row.benchmark.to_string());
+        // GlobalIds never hold spaces
+        fields.push(row.ifc_elements.join(" "));
+        fields.push(csv_escape(row.assembly.as_deref().unwrap_or_default()));
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes a CSV field when it holds a separator, a quote or a line break
+pub fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Shortens text to the 32767 characters a cell holds at most
+fn xlsx_text(text: &str) -> &str {
+    const MAX: usize = 32_767;
+    match text.char_indices().nth(MAX) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Writes a workbook with a summary sheet followed by a sheet of components
+fn write_xlsx(report: &Report, path: &Path) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let number = Format::new().set_num_format("#,##0.00");
+
+    let summary = workbook.add_worksheet().set_name("Summary")?;
+    let s = &report.summary;
+    summary.write_string_with_format(0, 0, "Project summary", &bold)?;
+    let mut row = 2;
+    let mut line = |sheet: &mut Worksheet, label: &str, value: f64| -> Result<(), XlsxError> {
+        sheet.write_string(row, 0, label)?;
+        sheet.write_number_with_format(row, 1, value, &number)?;
+        row += 1;
+        Ok(())
+    };
+    line(summary, "Total A-C (KgCO2e)", s.total)?;
+    for module in Module::ALL {
+        line(
+            summary,
+            &format!("{module} (KgCO2e)"),
+            s.modules.get(module),
+        )?;
+    }
+    line(summary, "Study period (years)", s.study_period)?;
+    line(summary, "Components", s.components as f64)?;
+    line(summary, "Better than their category", s.better as f64)?;
+    line(summary, "Typical of their category", s.typical as f64)?;
+    line(summary, "Worse than their category", s.worse as f64)?;
+    summary.write_string(row, 0, "Benchmark country")?;
+    summary.write_string(row, 1, s.benchmark_country.as_deref().unwrap_or("All"))?;
+    summary.autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Components")?;
+    let headers = HEADERS.iter().chain(&MODULE_HEADERS).chain(&RESULT_HEADERS);
+    for (col, header) in headers.enumerate() {
+        sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    for (i, c) in report.components.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, &c.material)?;
+        sheet.write_string(row, 1, c.ec3_id.as_deref().unwrap_or_default())?;
+        sheet.write_string(row, 2, &c.category)?;
+        sheet.write_string(row, 3, c.manufacturer.as_deref().unwrap_or_default())?;
+        sheet.write_string(row, 4, c.country.as_deref().unwrap_or_default())?;
+        sheet.write_number_with_format(row, 5, c.quantity, &number)?;
+        sheet.write_string(row, 6, &c.unit)?;
+        sheet.write_number_with_format(row, 7, c.gwp, &number)?;
+        sheet.write_string(row, 8, &c.declared_unit)?;
+        let mut col = 9;
+        for module in Module::ALL {
+            sheet.write_number_with_format(row, col, c.modules.get(module), &number)?;
+            col += 1;
+        }
+        sheet.write_number_with_format(row, col, c.total, &number)?;
+        if let Some(percentile) = c.percentile {
+            sheet.write_number_with_format(row, col + 1, percentile, &number)?;
+        }
+        sheet.write_string(row, col + 2, c.benchmark)?;
+        sheet.write_string(row, col + 3, xlsx_text(&c.ifc_elements.join(" ")))?;
+        sheet.write_string(row, col + 4, c.assembly.as_deref().unwrap_or_default())?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+
+    if !report.assemblies.is_empty() {
+        let sheet = workbook.add_worksheet().set_name("Assemblies")?;
+        let headers = ["assembly", "area_m2", "gwp_per_m2", "total_a_c", "layers"];
+        for (col, header) in headers.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+        }
+        for (i, a) in report.assemblies.iter().enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, &a.name)?;
+            sheet.write_number_with_format(row, 1, a.area, &number)?;
+            if let Some(gwp) = a.gwp_per_m2 {
+                sheet.write_number_with_format(row, 2, gwp, &number)?;
+            }
+            sheet.write_number_with_format(row, 3, a.total, &number)?;
+            sheet.write_string(row, 4, xlsx_text(&a.layers.join(" + ")))?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        sheet.autofit();
+    }
+
+    if !report.scenarios.is_empty() {
+        let sheet = workbook.add_worksheet().set_name("Scenarios")?;
+        let headers = std::iter::once(&"scenario").chain(&MODULE_HEADERS).chain(&[
+            "total_a_c",
+            "delta",
+            "delta_percent",
+            "changed_components",
+        ]);
+        for (col, header) in headers.enumerate() {
+            sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+        }
+        for (i, s) in report.scenarios.iter().enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, &s.name)?;
+            let mut col = 1;
+            for module in Module::ALL {
+                sheet.write_number_with_format(row, col, s.modules.get(module), &number)?;
+                col += 1;
+            }
+            sheet.write_number_with_format(row, col, s.total, &number)?;
+            sheet.write_number_with_format(row, col + 1, s.delta, &number)?;
+            if let Some(percent) = s.delta_percent {
+                sheet.write_number_with_format(row, col + 2, percent, &number)?;
+            }
+            sheet.write_number(row, col + 3, s.changes as f64)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        sheet.autofit();
+    }
+
+    if !report.sensitivity.swings.is_empty() {
+        let sheet = workbook.add_worksheet().set_name("Sensitivity")?;
+        let headers = [
+            "component",
+            "parameter",
+            "low_input",
+            "high_input",
+            "total_low",
+            "total_high",
+            "range",
+        ];
+        for (col, header) in headers.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+        }
+        for (i, s) in report.sensitivity.swings.iter().enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, &s.component)?;
+            sheet.write_string(row, 1, s.parameter.to_string())?;
+            sheet.write_number_with_format(row, 2, s.low_input, &number)?;
+            sheet.write_number_with_format(row, 3, s.high_input, &number)?;
+            sheet.write_number_with_format(row, 4, s.low, &number)?;
+            sheet.write_number_with_format(row, 5, s.high, &number)?;
+            sheet.write_number_with_format(row, 6, s.range(), &number)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        sheet.autofit();
+    }
+
+    workbook.save(path)
+}
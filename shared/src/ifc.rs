@@ -0,0 +1,1168 @@
+//! Quantity takeoff from IFC2x3 and IFC4 models written as STEP physical files (`.ifc`).
+//!
+//! The file is indexed without a schema and only the entities of quantities, materials and
+//! units are read. Elements get their quantities from the `IfcElementQuantity` sets related
+//! to them and their materials from `IfcRelAssociatesMaterial`, directly or through their
+//! type. Quantities are summed by material in a [Takeoff], keeping the GlobalIds of the
+//! elements, and the materials are mapped to EC3 categories with the mappings saved in
+//! `carbon.db`, see [material_db::get_ifc_mappings].
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    boq, material_db,
+    project::Project,
+    units::{Dimension, QuantityUnit},
+};
+
+#[derive(Debug)]
+pub enum IfcError {
+    Io(std::io::Error),
+    /// The file has no `ISO-10303-21` header or no DATA section
+    NotStep,
+    /// The schema of the file is not an IFC schema
+    UnsupportedSchema(String),
+}
+
+impl Display for IfcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IfcError::Io(e) => write!(f, "could not read the IFC file: {e}"),
+            IfcError::NotStep => write!(f, "not an IFC file in the STEP format"),
+            IfcError::UnsupportedSchema(schema) => {
+                write!(f, "unsupported schema '{schema}', expected IFC2X3 or IFC4")
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for IfcError {
+    fn from(e: std::io::Error) -> Self {
+        IfcError::Io(e)
+    }
+}
+
+/// An attribute of an entity
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    /// `$` for an unset attribute and `*` for a derived one
+    Null,
+    Ref(u64),
+    Str(String),
+    Number(f64),
+    /// Enumeration or boolean, without the dots, e.g. `LENGTHUNIT` or `T`
+    Enum(String),
+    List(Vec<Value>),
+    /// A value with its type, e.g. `IFCLENGTHMEASURE(0.2)`
+    Typed(String, Box<Value>),
+}
+
+impl Value {
+    fn as_ref(&self) -> Option<u64> {
+        match self {
+            Value::Ref(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            Value::Typed(_, value) => value.as_str(),
+            _ => None,
+        }
+    }
+
+    fn as_enum(&self) -> Option<&str> {
+        match self {
+            Value::Enum(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::Typed(_, value) => value.as_f64(),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> &[Value] {
+        match self {
+            Value::List(values) => values,
+            _ => &[],
+        }
+    }
+
+    fn refs(&self) -> impl Iterator<Item = u64> + '_ {
+        self.as_list().iter().filter_map(Value::as_ref)
+    }
+}
+
+/// An entity with its attributes in the order of the schema
+struct Entity {
+    kind: String,
+    args: Vec<Value>,
+}
+
+impl Entity {
+    fn arg(&self, i: usize) -> &Value {
+        self.args.get(i).unwrap_or(&Value::Null)
+    }
+}
+
+/// Position in the file of the type and the attributes of an entity
+struct Record {
+    kind: Range<usize>,
+    args: Range<usize>,
+}
+
+/// Entities of a STEP file. Attributes are only parsed when an entity is read,
+/// since most of a model is geometry this takeoff never looks at.
+struct Model {
+    text: String,
+    schema: String,
+    records: HashMap<u64, Record>,
+}
+
+impl Model {
+    fn parse(text: String) -> Result<Self, IfcError> {
+        if !text.trim_start().starts_with("ISO-10303-21") {
+            return Err(IfcError::NotStep);
+        }
+        let mut schema = None;
+        let mut records = HashMap::new();
+        let mut in_data = false;
+        for statement in statements(&text) {
+            let content = text[statement.clone()].trim();
+            if !in_data {
+                if content.starts_with("FILE_SCHEMA") {
+                    let args = content
+                        .find('(')
+                        .map_or(&b""[..], |i| &content.as_bytes()[i..]);
+                    schema = Parser::new(args)
+                        .list()
+                        .and_then(|list| list.as_list().first()?.as_list().first().cloned())
+                        .and_then(|s| s.as_str().map(str::to_uppercase));
+                }
+                in_data = content == "DATA";
+                continue;
+            }
+            if content == "ENDSEC" {
+                break;
+            }
+            let offset = statement.start
+                + (text[statement.clone()].len() - text[statement.clone()].trim_start().len());
+            if let Some((id, record)) = index_entity(content, offset) {
+                records.insert(id, record);
+            }
+        }
+        if !in_data {
+            return Err(IfcError::NotStep);
+        }
+        let schema = schema.unwrap_or_default();
+        if !schema.starts_with("IFC") {
+            return Err(IfcError::UnsupportedSchema(schema));
+        }
+        Ok(Self {
+            text,
+            schema,
+            records,
+        })
+    }
+
+    fn kind(&self, id: u64) -> Option<&str> {
+        self.records.get(&id).map(|r| &self.text[r.kind.clone()])
+    }
+
+    fn get(&self, id: u64) -> Option<Entity> {
+        let record = self.records.get(&id)?;
+        let args = Parser::new(self.text[record.args.clone()].as_bytes()).list()?;
+        Some(Entity {
+            kind: self.text[record.kind.clone()].to_string(),
+            args: match args {
+                Value::List(args) => args,
+                _ => Vec::new(),
+            },
+        })
+    }
+
+    fn get_ref(&self, value: &Value) -> Option<Entity> {
+        self.get(value.as_ref()?)
+    }
+
+    /// Entities of one of the given types
+    fn all<'a>(&'a self, kinds: &'a [&str]) -> impl Iterator<Item = Entity> + 'a {
+        self.records
+            .iter()
+            .filter(|(_, r)| kinds.contains(&&self.text[r.kind.clone()]))
+            .filter_map(|(id, _)| self.get(*id))
+    }
+}
+
+/// Ranges of the statements of the file, each ending before its `;`.
+/// Semicolons in strings and comments do not end a statement.
+fn statements(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' => {
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == b'\'' {
+                        if bytes.get(i + 1) == Some(&b'\'') {
+                            i += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = text[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 1);
+            }
+            b';' => {
+                statements.push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    statements
+}
+
+/// Splits `#12=IFCWALL(...)` into its id and record.
+/// Complex instances, `#12=(IFCA(...)IFCB(...))`, have no single type and are skipped.
+fn index_entity(content: &str, offset: usize) -> Option<(u64, Record)> {
+    let rest = content.strip_prefix('#')?;
+    let (id, rest) = rest.split_once('=')?;
+    let id: u64 = id.trim().parse().ok()?;
+    let kind_start = content.len() - rest.trim_start().len();
+    let open = kind_start + content[kind_start..].find('(')?;
+    let kind = content[kind_start..open].trim_end();
+    if kind.is_empty() || !kind.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+        return None;
+    }
+    Some((
+        id,
+        Record {
+            kind: offset + kind_start..offset + kind_start + kind.len(),
+            args: offset + open..offset + content.len(),
+        },
+    ))
+}
+
+/// Parser of the attribute list of an entity
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a [u8] {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|b| f(*b)) {
+            self.pos += 1;
+        }
+        &self.bytes[start..self.pos]
+    }
+
+    fn list(&mut self) -> Option<Value> {
+        if self.peek()? != b'(' {
+            return None;
+        }
+        self.pos += 1;
+        let mut values = Vec::new();
+        if self.peek()? == b')' {
+            self.pos += 1;
+            return Some(Value::List(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b')' => {
+                    self.pos += 1;
+                    return Some(Value::List(values));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        match self.peek()? {
+            b'$' | b'*' => {
+                self.pos += 1;
+                Some(Value::Null)
+            }
+            b'#' => {
+                self.pos += 1;
+                let digits = self.take_while(|b| b.is_ascii_digit());
+                std::str::from_utf8(digits)
+                    .ok()?
+                    .parse()
+                    .ok()
+                    .map(Value::Ref)
+            }
+            b'\'' => self.string(),
+            b'"' => {
+                self.pos += 1;
+                let hex = self.take_while(|b| b != b'"');
+                self.pos += 1;
+                Some(Value::Str(String::from_utf8_lossy(hex).into_owned()))
+            }
+            b'.' => {
+                self.pos += 1;
+                let name = self.take_while(|b| b != b'.');
+                self.pos += 1;
+                Some(Value::Enum(String::from_utf8_lossy(name).into_owned()))
+            }
+            b'(' => self.list(),
+            b'0'..=b'9' | b'-' | b'+' => {
+                let number = self.take_while(|b| {
+                    b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'-' | b'+')
+                });
+                std::str::from_utf8(number)
+                    .ok()?
+                    .parse()
+                    .ok()
+                    .map(Value::Number)
+            }
+            b if b.is_ascii_alphabetic() => {
+                let name = self.take_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+                let name = String::from_utf8_lossy(name).into_owned();
+                let inner = match self.list()? {
+                    Value::List(mut values) if values.len() == 1 => values.remove(0),
+                    list => list,
+                };
+                Some(Value::Typed(name, Box::new(inner)))
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<Value> {
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'\'' if self.bytes.get(self.pos + 1) == Some(&b'\'') => self.pos += 2,
+                b'\'' => break,
+                _ => self.pos += 1,
+            }
+        }
+        let raw = String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned();
+        self.pos += 1;
+        Some(Value::Str(decode_string(&raw)))
+    }
+}
+
+/// Decodes the escapes of STEP strings: `''`, `\\`, `\S\` for the upper half of ISO 8859-1,
+/// `\X\` for a byte in hexadecimal and `\X2\`, `\X4\` for UTF-16 and UTF-32 in hexadecimal.
+/// Code page switches, `\P?\`, are ignored.
+fn decode_string(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(c) = rest.chars().next() {
+        if c == '\'' && rest.starts_with("''") {
+            out.push('\'');
+            rest = &rest[2..];
+        } else if c != '\\' {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        } else if let Some(tail) = rest.strip_prefix("\\\\") {
+            out.push('\\');
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("\\S\\") {
+            let mut chars = tail.chars();
+            if let Some(c) = chars.next() {
+                out.extend(char::from_u32(c as u32 + 0x80));
+            }
+            rest = chars.as_str();
+        } else if let Some(tail) = rest.strip_prefix("\\X\\") {
+            let byte = tail.get(..2).and_then(|h| u8::from_str_radix(h, 16).ok());
+            out.extend(byte.map(char::from));
+            rest = tail.get(2..).unwrap_or_default();
+        } else if let Some(tail) = rest.strip_prefix("\\X2\\") {
+            let end = tail.find("\\X0\\").unwrap_or(tail.len());
+            // groups which are not hexadecimal, e.g. holding other characters, are skipped
+            let units: Vec<u16> = (0..end / 4)
+                .filter_map(|i| tail.get(i * 4..i * 4 + 4))
+                .filter_map(|hex| u16::from_str_radix(hex, 16).ok())
+                .collect();
+            out.push_str(&String::from_utf16_lossy(&units));
+            rest = tail.get(end + 4..).unwrap_or_default();
+        } else if let Some(tail) = rest.strip_prefix("\\X4\\") {
+            let end = tail.find("\\X0\\").unwrap_or(tail.len());
+            out.extend(
+                (0..end / 8)
+                    .filter_map(|i| tail.get(i * 8..i * 8 + 8))
+                    .filter_map(|hex| u32::from_str_radix(hex, 16).ok())
+                    .filter_map(char::from_u32),
+            );
+            rest = tail.get(end + 4..).unwrap_or_default();
+        } else if rest.starts_with("\\P") && rest.get(3..4) == Some("\\") {
+            rest = &rest[4..];
+        } else {
+            out.push(c);
+            rest = &rest[1..];
+        }
+    }
+    out
+}
+
+/// Factors from the units of the model to m, m², m³ and kg
+#[derive(Clone, Copy, Debug)]
+struct Units {
+    length: f64,
+    area: f64,
+    volume: f64,
+    mass: f64,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            length: 1.,
+            area: 1.,
+            volume: 1.,
+            mass: 1.,
+        }
+    }
+}
+
+impl Units {
+    /// Units assigned to the project, SI units without prefix if there are none
+    fn of_project(model: &Model) -> Self {
+        let mut units = Units::default();
+        for assignment in model.all(&["IFCUNITASSIGNMENT"]) {
+            for unit in assignment.arg(0).refs() {
+                if let Some((dimension, factor)) = unit_factor(model, unit) {
+                    units.set(dimension, factor);
+                }
+            }
+        }
+        units
+    }
+
+    fn set(&mut self, dimension: Dimension, factor: f64) {
+        match dimension {
+            Dimension::Length => self.length = factor,
+            Dimension::Area => self.area = factor,
+            Dimension::Volume => self.volume = factor,
+            Dimension::Mass => self.mass = factor,
+            Dimension::Count => {}
+        }
+    }
+
+    fn get(&self, dimension: Dimension) -> f64 {
+        match dimension {
+            Dimension::Length => self.length,
+            Dimension::Area => self.area,
+            Dimension::Volume => self.volume,
+            Dimension::Mass => self.mass,
+            Dimension::Count => 1.,
+        }
+    }
+}
+
+/// Dimension of an `IfcSIUnit` or `IfcConversionBasedUnit` and its factor to SI units
+fn unit_factor(model: &Model, id: u64) -> Option<(Dimension, f64)> {
+    let unit = model.get(id)?;
+    let dimension = match unit.arg(1).as_enum()? {
+        "LENGTHUNIT" => Dimension::Length,
+        "AREAUNIT" => Dimension::Area,
+        "VOLUMEUNIT" => Dimension::Volume,
+        "MASSUNIT" => Dimension::Mass,
+        _ => return None,
+    };
+    let factor = match unit.kind.as_str() {
+        "IFCSIUNIT" => {
+            let prefix = match unit.arg(2).as_enum() {
+                Some("KILO") => 1e3,
+                Some("HECTO") => 1e2,
+                Some("DECA") => 1e1,
+                Some("DECI") => 1e-1,
+                Some("CENTI") => 1e-2,
+                Some("MILLI") => 1e-3,
+                Some("MICRO") => 1e-6,
+                _ => 1.,
+            };
+            match (dimension, unit.arg(3).as_enum()) {
+                (Dimension::Area, _) => prefix * prefix,
+                (Dimension::Volume, _) => prefix * prefix * prefix,
+                // the SI unit of mass is the kilogram
+                (Dimension::Mass, Some("GRAM")) => prefix * 1e-3,
+                _ => prefix,
+            }
+        }
+        "IFCCONVERSIONBASEDUNIT" => {
+            let measure = model.get_ref(unit.arg(3))?;
+            let base = measure
+                .arg(1)
+                .as_ref()
+                .and_then(|id| unit_factor(model, id))
+                .map_or(1., |(_, factor)| factor);
+            measure.arg(0).as_f64()? * base
+        }
+        _ => return None,
+    };
+    Some((dimension, factor))
+}
+
+/// Quantities of an element in SI units, the best candidate of each dimension
+#[derive(Clone, Copy, Debug, Default)]
+struct ElementQuantities {
+    volume: Option<Measured>,
+    area: Option<Measured>,
+    length: Option<Measured>,
+    mass: Option<Measured>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Measured {
+    value: f64,
+    rank: u8,
+}
+
+impl ElementQuantities {
+    /// Keeps the quantity if it describes the element better than the one already found:
+    /// net before gross quantities and the larger value of two alike, e.g. the side area
+    /// of a wall before its footprint
+    fn add(&mut self, dimension: Dimension, name: &str, value: f64) {
+        let slot = match dimension {
+            Dimension::Volume => &mut self.volume,
+            Dimension::Area => &mut self.area,
+            Dimension::Length => &mut self.length,
+            Dimension::Mass => &mut self.mass,
+            Dimension::Count => return,
+        };
+        let name = name.to_lowercase();
+        let rank = if name.contains("net") || name == "length" {
+            0
+        } else if ["width", "height", "depth", "thickness", "perimeter"]
+            .iter()
+            .any(|n| name.contains(n))
+        {
+            3
+        } else if name.contains("gross") {
+            2
+        } else {
+            1
+        };
+        keep(slot, Measured { value, rank });
+    }
+
+    /// Keeps the best quantities of both, an element may have several quantity sets
+    fn merge(&mut self, other: &ElementQuantities) {
+        for (slot, measured) in [
+            (&mut self.volume, other.volume),
+            (&mut self.area, other.area),
+            (&mut self.length, other.length),
+            (&mut self.mass, other.mass),
+        ] {
+            if let Some(m) = measured {
+                keep(slot, m);
+            }
+        }
+    }
+}
+
+fn keep(slot: &mut Option<Measured>, measured: Measured) {
+    let better = match slot {
+        Some(m) => measured.rank < m.rank || (measured.rank == m.rank && measured.value > m.value),
+        None => true,
+    };
+    if better {
+        *slot = Some(measured);
+    }
+}
+
+/// Quantities of the elements made of one material of the model
+#[derive(Clone, Debug, Default)]
+pub struct MaterialTakeoff {
+    /// Name of the material in the model
+    pub material: String,
+    /// m³, `None` when no element of the material has a volume
+    pub volume: Option<f64>,
+    /// m², layers of a layer set all take the area of the element
+    pub area: Option<f64>,
+    /// m
+    pub length: Option<f64>,
+    /// kg
+    pub mass: Option<f64>,
+    /// GlobalIds of the elements made of the material
+    pub elements: Vec<String>,
+}
+
+impl MaterialTakeoff {
+    /// Quantity in the dimension of a declared unit, falling back to the first quantity
+    /// the model has which can be converted with the conversion factors of a component
+    pub fn quantity(&self, dimension: Dimension) -> (f64, QuantityUnit) {
+        let count = (self.elements.len() as f64, QuantityUnit::Item);
+        let quantities = [
+            (
+                Dimension::Volume,
+                self.volume.map(|v| (v, QuantityUnit::M3)),
+            ),
+            (Dimension::Mass, self.mass.map(|m| (m, QuantityUnit::Kg))),
+            (Dimension::Area, self.area.map(|a| (a, QuantityUnit::M2))),
+            (Dimension::Length, self.length.map(|l| (l, QuantityUnit::M))),
+            (Dimension::Count, Some(count)),
+        ];
+        quantities
+            .iter()
+            .find(|(d, q)| *d == dimension && q.is_some())
+            .or_else(|| quantities.iter().find(|(_, q)| q.is_some()))
+            .and_then(|(_, q)| *q)
+            .unwrap_or(count)
+    }
+
+    fn add(&mut self, quantities: &ElementQuantities, share: f64) {
+        let sum = |total: &mut Option<f64>, measured: Option<Measured>, share: f64| {
+            if let Some(m) = measured {
+                *total = Some(total.unwrap_or_default() + m.value * share);
+            }
+        };
+        sum(&mut self.volume, quantities.volume, share);
+        sum(&mut self.mass, quantities.mass, share);
+        sum(&mut self.area, quantities.area, 1.);
+        sum(&mut self.length, quantities.length, 1.);
+    }
+}
+
+/// Quantities of a model summed by material
+#[derive(Clone, Debug, Default)]
+pub struct Takeoff {
+    /// Schema of the file, e.g. `IFC2X3` or `IFC4`
+    pub schema: String,
+    /// Sorted by material name
+    pub materials: Vec<MaterialTakeoff>,
+    /// GlobalIds of the elements with quantities but no material
+    pub unassigned: Vec<String>,
+}
+
+impl Takeoff {
+    pub fn load(path: &Path) -> Result<Self, IfcError> {
+        let bytes = std::fs::read(path)?;
+        Self::parse(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    pub fn parse(text: String) -> Result<Self, IfcError> {
+        let model = Model::parse(text)?;
+        let units = Units::of_project(&model);
+        let quantities = element_quantities(&model, &units);
+        let materials = element_materials(&model);
+
+        let mut takeoff: BTreeMap<String, MaterialTakeoff> = BTreeMap::new();
+        let mut unassigned = Vec::new();
+        let mut elements: Vec<&u64> = quantities.keys().chain(materials.keys()).collect();
+        elements.sort_unstable();
+        elements.dedup();
+        for element in elements {
+            let Some(global_id) = model
+                .get(*element)
+                .and_then(|e| e.arg(0).as_str().map(str::to_string))
+            else {
+                continue;
+            };
+            let element_quantities = quantities.get(element).copied().unwrap_or_default();
+            let Some(shares) = materials.get(element).filter(|s| !s.is_empty()) else {
+                unassigned.push(global_id);
+                continue;
+            };
+            for (material, share) in shares {
+                let entry = takeoff
+                    .entry(material.clone())
+                    .or_insert_with(|| MaterialTakeoff {
+                        material: material.clone(),
+                        ..Default::default()
+                    });
+                entry.add(&element_quantities, *share);
+                if entry.elements.last() != Some(&global_id) {
+                    entry.elements.push(global_id.clone());
+                }
+            }
+        }
+        Ok(Self {
+            schema: model.schema,
+            materials: takeoff.into_values().collect(),
+            unassigned,
+        })
+    }
+}
+
+/// Quantities of the `IfcElementQuantity` sets related to each element, by entity id
+fn element_quantities(model: &Model, units: &Units) -> HashMap<u64, ElementQuantities> {
+    let mut elements: HashMap<u64, ElementQuantities> = HashMap::new();
+    for rel in model.all(&["IFCRELDEFINESBYPROPERTIES"]) {
+        let Some(set) = model.get_ref(rel.arg(5)) else {
+            continue;
+        };
+        if set.kind != "IFCELEMENTQUANTITY" {
+            continue;
+        }
+        let mut quantities = ElementQuantities::default();
+        for quantity in set.arg(5).refs().filter_map(|id| model.get(id)) {
+            let dimension = match quantity.kind.as_str() {
+                "IFCQUANTITYVOLUME" => Dimension::Volume,
+                "IFCQUANTITYAREA" => Dimension::Area,
+                "IFCQUANTITYLENGTH" => Dimension::Length,
+                "IFCQUANTITYWEIGHT" => Dimension::Mass,
+                _ => continue,
+            };
+            let Some(value) = quantity.arg(3).as_f64() else {
+                continue;
+            };
+            // a quantity may have its own unit instead of the one of the project
+            let factor = quantity
+                .arg(2)
+                .as_ref()
+                .and_then(|unit| unit_factor(model, unit))
+                .map_or(units.get(dimension), |(_, factor)| factor);
+            let name = quantity.arg(0).as_str().unwrap_or_default();
+            quantities.add(dimension, name, value * factor);
+        }
+        for element in rel.arg(4).refs() {
+            elements.entry(element).or_default().merge(&quantities);
+        }
+    }
+    elements
+}
+
+/// Materials of each element, by entity id, with the share of the volume of the element
+/// they make. Materials associated with the type of an element apply when the element
+/// has none of its own.
+fn element_materials(model: &Model) -> HashMap<u64, Vec<(String, f64)>> {
+    let mut direct: HashMap<u64, Vec<(String, f64)>> = HashMap::new();
+    for rel in model.all(&["IFCRELASSOCIATESMATERIAL"]) {
+        // a material used by several layers counts once per element
+        let mut shares: Vec<(String, f64)> = Vec::new();
+        for (material, share) in material_shares(model, rel.arg(5)) {
+            match shares.iter_mut().find(|(m, _)| *m == material) {
+                Some((_, total)) => *total += share,
+                None => shares.push((material, share)),
+            }
+        }
+        for object in rel.arg(4).refs() {
+            direct.insert(object, shares.clone());
+        }
+    }
+    let mut elements = direct.clone();
+    for rel in model.all(&["IFCRELDEFINESBYTYPE"]) {
+        let Some(shares) = rel.arg(5).as_ref().and_then(|t| direct.get(&t)) else {
+            continue;
+        };
+        for object in rel.arg(4).refs() {
+            elements.entry(object).or_insert_with(|| shares.clone());
+        }
+    }
+    // types are not elements of the model
+    elements.retain(|id, _| {
+        model
+            .kind(*id)
+            .is_some_and(|kind| !kind.ends_with("TYPE") && !kind.ends_with("STYLE"))
+    });
+    elements
+}
+
+/// Names of the materials of an `IfcMaterialSelect` and their share of the volume.
+/// Layers share the volume by thickness, constituents by their fraction and the materials
+/// of lists and profile sets equally.
+fn material_shares(model: &Model, value: &Value) -> Vec<(String, f64)> {
+    let Some(entity) = model.get_ref(value) else {
+        return Vec::new();
+    };
+    let name = |value: &Value| -> Option<String> {
+        let material = model.get_ref(value)?;
+        let name = material.arg(0).as_str()?.trim();
+        (material.kind == "IFCMATERIAL" && !name.is_empty()).then(|| name.to_string())
+    };
+    let equal = |names: Vec<String>| {
+        let share = 1. / names.len().max(1) as f64;
+        names.into_iter().map(|n| (n, share)).collect()
+    };
+    match entity.kind.as_str() {
+        "IFCMATERIAL" => name(value).map(|n| vec![(n, 1.)]).unwrap_or_default(),
+        "IFCMATERIALLIST" => equal(entity.arg(0).as_list().iter().filter_map(name).collect()),
+        "IFCMATERIALLAYERSETUSAGE"
+        | "IFCMATERIALPROFILESETUSAGE"
+        | "IFCMATERIALPROFILESETUSAGETAPERING" => material_shares(model, entity.arg(0)),
+        "IFCMATERIALLAYERSET" => {
+            let layers: Vec<(Option<String>, f64)> = entity
+                .arg(0)
+                .refs()
+                .filter_map(|id| model.get(id))
+                .map(|layer| {
+                    (
+                        name(layer.arg(0)),
+                        layer.arg(1).as_f64().unwrap_or_default(),
+                    )
+                })
+                .collect();
+            let total: f64 = layers.iter().map(|(_, t)| t).sum();
+            let count = layers.len().max(1) as f64;
+            // a layer without material, e.g. an air gap, takes its share of the volume away
+            layers
+                .into_iter()
+                .filter_map(|(n, t)| Some((n?, if total > 0. { t / total } else { 1. / count })))
+                .collect()
+        }
+        "IFCMATERIALLAYER" => name(entity.arg(0))
+            .map(|n| vec![(n, 1.)])
+            .unwrap_or_default(),
+        "IFCMATERIALCONSTITUENTSET" => {
+            let constituents: Vec<(Option<String>, Option<f64>)> = entity
+                .arg(2)
+                .refs()
+                .filter_map(|id| model.get(id))
+                .map(|c| (name(c.arg(2)), c.arg(3).as_f64()))
+                .collect();
+            if constituents.iter().all(|(_, fraction)| fraction.is_some()) {
+                constituents
+                    .into_iter()
+                    .filter_map(|(n, f)| Some((n?, f?)))
+                    .collect()
+            } else {
+                equal(constituents.into_iter().filter_map(|(n, _)| n).collect())
+            }
+        }
+        "IFCMATERIALCONSTITUENT" | "IFCMATERIALPROFILE" => name(entity.arg(2))
+            .map(|n| vec![(n, 1.)])
+            .unwrap_or_default(),
+        "IFCMATERIALPROFILESET" => equal(
+            entity
+                .arg(2)
+                .refs()
+                .filter_map(|id| model.get(id))
+                .filter_map(|profile| name(profile.arg(2)))
+                .collect(),
+        ),
+        _ => Vec::new(),
+    }
+}
+
+/// A material of the model and the category it is added to the project as
+pub struct IfcRow {
+    pub takeoff: MaterialTakeoff,
+    pub category: Option<String>,
+    /// Whether [IfcRow::category] comes from a saved mapping instead of a guess
+    pub mapped: bool,
+    /// Whether the material is added to the project
+    pub include: bool,
+}
+
+/// A model being mapped to categories and reviewed before its materials are added
+pub struct IfcImport {
+    pub path: PathBuf,
+    pub takeoff: Takeoff,
+    pub rows: Vec<IfcRow>,
+}
+
+impl IfcImport {
+    /// Reads the takeoff of a model and maps its materials with the saved mappings,
+    /// guessing the category of the others. Only mapped materials start included.
+    pub fn open(path: PathBuf) -> Result<Self, IfcError> {
+        let takeoff = Takeoff::load(&path)?;
+        let mappings = material_db::get_ifc_mappings().unwrap_or_else(|e| {
+            eprintln!("ERROR: could not load the IFC material mappings: {e}");
+            HashMap::new()
+        });
+        let categories: Vec<String> = material_db::get_category_counts()
+            .map(|counts| counts.into_keys().collect())
+            .unwrap_or_default();
+        let rows = takeoff
+            .materials
+            .iter()
+            .map(|material| {
+                let mapped = mappings.get(&material.material).cloned();
+                IfcRow {
+                    include: mapped.is_some(),
+                    mapped: mapped.is_some(),
+                    category: mapped.or_else(|| guess_category(&material.material, &categories)),
+                    takeoff: material.clone(),
+                }
+            })
+            .collect();
+        Ok(Self {
+            path,
+            takeoff,
+            rows,
+        })
+    }
+
+    /// Saves the mappings of the included rows and adds them to the project as generic
+    /// components of their category, returning how many were added
+    pub fn add_to_project(&self, project: &mut Project) -> rusqlite::Result<usize> {
+        let mut added = 0;
+        for row in self.rows.iter().filter(|r| r.include) {
+            let Some(category) = &row.category else {
+                continue;
+            };
+            material_db::set_ifc_mapping(&row.takeoff.material, category)?;
+            let declared = material_db::get_category_unit(category)?;
+            let dimension = QuantityUnit::from_declared(&declared.unit)
+                .map_or(Dimension::Volume, |unit| unit.dimension());
+            let (quantity, unit) = row.takeoff.quantity(dimension);
+            project.add_generic_comp(category);
+            if let Some(component) = project.components.last_mut() {
+                component.quantity = quantity;
+                component.unit = Some(unit);
+                component.ifc_elements = row.takeoff.elements.clone();
+            }
+            added += 1;
+        }
+        project.calculate();
+        Ok(added)
+    }
+}
+
+/// The category a material name most likely belongs to: the most similar category name,
+/// or the longest one the name contains as a word, e.g. `Concrete` for `Concrete C30/37`
+fn guess_category(material: &str, categories: &[String]) -> Option<String> {
+    boq::best_category(material, categories).or_else(|| {
+        let words: Vec<String> = boq::words(material).collect();
+        categories
+            .iter()
+            .filter(|c| words.contains(&c.to_lowercase()))
+            .max_by_key(|c| c.len())
+            .cloned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two walls with a layer set and a column typed with its material, in millimetres,
+    /// square feet, cubic centimetres and tonnes
+    const IFC2X3: &str = r"ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('ViewDefinition [CoordinationView]'),'2;1');
+FILE_NAME('walls.ifc','2024-01-01T00:00:00',(''),(''),'','','');
+FILE_SCHEMA(('IFC2X3'));
+ENDSEC;
+DATA;
+#1=IFCPROJECT('0YvctVUKr0kugbFTf53O9L',$,'Project',$,$,$,$,$,#2);
+#2=IFCUNITASSIGNMENT((#3,#4,#5,#6));
+#3=IFCSIUNIT(*,.LENGTHUNIT.,.MILLI.,.METRE.);
+#4=IFCCONVERSIONBASEDUNIT(#7,.AREAUNIT.,'square foot',#8);
+#5=IFCSIUNIT(*,.VOLUMEUNIT.,.CENTI.,.CUBIC_METRE.);
+#6=IFCCONVERSIONBASEDUNIT(#7,.MASSUNIT.,'tonne',#9);
+#7=IFCDIMENSIONALEXPONENTS(0,0,0,0,0,0,0);
+#8=IFCMEASUREWITHUNIT(IFCAREAMEASURE(0.09290304),#10);
+#9=IFCMEASUREWITHUNIT(IFCMASSMEASURE(1000.),#11);
+#10=IFCSIUNIT(*,.AREAUNIT.,$,.SQUARE_METRE.);
+#11=IFCSIUNIT(*,.MASSUNIT.,.KILO.,.GRAM.);
+#20=IFCWALLSTANDARDCASE('2O2Fr$t4X7Zf8NOew3FLOH',$,'Wall north',$,$,$,$,$);
+#21=IFCWALLSTANDARDCASE('2O2Fr$t4X7Zf8NOew3FLOI',$,'Wall south',$,$,$,$,$);
+#22=IFCMATERIAL('Concrete C30/37');
+#23=IFCMATERIAL('Mineral wool');
+#24=IFCMATERIALLAYER(#22,200.,$);
+#25=IFCMATERIALLAYER($,50.,.T.);
+#26=IFCMATERIALLAYER(#23,100.,$);
+#27=IFCMATERIALLAYERSET((#24,#25,#26),'Exterior wall');
+#28=IFCMATERIALLAYERSETUSAGE(#27,.AXIS2.,.POSITIVE.,0.);
+#29=IFCRELASSOCIATESMATERIAL('1kTvXnbbzCWw8lcMd1dR4o',$,$,$,(#20,#21),#28);
+#30=IFCELEMENTQUANTITY('3kTvXnbbzCWw8lcMd1dR4o',$,'BaseQuantities',$,$,(#31,#32,#33,#34,#35));
+#31=IFCQUANTITYLENGTH('Width',$,$,350.);
+#32=IFCQUANTITYLENGTH('Length',$,$,5000.);
+#33=IFCQUANTITYAREA('NetSideArea',$,$,100.);
+#34=IFCQUANTITYVOLUME('GrossVolume',$,$,4000000.);
+#35=IFCQUANTITYVOLUME('NetVolume',$,$,3500000.);
+#36=IFCRELDEFINESBYPROPERTIES('4kTvXnbbzCWw8lcMd1dR4o',$,$,$,(#20,#21),#30);
+#40=IFCCOLUMN('1O2Fr$t4X7Zf8NOew3FLOH',$,'Column',$,$,$,$,$);
+#41=IFCCOLUMNTYPE('1O2Fr$t4X7Zf8NOew3FLOT',$,'HEB 200',$,$,$,$,$,$,.COLUMN.);
+#42=IFCRELDEFINESBYTYPE('5kTvXnbbzCWw8lcMd1dR4o',$,$,$,(#40),#41);
+#43=IFCMATERIAL('Steel S355');
+#44=IFCRELASSOCIATESMATERIAL('6kTvXnbbzCWw8lcMd1dR4o',$,$,$,(#41),#43);
+#45=IFCELEMENTQUANTITY('7kTvXnbbzCWw8lcMd1dR4o',$,'BaseQuantities',$,$,(#46,#47));
+#46=IFCQUANTITYVOLUME('NetVolume',$,$,100000.);
+#47=IFCQUANTITYWEIGHT('NetWeight',$,#11,785.);
+#48=IFCRELDEFINESBYPROPERTIES('8kTvXnbbzCWw8lcMd1dR4o',$,$,$,(#40),#45);
+#50=IFCBEAM('2Q2Fr$t4X7Zf8NOew3FLOH',$,'Beam',$,$,$,$,$);
+#51=IFCELEMENTQUANTITY('CkTvXnbbzCWw8lcMd1dR4o',$,'BaseQuantities',$,$,(#52));
+#52=IFCQUANTITYVOLUME('NetVolume',$,$,300000.);
+#53=IFCRELDEFINESBYPROPERTIES('DkTvXnbbzCWw8lcMd1dR4o',$,$,$,(#50),#51);
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    /// Slabs of a type, one overriding the material of its type, and a window of
+    /// constituents, in metres and cubic feet
+    const IFC4: &str = r"ISO-10303-21;
+HEADER;
+FILE_DESCRIPTION(('ViewDefinition [ReferenceView_V1.2]'),'2;1');
+FILE_NAME('slabs.ifc','2024-01-01T00:00:00',(''),(''),'','','');
+FILE_SCHEMA(('IFC4'));
+ENDSEC;
+DATA;
+#1=IFCPROJECT('0YvctVUKr0kugbFTf53O9M',$,'Project',$,$,$,$,$,#2);
+#2=IFCUNITASSIGNMENT((#3,#4));
+#3=IFCSIUNIT(*,.LENGTHUNIT.,$,.METRE.);
+#4=IFCCONVERSIONBASEDUNIT(#5,.VOLUMEUNIT.,'cubic foot',#6);
+#5=IFCDIMENSIONALEXPONENTS(3,0,0,0,0,0,0);
+#6=IFCMEASUREWITHUNIT(IFCVOLUMEMEASURE(0.028316846592),#7);
+#7=IFCSIUNIT(*,.VOLUMEUNIT.,$,.CUBIC_METRE.);
+#10=IFCSLAB('1O2Fr$t4X7Zf8NOew3FLOA',$,'Slab 1',$,$,$,$,$,.FLOOR.);
+#11=IFCSLAB('1O2Fr$t4X7Zf8NOew3FLOB',$,'Slab 2',$,$,$,$,$,.FLOOR.);
+#12=IFCSLABTYPE('1O2Fr$t4X7Zf8NOew3FLOT',$,'Slab type',$,$,$,$,$,$,.FLOOR.);
+#13=IFCRELDEFINESBYTYPE('5kTvXnbbzCWw8lcMd1dR4p',$,$,$,(#10,#11),#12);
+#14=IFCMATERIAL('B\X2\00E9\X0\ton arm''e',$,$);
+#15=IFCRELASSOCIATESMATERIAL('6kTvXnbbzCWw8lcMd1dR4p',$,$,$,(#12),#14);
+#16=IFCMATERIAL('Timber',$,'Wood');
+#17=IFCRELASSOCIATESMATERIAL('6kTvXnbbzCWw8lcMd1dR4q',$,$,$,(#11),#16);
+#18=IFCELEMENTQUANTITY('7kTvXnbbzCWw8lcMd1dR4p',$,'Qto_SlabBaseQuantities',$,$,(#19,#20));
+#19=IFCQUANTITYVOLUME('NetVolume',$,$,100.,$);
+#20=IFCQUANTITYAREA('NetArea',$,$,25.,$);
+#21=IFCRELDEFINESBYPROPERTIES('8kTvXnbbzCWw8lcMd1dR4p',$,$,$,(#10,#11),#18);
+#30=IFCWINDOW('2P2Fr$t4X7Zf8NOew3FLOH',$,'Window',$,$,$,$,$,1.2,1.,$,$,$);
+#31=IFCMATERIAL('Glass',$,$);
+#32=IFCMATERIAL('Aluminium',$,$);
+#33=IFCMATERIALCONSTITUENT('Pane',$,#31,0.25,$);
+#34=IFCMATERIALCONSTITUENT('Frame',$,#32,0.75,$);
+#35=IFCMATERIALCONSTITUENTSET('Window',$,(#33,#34));
+#36=IFCRELASSOCIATESMATERIAL('9kTvXnbbzCWw8lcMd1dR4p',$,$,$,(#30),#35);
+#37=IFCELEMENTQUANTITY('AkTvXnbbzCWw8lcMd1dR4p',$,'Qto_WindowBaseQuantities',$,$,(#38,#39));
+#38=IFCQUANTITYAREA('Area',$,$,1.2,$);
+#39=IFCQUANTITYWEIGHT('GrossWeight',$,#40,40000.,$);
+#40=IFCSIUNIT(*,.MASSUNIT.,$,.GRAM.);
+#41=IFCRELDEFINESBYPROPERTIES('BkTvXnbbzCWw8lcMd1dR4p',$,$,$,(#30),#37);
+ENDSEC;
+END-ISO-10303-21;
+";
+
+    fn material<'a>(takeoff: &'a Takeoff, name: &str) -> &'a MaterialTakeoff {
+        takeoff
+            .materials
+            .iter()
+            .find(|m| m.material == name)
+            .unwrap_or_else(|| panic!("{name} is not in the takeoff"))
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("quantity is missing");
+        assert!((value - expected).abs() < 1e-6, "{value} != {expected}");
+    }
+
+    #[test]
+    fn takes_off_an_ifc2x3_model() {
+        let takeoff = Takeoff::parse(IFC2X3.to_string()).unwrap();
+        assert_eq!(takeoff.schema, "IFC2X3");
+        let names: Vec<&str> = takeoff
+            .materials
+            .iter()
+            .map(|m| m.material.as_str())
+            .collect();
+        assert_eq!(names, ["Concrete C30/37", "Mineral wool", "Steel S355"]);
+
+        // 3.5 m³ per wall shared by the thickness of the layers, the air gap takes 50 of 350 mm
+        let concrete = material(&takeoff, "Concrete C30/37");
+        assert_close(concrete.volume, 2. * 3.5 * 200. / 350.);
+        assert_close(
+            material(&takeoff, "Mineral wool").volume,
+            2. * 3.5 * 100. / 350.,
+        );
+        // every layer takes the area and length of the wall
+        assert_close(concrete.area, 2. * 100. * 0.09290304);
+        assert_close(concrete.length, 2. * 5.);
+        assert_eq!(
+            concrete.elements,
+            ["2O2Fr$t4X7Zf8NOew3FLOH", "2O2Fr$t4X7Zf8NOew3FLOI"]
+        );
+
+        // the material of the column comes from its type, the weight has its own unit
+        let steel = material(&takeoff, "Steel S355");
+        assert_close(steel.volume, 0.1);
+        assert_close(steel.mass, 785.);
+        assert_eq!(steel.elements, ["1O2Fr$t4X7Zf8NOew3FLOH"]);
+
+        assert_eq!(takeoff.unassigned, ["2Q2Fr$t4X7Zf8NOew3FLOH"]);
+    }
+
+    #[test]
+    fn takes_off_an_ifc4_model() {
+        let takeoff = Takeoff::parse(IFC4.to_string()).unwrap();
+        assert_eq!(takeoff.schema, "IFC4");
+        let cubic_foot = 0.028316846592;
+
+        // only the first slab inherits the material of the type, the second has its own
+        let concrete = material(&takeoff, "Béton arm'e");
+        assert_close(concrete.volume, 100. * cubic_foot);
+        assert_close(concrete.area, 25.);
+        assert_eq!(concrete.elements, ["1O2Fr$t4X7Zf8NOew3FLOA"]);
+        let timber = material(&takeoff, "Timber");
+        assert_close(timber.volume, 100. * cubic_foot);
+        assert_eq!(timber.elements, ["1O2Fr$t4X7Zf8NOew3FLOB"]);
+
+        // constituents share the weight of the window by their fraction
+        assert_close(material(&takeoff, "Glass").mass, 40. * 0.25);
+        assert_close(material(&takeoff, "Aluminium").mass, 40. * 0.75);
+        assert_close(material(&takeoff, "Glass").area, 1.2);
+        assert!(takeoff.unassigned.is_empty());
+    }
+
+    #[test]
+    fn prefers_the_dimension_of_the_declared_unit() {
+        let takeoff = Takeoff::parse(IFC2X3.to_string()).unwrap();
+        let steel = material(&takeoff, "Steel S355");
+        assert_eq!(steel.quantity(Dimension::Mass), (785., QuantityUnit::Kg));
+        let (volume, unit) = steel.quantity(Dimension::Volume);
+        assert_close(Some(volume), 0.1);
+        assert_eq!(unit, QuantityUnit::M3);
+        // no area, the volume is converted with the thickness of the component
+        assert_eq!(steel.quantity(Dimension::Area).1, QuantityUnit::M3);
+        assert_eq!(steel.quantity(Dimension::Count), (1., QuantityUnit::Item));
+    }
+
+    #[test]
+    fn decodes_step_strings() {
+        let cases = [
+            (r"plain", "plain"),
+            (r"arm''e", "arm'e"),
+            (r"back\\slash", r"back\slash"),
+            (r"\S\Dhre", "Ähre"),
+            (r"caf\X\E9", "café"),
+            (r"B\X2\00E9\X0\ton", "Béton"),
+            (r"\X2\00E900E8\X0\", "éè"),
+            (r"\X4\0001F600\X0\!", "😀!"),
+            (r"\PA\code page", "code page"),
+            // groups which are not hexadecimal are skipped, even if they split a character
+            (r"a\X2\000ü\X0\b", "ab"),
+            (r"a\X4\0000000ü\X0\b", "ab"),
+            (r"a\X2\00E", "a"),
+            (r"a\X\", "a"),
+        ];
+        for (raw, decoded) in cases {
+            assert_eq!(decode_string(raw), decoded, "{raw}");
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use std::{
-    sync::mpsc::{channel, Receiver},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc,
+    },
     thread,
+    time::Duration,
 };
 
 use ec3api::{
@@ -9,7 +15,7 @@ use ec3api::{
 };
 
 use crate::{
-    material_db::{migrate, write},
+    material_db::{self, migrate, write, ChangeReport},
     settings,
 };
 
@@ -22,6 +28,204 @@ pub enum CError {
 }
 pub type Result<T> = std::result::Result<T, CError>;
 
+impl Display for CError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CError::FromApi => write!(f, "unexpected response from EC3"),
+            CError::FromDb => write!(f, "could not write to the database"),
+            CError::RequestError => write!(f, "request to EC3 failed"),
+            CError::NoAuth => write!(f, "EC3 refused the API key"),
+        }
+    }
+}
+
+/// Categories refreshed more recently than this are skipped by [Runner::update_db]
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Debug)]
+pub struct UpdateOptions {
+    /// Categories refreshed within this age are not fetched again
+    pub max_age: Duration,
+    /// Fetches every category, regardless of when it was refreshed
+    pub force: bool,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            max_age: DEFAULT_MAX_AGE,
+            force: false,
+        }
+    }
+}
+
+impl UpdateOptions {
+    /// True when the category was refreshed within [UpdateOptions::max_age]
+    fn is_fresh(&self, category: &str) -> bool {
+        if self.force {
+            return false;
+        }
+        match material_db::category_refreshed_at(category) {
+            Ok(Some(refreshed)) => material_db::now() - refreshed < self.max_age.as_secs() as i64,
+            _ => false,
+        }
+    }
+}
+
+/// Outcome of [Runner::update_db] per category
+#[derive(Clone, Debug, Default)]
+pub struct UpdateSummary {
+    pub succeeded: Vec<String>,
+    /// Category and the reason it failed
+    pub failed: Vec<(String, String)>,
+    pub skipped: Vec<String>,
+    /// Materials written by the succeeded categories
+    pub changes: ChangeReport,
+    /// The update was cancelled before visiting every category
+    pub cancelled: bool,
+}
+
+impl Display for UpdateSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} categories updated, {} failed, {} skipped",
+            self.succeeded.len(),
+            self.failed.len(),
+            self.skipped.len()
+        )?;
+        if self.cancelled {
+            write!(f, " before it was cancelled")?;
+        }
+        write!(f, ". Materials: {}", self.changes)
+    }
+}
+
+/// Shared flag to stop a background job, checked between categories
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Messages sent by [Runner::update_db] while it runs
+#[derive(Clone, Debug)]
+pub enum Progress {
+    /// The category tree was received, `total` categories will be visited
+    Started { total: usize },
+    /// The materials of a category are being fetched
+    Fetching { category: String },
+    /// A category was visited
+    Category {
+        category: String,
+        outcome: Outcome,
+        done: usize,
+        total: usize,
+    },
+    /// Last message of an update that ran, completely or until it was cancelled
+    Finished(UpdateSummary),
+    /// Last message of an update that could not start
+    Error(String),
+}
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Written(ChangeReport),
+    Skipped,
+    Failed(String),
+}
+
+/// A running [Runner::update_db], keeping track of the progress it reported
+pub struct UpdateJob {
+    rx: Receiver<Progress>,
+    cancel: CancelToken,
+    /// Category being fetched
+    pub current: Option<String>,
+    pub done: usize,
+    pub total: usize,
+    /// Materials written so far
+    pub materials: usize,
+    /// Categories which failed and the reason
+    pub errors: Vec<(String, String)>,
+    /// Set when the job was asked to stop
+    pub cancelling: bool,
+}
+
+impl UpdateJob {
+    /// Stops the job after the category being fetched
+    pub fn cancel(&mut self) {
+        self.cancel.cancel();
+        self.cancelling = true;
+    }
+
+    /// Fraction of the categories visited, between 0 and 1
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 0.,
+            total => self.done as f32 / total as f32,
+        }
+    }
+
+    /// Applies the progress received so far without blocking.
+    /// Returns the outcome of the job once it is over.
+    pub fn poll(&mut self) -> Option<std::result::Result<UpdateSummary, String>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(progress) => {
+                    if let Some(outcome) = self.apply(progress) {
+                        return Some(outcome);
+                    }
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(Err("the update stopped unexpectedly".into()))
+                }
+            }
+        }
+    }
+
+    /// Waits for the next progress message, `None` once the sender is gone
+    pub fn recv(&mut self) -> Option<Progress> {
+        let progress = self.rx.recv().ok()?;
+        self.apply(progress.clone());
+        Some(progress)
+    }
+
+    fn apply(&mut self, progress: Progress) -> Option<std::result::Result<UpdateSummary, String>> {
+        match progress {
+            Progress::Started { total } => self.total = total,
+            Progress::Fetching { category } => self.current = Some(category),
+            Progress::Category {
+                category,
+                outcome,
+                done,
+                total,
+            } => {
+                self.done = done;
+                self.total = total;
+                self.current = None;
+                match outcome {
+                    Outcome::Written(changes) => self.materials += changes.total(),
+                    Outcome::Skipped => {}
+                    Outcome::Failed(error) => self.errors.push((category, error)),
+                }
+            }
+            Progress::Finished(summary) => return Some(Ok(summary)),
+            Progress::Error(error) => return Some(Err(error)),
+        }
+        None
+    }
+}
+
 pub struct Runner {
     categories_rx: Option<Receiver<Node<Ec3Category>>>,
     pub categories: Option<Node<Ec3Category>>,
@@ -39,7 +243,12 @@ impl Runner {
                     self.categories = Some(categories);
                     return true;
                 }
-                Err(_) => {
+                // the fetch failed, there is nothing left to wait for
+                Err(TryRecvError::Disconnected) => {
+                    self.categories_rx = None;
+                    return true;
+                }
+                Err(TryRecvError::Empty) => {
                     return false;
                 }
             }
@@ -69,48 +278,110 @@ impl Runner {
             }
         });
     }
-    pub fn update_db(api_key: &str) -> Result<Receiver<()>> {
+    /// Fetches the materials of every category into the database on a new thread.
+    /// Progress is recorded per category, so an interrupted update resumes
+    /// with the categories it did not finish.
+    pub fn update_db(api_key: &str, options: UpdateOptions) -> Result<UpdateJob> {
         let api_key = api_key.to_string();
+        let cancel = CancelToken::new();
 
-        let (tx, rx) = channel::<()>();
-        thread::spawn(move || -> Result<()> {
-            migrate().map_err(|e| {
-                eprintln!("ERROR: Not possible to migrate - {:?}", e);
-                CError::FromDb
-            })?;
-            println!("Updating DB...");
-            // load categories
-            let mut runner = Runner {
-                categories_rx: None,
-                categories: None,
-            };
-            runner.fetch_categories(&api_key);
-            loop {
-                if runner.categories_loaded() {
-                    break;
+        let (tx, rx) = channel::<Progress>();
+        let token = cancel.clone();
+        thread::spawn(move || {
+            let last = match run_update(&api_key, &options, &token, &tx) {
+                Ok(summary) => {
+                    println!("Done updating DB! {summary}");
+                    Progress::Finished(summary)
                 }
-            }
-            let category_tree = runner.categories.ok_or_else(|| {
-                eprintln!("ERROR: received no category tree.");
-                CError::FromApi
-            })?;
-
-            let categories = category_tree.children.ok_or_else(|| {
-                eprintln!("ERROR: category tree contains no children");
-                CError::FromApi
-            })?;
-
-            // for each high level category
-            traverse_fetch(&categories, &api_key);
-            // first_level_fetch(&categories, &api_key)?;
-            if let Err(e) = tx.send(()) {
+                Err(e) => {
+                    eprintln!("ERROR: update failed - {e}");
+                    Progress::Error(e.to_string())
+                }
+            };
+            if let Err(e) = tx.send(last) {
                 eprintln!("ERROR: not possible to send to another thread - {:?}", e);
             }
-            println!("Done updating DB!");
-            Ok(())
         });
-        Ok(rx)
+        Ok(UpdateJob {
+            rx,
+            cancel,
+            current: None,
+            done: 0,
+            total: 0,
+            materials: 0,
+            errors: Vec::new(),
+            cancelling: false,
+        })
+    }
+}
+
+fn run_update(
+    api_key: &str,
+    options: &UpdateOptions,
+    cancel: &CancelToken,
+    progress: &Sender<Progress>,
+) -> Result<UpdateSummary> {
+    migrate().map_err(|e| {
+        eprintln!("ERROR: Not possible to migrate - {:?}", e);
+        CError::FromDb
+    })?;
+    println!("Updating DB...");
+    // load categories
+    let mut runner = Runner {
+        categories_rx: None,
+        categories: None,
+    };
+    runner.fetch_categories(api_key);
+    while !runner.categories_loaded() {
+        if cancel.is_cancelled() {
+            return Ok(UpdateSummary {
+                cancelled: true,
+                ..Default::default()
+            });
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let category_tree = runner.categories.ok_or_else(|| {
+        eprintln!("ERROR: received no category tree.");
+        CError::FromApi
+    })?;
+
+    // kept for browsing the categories offline
+    match material_db::write_category_tree(&category_tree) {
+        Ok(count) => println!("Stored {count} categories."),
+        Err(e) => eprintln!("ERROR: while writing the category tree: {e}"),
+    }
+
+    let categories = category_tree.children.ok_or_else(|| {
+        eprintln!("ERROR: category tree contains no children");
+        CError::FromApi
+    })?;
+
+    // for each high level category
+    let mut traversal = Traversal {
+        api_key,
+        options,
+        cancel,
+        progress,
+        summary: UpdateSummary::default(),
+        done: 0,
+        total: count_categories(&categories),
+    };
+    let _ = progress.send(Progress::Started {
+        total: traversal.total,
+    });
+    if let Err(e) = traversal.fetch(&categories) {
+        eprintln!("ERROR: update stopped - {e}");
     }
+    // first_level_fetch(&categories, &api_key)?;
+    Ok(traversal.summary)
+}
+
+fn count_categories(categories: &[Node<Ec3Category>]) -> usize {
+    categories
+        .iter()
+        .map(|cat| 1 + cat.children.as_deref().map_or(0, count_categories))
+        .sum()
 }
 
 #[allow(dead_code)]
@@ -130,29 +401,86 @@ fn first_level_fetch(categories: &[Node<Ec3Category>], api_key: &str) -> Result<
     Ok(())
 }
 
-fn traverse_fetch(categories: &[Node<Ec3Category>], api_key: &str) {
-    for cat in categories {
-        let category = cat.value.name.clone();
-        let materials: Vec<Ec3Material> = match fetch_category(api_key, &category) {
-            Ok(mat) => mat,
-            Err(_) => return,
-        };
-        println!(
-            "Received {count} materials from {category}",
-            count = materials.len()
-        );
-        let _ = write(&materials, &category).map_err(|e| {
-            eprintln!("ERROR: while writing to db: {}", e);
-            CError::FromDb
-        });
-        let _ = crate::material_db::write_category(&cat.value);
-        if cat.children.as_ref().is_some_and(|c| !c.is_empty()) {
-            let children = &cat.children.as_ref().unwrap();
-            traverse_fetch(children, api_key);
+/// State of a running update, shared by the recursive [Traversal::fetch]
+struct Traversal<'a> {
+    api_key: &'a str,
+    options: &'a UpdateOptions,
+    cancel: &'a CancelToken,
+    progress: &'a Sender<Progress>,
+    summary: UpdateSummary,
+    done: usize,
+    total: usize,
+}
+
+impl Traversal<'_> {
+    /// Fetches the categories and their subcategories, recording the outcome of each one.
+    /// A failed category does not stop the traversal, except when the API key is refused.
+    fn fetch(&mut self, categories: &[Node<Ec3Category>]) -> Result<()> {
+        for cat in categories {
+            if self.cancel.is_cancelled() {
+                self.summary.cancelled = true;
+                return Ok(());
+            }
+            let category = cat.value.name.clone();
+            let outcome = if self.options.is_fresh(&category) {
+                self.summary.skipped.push(category.clone());
+                Outcome::Skipped
+            } else {
+                let _ = self.progress.send(Progress::Fetching {
+                    category: category.clone(),
+                });
+                match update_category(cat, self.api_key) {
+                    Ok(changes) => {
+                        self.summary.succeeded.push(category.clone());
+                        self.summary.changes += changes;
+                        Outcome::Written(changes)
+                    }
+                    Err(e) => {
+                        let _ = material_db::fail_category_update(&category, &e.to_string());
+                        self.summary.failed.push((category.clone(), e.to_string()));
+                        if let CError::NoAuth = e {
+                            return Err(e);
+                        }
+                        Outcome::Failed(e.to_string())
+                    }
+                }
+            };
+            self.done += 1;
+            let _ = self.progress.send(Progress::Category {
+                category,
+                outcome,
+                done: self.done,
+                total: self.total,
+            });
+            if cat.children.as_ref().is_some_and(|c| !c.is_empty()) {
+                let children = &cat.children.as_ref().unwrap();
+                self.fetch(children)?;
+            }
         }
+        Ok(())
     }
 }
 
+/// Fetches and writes the materials of a category
+fn update_category(cat: &Node<Ec3Category>, api_key: &str) -> Result<ChangeReport> {
+    let category = &cat.value.name;
+    let db_error = |e: rusqlite::Error| {
+        eprintln!("ERROR: while writing to db: {}", e);
+        CError::FromDb
+    };
+    material_db::begin_category_update(category).map_err(db_error)?;
+    let materials: Vec<Ec3Material> = fetch_category(api_key, category)?;
+    println!(
+        "Received {count} materials from {category}",
+        count = materials.len()
+    );
+    let changes = write(&materials, category).map_err(db_error)?;
+    println!("{category}: {changes}");
+    let _ = material_db::write_category(&cat.value);
+    material_db::complete_category_update(category, materials.len()).map_err(db_error)?;
+    Ok(changes)
+}
+
 fn fetch_category(api_key: &str, query: &str) -> Result<Vec<Ec3Material>> {
     let mut mf = ec3api::material_filter::MaterialFilter::of_category(query);
     mf.add_filter("jurisdiction", "in", vec!["150"]);
@@ -1,47 +1,124 @@
+pub mod assembly;
+pub mod benchmark;
+pub mod boq;
+pub mod export;
+pub mod ifc;
 pub mod jobs;
+pub mod lifecycle;
 pub mod material_db;
+pub mod migrations;
+mod pdf;
 pub mod project;
+pub mod project_file;
+pub mod query;
+pub mod recommend;
+pub mod report;
+pub mod scenario;
+pub mod sensitivity;
 pub mod settings;
+pub mod uncertainty;
+pub mod units;
 use std::{
-    collections::{BTreeSet, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::Display,
-    sync::mpsc::{channel, Receiver},
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, TryRecvError},
     thread,
 };
 
 use egui_notify::{Anchor, Toasts};
+use project_file::ProjectFileError;
 
-use ec3api::{
-    material_filter::MaterialFilter,
-    models::{Ec3Category, Ec3Material, Node},
-    Ec3Result,
-};
+use ec3api::{material_filter::MaterialFilter, models::Ec3Material, Ec3Result};
 
 extern crate ec3api;
 
-pub type CategoriesTree = ec3api::models::Node<ec3api::models::Ec3Category>;
+pub type CategoriesTree = material_db::CategoryNode;
 
 pub type Material = ec3api::models::Ec3Material;
 
+/// A category with the distribution of the GWP of its materials
+pub struct CategoryDetail {
+    pub category: ec3api::models::Category,
+    pub declared_unit: ec3api::models::DeclaredUnit,
+    pub stats: material_db::CategoryStats,
+    /// GWP of every material, sorted ascending
+    pub gwps: Vec<f64>,
+}
+
+impl CategoryDetail {
+    fn load(name: &str) -> rusqlite::Result<Self> {
+        Ok(Self {
+            category: material_db::get_category(name)?,
+            // only set once the category itself was updated
+            declared_unit: material_db::get_category_unit(name).unwrap_or_default(),
+            stats: material_db::get_category_stats(name)?,
+            gwps: material_db::get_category_gwps(name, None)?,
+        })
+    }
+}
+
 pub struct State {
     pub materials_loaded: bool,
     pub materials: Vec<Ec3Material>,
-    pub categories: Option<Node<Ec3Category>>,
+    /// Highlighted excerpts of the last text search, by material id
+    pub snippets: HashMap<String, String>,
+    /// Category tree stored in the database, loaded on first use
+    pub categories: Option<CategoriesTree>,
+    /// Why the last refresh of the category tree from EC3 failed
+    pub categories_error: Option<String>,
     pub loaded_categories: BTreeSet<String>,
+    /// Number of materials in the database of every category, subcategories included
+    pub category_counts: HashMap<String, usize>,
     pub filter_input: String,
     pub fetch_input: String,
     pub country: String,
+    pub manufacturer: String,
+    pub search_category: String,
+    pub gwp_min: Option<f64>,
+    pub gwp_max: Option<f64>,
     pub sort_by: SortBy,
     pub active_tab: Tabs,
     pub selected_category: String,
     materials_rx: Option<Receiver<Vec<Ec3Material>>>,
-    categories_rx: Option<Receiver<Node<Ec3Category>>>,
-    pub job_rx: Option<Receiver<()>>,
+    categories_rx: Option<Receiver<Result<usize, String>>>,
+    /// Database update running in the background
+    pub update_job: Option<jobs::UpdateJob>,
+    /// Options of the next database update
+    pub update_options: jobs::UpdateOptions,
     pub selected: Option<Ec3Material>,
+    /// Distribution of the category of the selected material, in the searched country if any
+    pub selected_benchmark: Option<benchmark::Distribution>,
+    /// GWP values of the selected material across database updates
+    pub selected_history: Vec<material_db::HistoryPoint>,
+    /// Minimum change in percent listed by [State::load_gwp_changes]
+    pub change_threshold: f64,
+    /// Materials whose GWP changed in the last updates, shown until closed
+    pub gwp_changes: Option<Vec<material_db::GwpChange>>,
     pub api_key: Option<String>,
     pub toasts: Toasts,
-    pub category_stats: Option<f64>,
+    /// Statistics of the category of the selected material
+    pub category_stats: Option<material_db::CategoryStats>,
+    /// Category shown on the category page
+    pub category_detail: Option<CategoryDetail>,
     pub project: Option<project::Project>,
+    pub project_path: Option<PathBuf>,
+    /// Index of the project component whose life-cycle assumptions are edited
+    pub selected_component: Option<usize>,
+    /// Bill of quantities being imported into the project
+    pub boq_import: Option<boq::BoqImport>,
+    /// IFC model whose materials are being mapped before they are added to the project
+    pub ifc_import: Option<ifc::IfcImport>,
+    /// Assembly library window, `None` when it is closed
+    pub assembly_library: Option<assembly::AssemblyLibrary>,
+    /// Scenario shown and edited on the calculate page, `None` for the baseline
+    pub active_scenario: Option<String>,
+    /// Name typed to add or rename a scenario
+    pub scenario_input: String,
+    /// Lower-carbon alternatives of the selected component
+    pub alternatives: Option<recommend::Alternatives>,
+    /// Last Monte Carlo simulation of the project
+    pub simulation: Option<uncertainty::Simulation>,
     pub api_key_input: String,
 }
 
@@ -50,8 +127,14 @@ impl State {
         State {
             materials_loaded: false,
             materials: Vec::new(),
+            snippets: HashMap::new(),
             loaded_categories: BTreeSet::new(),
+            category_counts: material_db::get_category_counts().unwrap_or_else(|e| {
+                eprintln!("ERROR: {e}");
+                HashMap::new()
+            }),
             categories: None,
+            categories_error: None,
             filter_input: String::new(),
             fetch_input: String::new(),
             api_key,
@@ -60,56 +143,108 @@ impl State {
             selected_category: String::new(),
             materials_rx: None,
             categories_rx: None,
-            job_rx: None,
+            update_job: None,
+            update_options: jobs::UpdateOptions::default(),
             country: String::new(),
+            manufacturer: String::new(),
+            search_category: String::new(),
+            gwp_min: None,
+            gwp_max: None,
             selected: None,
+            selected_benchmark: None,
+            selected_history: Vec::new(),
+            change_threshold: 10.,
+            gwp_changes: None,
             toasts: Toasts::default().with_anchor(Anchor::BottomRight),
             category_stats: None,
+            category_detail: None,
             project: None,
+            project_path: None,
+            selected_component: None,
+            boq_import: None,
+            ifc_import: None,
+            assembly_library: None,
+            active_scenario: None,
+            scenario_input: String::new(),
+            alternatives: None,
+            simulation: None,
             api_key_input: String::new(),
         }
     }
 
-    /// Loads Categories
+    /// Fetches the category tree from EC3 on a new thread and stores it in the database,
+    /// [State::preload_categories] reloads it once stored
     pub fn fetch_categories(&mut self) {
-        if self.api_key.is_none() {
+        let Some(api_key) = self.api_key.clone() else {
             return;
-        }
-        let (categories_tx, categories_rx) = channel::<Node<Ec3Category>>();
+        };
+        let (categories_tx, categories_rx) = channel::<Result<usize, String>>();
 
         self.categories_rx = Some(categories_rx);
+        self.categories_error = None;
 
-        let api_key = self.api_key.to_owned();
         thread::spawn(move || {
-            match ec3api::Ec3api::new(&api_key.unwrap())
+            let result = match ec3api::Ec3api::new(&api_key)
                 .endpoint(ec3api::Endpoint::Categories)
                 .fetch_all()
             {
-                Ok(result) => {
+                Ok(Ec3Result::Categories(categories)) => {
                     println!("Finished fetching categories.");
-                    if let Ec3Result::Categories(categories) = result {
-                        if let Err(e) = categories_tx.send(categories) {
-                            println!("ERROR: {:?}", e);
-                        }
-                    }
-                }
-                Err(err) => {
-                    println!("ERROR: {:?}", err);
+                    material_db::write_category_tree(&categories).map_err(|e| e.to_string())
                 }
+                Ok(_) => Err("EC3 did not return categories".to_string()),
+                Err(err) => Err(format!("{:?}", err)),
+            };
+            if let Err(e) = categories_tx.send(result) {
+                println!("ERROR: {:?}", e);
             }
         });
     }
 
-    /// Search materials by the input field given in [self]
+    /// Loads the category tree from the database. On the first load it is fetched
+    /// from EC3 when it was never stored and an API key is available.
+    pub fn load_categories(&mut self) {
+        match material_db::load_category_tree() {
+            Ok(tree) => {
+                if tree.children.is_empty()
+                    && self.categories.is_none()
+                    && self.categories_rx.is_none()
+                {
+                    self.fetch_categories();
+                }
+                self.categories = Some(tree);
+            }
+            Err(e) => eprintln!("ERROR: loading categories: {}", e),
+        }
+    }
+
+    /// Search materials by the input fields given in [self]
     pub fn fetch_materials_from_input(&mut self) {
-        let input = self.fetch_input.clone();
+        let query = match self.search_query() {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("ERROR: invalid search query: {e}");
+                return;
+            }
+        };
         // deprecated
         // self.fetch_materials(&category);
         // self.load_by_category(&category);
-        self.search_by_name(&input);
+        self.search(&query);
         self.reset_filters();
     }
 
+    /// Builds a [material_db::SearchQuery] from the query typed in `fetch_input`
+    /// and the other search fields
+    pub fn search_query(&self) -> Result<material_db::SearchQuery, query::ParseError> {
+        Ok(query::parse(&self.fetch_input)?
+            .to_search()
+            .country(&self.country)
+            .manufacturer(&self.manufacturer)
+            .category(&self.search_category)
+            .gwp_range(self.gwp_min, self.gwp_max))
+    }
+
     /// Spawns thread to fetch materials
     #[deprecated]
     #[allow(dead_code)]
@@ -166,6 +301,7 @@ impl State {
                         .map(|mat| mat.category.name.clone())
                         .collect::<BTreeSet<_>>();
                     self.materials = filtered;
+                    self.snippets.clear();
                     self.materials_loaded = true;
                     false
                 }
@@ -176,34 +312,46 @@ impl State {
         }
     }
 
-    /// Tries receiving the categories from the Message receiver
-    /// and returns True while a message_receiver exists
-    /// and its trying to receive (aka when loading).
+    /// Tries receiving the outcome of [State::fetch_categories], loading the stored tree
+    /// when there is none yet, and returns True while the categories are being fetched.
     pub fn preload_categories(&mut self) -> bool {
-        if let Some(_categories) = &self.categories {
-            return false;
-        }
         if let Some(rx) = &self.categories_rx {
             match rx.try_recv() {
-                Ok(categories) => {
-                    println!("Received categories");
-                    self.categories = Some(categories);
-                    return false;
+                Ok(Ok(count)) => {
+                    println!("Received {count} categories");
+                    self.categories_rx = None;
+                    self.load_categories();
                 }
-                Err(_) => {
-                    return true;
+                Ok(Err(e)) => {
+                    eprintln!("ERROR: fetching categories: {e}");
+                    self.categories_rx = None;
+                    self.categories_error = Some(e);
                 }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => self.categories_rx = None,
             }
         }
-        false
+        if self.categories.is_none() {
+            self.load_categories();
+        }
+        self.categories_rx.is_some()
     }
 
     pub fn save_materials(&mut self) {
         let _ = material_db::write(&self.materials, &self.fetch_input)
             .map_err(|e| eprintln!("ERROR: {}", e));
+        self.refresh_category_counts();
     }
 
-    /// Loads a Vec<Material> from the db into state from a given category
+    /// Recounts the materials of every category after the database was written
+    pub fn refresh_category_counts(&mut self) {
+        match material_db::get_category_counts() {
+            Ok(counts) => self.category_counts = counts,
+            Err(e) => eprintln!("ERROR: {}", e),
+        }
+    }
+
+    /// Loads a Vec<Material> from the db into state from a given category and its subcategories
     pub fn load_by_category(&mut self, category: &str) {
         let result = material_db::load_category(category);
         match result {
@@ -213,6 +361,7 @@ impl State {
                     .map(|mat| mat.category.name.clone())
                     .collect::<BTreeSet<_>>();
                 self.materials = _materials;
+                self.snippets.clear();
                 self.materials_loaded = true;
                 self.reset_filters();
             }
@@ -230,26 +379,51 @@ impl State {
         self.sort_by = op;
     }
 
-    /// Performs a search on the database by the given input, loading internally the returned vector of materials
-    fn search_by_name(&mut self, input: &str) {
-        if input.is_empty() {
+    /// Performs a search on the database with the given query, loading internally the returned vector of materials
+    fn search(&mut self, query: &material_db::SearchQuery) {
+        if query.is_empty() {
             return;
         }
-        let result = material_db::query_materials(input);
+        let result = material_db::search_hits(query);
         match result {
-            Ok(_materials) => {
-                self.loaded_categories = _materials
+            Ok(hits) => {
+                self.loaded_categories = hits
                     .iter()
-                    .map(|mat| mat.category.name.clone())
+                    .map(|hit| hit.material.category.name.clone())
                     .collect::<BTreeSet<_>>();
 
-                self.materials = _materials;
+                self.snippets = hits
+                    .iter()
+                    .filter_map(|hit| Some((hit.material.id.clone(), hit.snippet.clone()?)))
+                    .collect();
+                self.materials = hits.into_iter().map(|hit| hit.material).collect();
                 self.materials_loaded = true;
             }
             Err(e) => eprintln!("ERROR: {}", e),
         }
     }
 
+    /// Selects a material, loading its category statistics and GWP history
+    pub fn select_material(&mut self, material: Ec3Material) {
+        self.category_stats = material_db::get_category_stats(&material.category.name).ok();
+        let country = Some(self.country.trim()).filter(|c| !c.is_empty());
+        self.selected_benchmark = benchmark::Distribution::load(&material.category.name, country);
+        self.selected_history =
+            material_db::get_material_history(&material.id).unwrap_or_else(|e| {
+                eprintln!("ERROR: {e}");
+                Vec::new()
+            });
+        self.selected = Some(material);
+    }
+
+    /// Loads the materials whose GWP changed by more than [State::change_threshold]
+    pub fn load_gwp_changes(&mut self) {
+        match material_db::gwp_changes(self.change_threshold, None) {
+            Ok(changes) => self.gwp_changes = Some(changes),
+            Err(e) => eprintln!("ERROR: {}", e),
+        }
+    }
+
     /// Removes selected material, category and filter_input
     fn reset_filters(&mut self) {
         self.selected = None;
@@ -259,16 +433,52 @@ impl State {
 
     pub fn get_category_info(&mut self) {
         let category = self.selected.as_ref().unwrap().category.name.clone();
-        self.selected_category = category;
-        match material_db::get_category(&self.selected_category) {
-            Ok(category) => {
-                // set category in state to display
-                dbg!(category);
+        self.select_category(&category);
+    }
+
+    /// Loads the description and statistics of a category for the category page
+    pub fn select_category(&mut self, name: &str) {
+        self.selected_category = name.to_string();
+        self.category_detail = match CategoryDetail::load(name) {
+            Ok(detail) => Some(detail),
+            Err(e) => {
+                eprintln!("ERROR: gettin category from db: {e}");
+                None
             }
-            Err(e) => eprintln!("ERROR: gettin category from db: {e}"),
         };
     }
 
+    /// Starts an empty project which has not been saved yet
+    pub fn new_project(&mut self) {
+        self.project = Some(project::Project::new());
+        self.project_path = None;
+        self.selected_component = None;
+        self.active_scenario = None;
+        self.alternatives = None;
+        self.simulation = None;
+    }
+
+    /// Loads a project from a file, replacing the current one
+    pub fn open_project(&mut self, path: PathBuf) -> Result<(), ProjectFileError> {
+        let project = project::Project::load(&path)?;
+        self.project = Some(project);
+        self.project_path = Some(path);
+        self.selected_component = None;
+        self.active_scenario = None;
+        self.alternatives = None;
+        self.simulation = None;
+        Ok(())
+    }
+
+    /// Saves the current project to the given path, which is then used by later saves
+    pub fn save_project_as(&mut self, path: PathBuf) -> Result<(), ProjectFileError> {
+        if let Some(project) = &self.project {
+            project.save(&path)?;
+            self.project_path = Some(path);
+        }
+        Ok(())
+    }
+
     pub fn get_selected(&self) -> &ec3api::models::Ec3Material {
         &self.selected.as_ref().unwrap()
     }
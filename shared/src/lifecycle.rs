@@ -0,0 +1,229 @@
+use std::{
+    fmt::Display,
+    ops::{Add, AddAssign},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Default reference study period in years
+pub const STUDY_PERIOD: f64 = 60.;
+
+/// Life-cycle modules of EN 15978 calculated for each component
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Module {
+    A1A3,
+    A4,
+    A5,
+    B4,
+    C1C4,
+    D,
+}
+
+impl Module {
+    pub const ALL: [Module; 6] = [
+        Module::A1A3,
+        Module::A4,
+        Module::A5,
+        Module::B4,
+        Module::C1C4,
+        Module::D,
+    ];
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Module::A1A3 => write!(f, "A1-A3"),
+            Module::A4 => write!(f, "A4"),
+            Module::A5 => write!(f, "A5"),
+            Module::B4 => write!(f, "B4"),
+            Module::C1C4 => write!(f, "C1-C4"),
+            Module::D => write!(f, "D"),
+        }
+    }
+}
+
+/// Assumptions of a component for the modules after the product stage.
+/// The default only accounts for A1-A3.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifeCycle {
+    /// Distance from factory to site in km
+    pub transport_km: f64,
+    /// KgCO2e emitted per declared unit and km transported
+    pub transport_factor: f64,
+    /// Share of the material wasted on site, 0.05 is 5%
+    pub waste_rate: f64,
+    /// Years until the component is replaced, 0 means it lasts the whole study period
+    pub service_life: f64,
+    /// KgCO2e per declared unit for deconstruction, transport, waste processing and disposal
+    pub end_of_life: f64,
+    /// KgCO2e per declared unit of loads and benefits beyond the system boundary, usually negative
+    pub beyond_boundary: f64,
+}
+
+/// GWP of a component or project split by [Module], in KgCO2e
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ModuleBreakdown {
+    pub a1_a3: f64,
+    pub a4: f64,
+    pub a5: f64,
+    pub b4: f64,
+    pub c1_c4: f64,
+    pub d: f64,
+}
+
+impl ModuleBreakdown {
+    /// Calculates the modules for `units` declared units of a material with `gwp` per declared unit.
+    ///
+    /// A5 covers producing, transporting and disposing of the wasted material.
+    /// B4 repeats A1-A5 and C1-C4 for every replacement needed within `study_period`.
+    pub fn calculate(units: f64, gwp: f64, life_cycle: &LifeCycle, study_period: f64) -> Self {
+        let a1_a3 = units * gwp;
+        let a4 = units * life_cycle.transport_km * life_cycle.transport_factor;
+        let c1_c4 = units * life_cycle.end_of_life;
+        let a5 = life_cycle.waste_rate * (a1_a3 + a4 + c1_c4);
+        let b4 = replacements(life_cycle.service_life, study_period) * (a1_a3 + a4 + a5 + c1_c4);
+        let d = units * life_cycle.beyond_boundary;
+        Self {
+            a1_a3,
+            a4,
+            a5,
+            b4,
+            c1_c4,
+            d,
+        }
+    }
+
+    /// Total of modules A to C. Module D is reported separately as EN 15978 asks.
+    pub fn total(&self) -> f64 {
+        self.a1_a3 + self.a4 + self.a5 + self.b4 + self.c1_c4
+    }
+
+    pub fn get(&self, module: Module) -> f64 {
+        match module {
+            Module::A1A3 => self.a1_a3,
+            Module::A4 => self.a4,
+            Module::A5 => self.a5,
+            Module::B4 => self.b4,
+            Module::C1C4 => self.c1_c4,
+            Module::D => self.d,
+        }
+    }
+}
+
+impl Add for ModuleBreakdown {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            a1_a3: self.a1_a3 + rhs.a1_a3,
+            a4: self.a4 + rhs.a4,
+            a5: self.a5 + rhs.a5,
+            b4: self.b4 + rhs.b4,
+            c1_c4: self.c1_c4 + rhs.c1_c4,
+            d: self.d + rhs.d,
+        }
+    }
+}
+
+impl AddAssign for ModuleBreakdown {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+/// Number of times a component with `service_life` is replaced within `study_period`
+pub fn replacements(service_life: f64, study_period: f64) -> f64 {
+    if service_life <= 0. || service_life >= study_period {
+        return 0.;
+    }
+    (study_period / service_life).ceil() - 1.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, what: &str) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{what}: {actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn replacements_within_the_study_period() {
+        let cases = [
+            // lasts the whole study period
+            (0., 0.),
+            (-5., 0.),
+            (60., 0.),
+            (80., 0.),
+            // divides the study period
+            (30., 1.),
+            (20., 2.),
+            (1., 59.),
+            // the last replacement is cut short by the end of the study period
+            (25., 2.),
+            (59., 1.),
+        ];
+        for (service_life, expected) in cases {
+            assert_eq!(
+                replacements(service_life, STUDY_PERIOD),
+                expected,
+                "service life {service_life}"
+            );
+        }
+    }
+
+    #[test]
+    fn modules_add_up() {
+        let life_cycle = LifeCycle {
+            transport_km: 50.,
+            transport_factor: 0.1,
+            waste_rate: 0.1,
+            service_life: 20.,
+            end_of_life: 5.,
+            beyond_boundary: -20.,
+        };
+        let modules = ModuleBreakdown::calculate(10., 100., &life_cycle, STUDY_PERIOD);
+        let expected = [
+            (Module::A1A3, 1000.),
+            (Module::A4, 50.),
+            // 10 % of A1-A3, A4 and C1-C4
+            (Module::A5, 110.),
+            // A1-A5 and C1-C4 twice
+            (Module::B4, 2420.),
+            (Module::C1C4, 50.),
+            (Module::D, -200.),
+        ];
+        for (module, value) in expected {
+            assert_close(modules.get(module), value, &module.to_string());
+        }
+        // D is left out of the total
+        assert_close(modules.total(), 3630., "total");
+
+        let product_only =
+            ModuleBreakdown::calculate(10., 100., &LifeCycle::default(), STUDY_PERIOD);
+        assert_eq!(
+            product_only,
+            ModuleBreakdown {
+                a1_a3: 1000.,
+                ..Default::default()
+            }
+        );
+
+        let mut sum = modules;
+        sum += product_only;
+        assert_eq!(sum, modules + product_only);
+        for module in Module::ALL {
+            assert_close(
+                sum.get(module),
+                modules.get(module) + product_only.get(module),
+                &module.to_string(),
+            );
+        }
+        assert_close(sum.total(), modules.total() + product_only.total(), "total");
+    }
+}
@@ -1,11 +1,24 @@
-use std::{collections::HashSet, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    str::FromStr,
+};
 
-use ec3api::models::{Category, DeclaredUnit, Ec3Category, Gwp};
-use rusqlite::{Connection, Result};
+use ec3api::models::{Category, DeclaredUnit, Ec3Category, Gwp, Node};
+use rusqlite::{types::Value, Connection, OptionalExtension, Result};
 
-use crate::{settings, Material};
+use crate::{
+    assembly::{Assembly, Layer, LayerAmount, LayerMaterial},
+    migrations::{self, MigrationError},
+    settings,
+    units::{ConversionFactors, QuantityUnit},
+    Material,
+};
+pub fn db_path() -> PathBuf {
+    settings::SettingsProvider::default_path().join("carbon.db")
+}
 pub fn connection() -> Result<Connection> {
-    Connection::open(settings::SettingsProvider::default_path().join("carbon.db"))
+    Connection::open(db_path())
 }
 pub fn write_category(category: &Ec3Category) -> Result<()> {
     let conn = connection()?;
@@ -28,17 +41,140 @@ pub fn write_category(category: &Ec3Category) -> Result<()> {
         }
     }
 }
-pub fn load_category(category: &str) -> Result<Vec<Material>> {
+/// A category of the EC3 category tree stored in the database, with its subcategories
+#[derive(Debug, Clone, Default)]
+pub struct CategoryNode {
+    pub id: String,
+    pub name: String,
+    pub declared_unit: DeclaredUnit,
+    pub children: Vec<CategoryNode>,
+}
+
+/// Replaces the stored category tree with the subcategories of `tree`, returning
+/// the number of categories written. The root itself is not stored.
+/// Categories which are no longer in the tree keep their materials but leave the tree.
+pub fn write_category_tree(tree: &Node<Ec3Category>) -> Result<usize> {
+    let mut conn = connection()?;
+    let tx = conn.transaction()?;
+    tx.execute("UPDATE categories SET position = NULL;", [])?;
+    let mut position = 0;
+    if let Some(children) = &tree.children {
+        write_nodes(&tx, children, None, &mut position)?;
+    }
+    tx.commit()?;
+    Ok(position)
+}
+
+/// Writes `nodes` and their subcategories in depth-first order, numbering them by `position`
+fn write_nodes(
+    conn: &Connection,
+    nodes: &[Node<Ec3Category>],
+    parent: Option<&str>,
+    position: &mut usize,
+) -> Result<()> {
+    // the display name and description are only known once materials of the category
+    // were written, the tree is authoritative for the parent
+    let mut stmt = conn.prepare_cached(
+        "
+INSERT INTO categories (id, name, display_name, description, parent_id, declared_value, declared_unit, position)
+VALUES (?1, ?2, ?2, '', ?3, ?4, ?5, ?6)
+ON CONFLICT(id) DO UPDATE SET
+    name = excluded.name,
+    parent_id = excluded.parent_id,
+    declared_value = excluded.declared_value,
+    declared_unit = excluded.declared_unit,
+    position = excluded.position;
+",
+    )?;
+    for node in nodes {
+        let category = &node.value;
+        stmt.execute(rusqlite::params![
+            category.id,
+            category.name,
+            parent,
+            category.declared_unit.value,
+            format!("{:?}", category.declared_unit.unit),
+            *position as i64,
+        ])?;
+        *position += 1;
+        if let Some(children) = &node.children {
+            write_nodes(conn, children, Some(&category.name), position)?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the stored category tree under a root without name, whose children are the
+/// top level categories. The root has no children when the tree was never stored.
+pub fn load_category_tree() -> Result<CategoryNode> {
     let conn = connection()?;
     let mut stmt = conn.prepare(
-        r"SELECT 
-            materials.id, materials.name, materials.description, materials.gwp, materials.gwp_unit, categories.name, categories.display_name, categories.id, categories.description, manufacturers.name, manufacturers.country, materials.declared_value, materials.declared_unit FROM materials
-        JOIN categories ON materials.category_id = categories.id
-        LEFT JOIN manufacturers ON materials.manufacturer_name = manufacturers.name
-        WHERE categories.name = (?1)
-        OR categories.parent_id = (?1);
         "
+SELECT id, name, parent_id, declared_value, declared_unit FROM categories
+WHERE position IS NOT NULL
+ORDER BY position;
+",
     )?;
+    let rows = stmt.query_map([], |row| {
+        let node = CategoryNode {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            declared_unit: DeclaredUnit {
+                value: row.get::<_, Option<f64>>(3)?.unwrap_or_default(),
+                unit: row
+                    .get::<_, Option<String>>(4)?
+                    .and_then(|unit| ec3api::models::Unit::from_str(&unit).ok())
+                    .unwrap_or(ec3api::models::Unit::Unknown),
+            },
+            children: Vec::new(),
+        };
+        Ok((node, row.get::<_, Option<String>>(2)?))
+    })?;
+    let rows = rows.collect::<Result<Vec<_>>>()?;
+
+    // group the categories by parent, those whose parent is not in the tree are top level
+    let names: HashSet<&str> = rows.iter().map(|(node, _)| node.name.as_str()).collect();
+    let mut children: HashMap<String, Vec<CategoryNode>> = HashMap::new();
+    for (node, parent) in &rows {
+        let parent = parent
+            .as_deref()
+            .filter(|p| names.contains(p))
+            .unwrap_or("");
+        children
+            .entry(parent.to_string())
+            .or_default()
+            .push(node.clone());
+    }
+    let mut root = CategoryNode::default();
+    attach_children(&mut root, &mut children);
+    Ok(root)
+}
+
+/// Moves the subcategories of `node` out of `children`, recursively.
+/// Each list is taken once, so a cycle of parents cannot recurse forever.
+fn attach_children(node: &mut CategoryNode, children: &mut HashMap<String, Vec<CategoryNode>>) {
+    node.children = children.remove(&node.name).unwrap_or_default();
+    for child in &mut node.children {
+        attach_children(child, children);
+    }
+}
+
+/// Selects the name of the category `?1` and of all its descendants, at any depth
+const SUBTREE: &str = r"WITH RECURSIVE subtree(name) AS (
+    SELECT (?1)
+    UNION SELECT categories.name FROM categories
+    JOIN subtree ON categories.parent_id = subtree.name
+)";
+
+/// Loads the materials of a category and of all its subcategories
+pub fn load_category(category: &str) -> Result<Vec<Material>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare(&format!(
+        "{SUBTREE}
+        SELECT {MATERIAL_COLUMNS} FROM materials
+        {JOIN_MATERIALS}
+        WHERE categories.name IN subtree;"
+    ))?;
 
     let mut materials = Vec::new();
     let rows = stmt.query_map([category], f)?;
@@ -49,6 +185,29 @@ pub fn load_category(category: &str) -> Result<Vec<Material>> {
     Ok(materials)
 }
 
+/// Number of materials of every category, including those of its subcategories at any depth
+pub fn get_category_counts() -> Result<HashMap<String, usize>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare(
+        "
+WITH RECURSIVE ancestry(name, ancestor) AS (
+    SELECT name, name FROM categories
+    UNION SELECT ancestry.name, categories.parent_id FROM ancestry
+    JOIN categories ON categories.name = ancestry.ancestor
+    WHERE categories.parent_id IS NOT NULL
+)
+SELECT ancestry.ancestor, COUNT(materials.id) FROM ancestry
+JOIN categories ON categories.name = ancestry.name
+JOIN materials ON materials.category_id = categories.id
+GROUP BY ancestry.ancestor;
+",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+    })?;
+    rows.collect()
+}
+
 fn h(row: &rusqlite::Row<'_>) -> Result<Category> {
     let id: String = row.get(0)?;
     let name: String = row.get(1)?;
@@ -114,52 +273,82 @@ fn f(row: &rusqlite::Row<'_>) -> Result<Material> {
         },
     })
 }
-pub fn migrate() -> Result<()> {
-    let conn = connection()?;
-    conn.execute(
-        r"
-        CREATE TABLE IF NOT EXISTS categories (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            display_name TEXT,
-            description TEXT,
-            parent_id TEXT,
-            declared_value REAL,
-            declared_unit TEXT    
-);",
-        (),
-    )?;
-    conn.execute(
-        r"CREATE TABLE IF NOT EXISTS manufacturers (
-            name TEXT PRIMARY KEY,
-            country TEXT
-        );",
-        (),
-    )?;
-    conn.execute(
-        r"CREATE TABLE if not exists materials (
-            id                  TEXT PRIMARY KEY,
-            name                TEXT NOT NULL,
-            description         TEXT,
-            category_id         TEXT NOT NULL,
-            gwp                 REAL,
-            gwp_unit            TEXT,
-            manufacturer_name   TEXT,
-            declared_value      REAL,
-            declared_unit       TEXT,           
-            FOREIGN KEY(category_id) 
-              REFERENCES categories (id),
-            FOREIGN KEY(manufacturer_name)
-              REFERENCES manufacturers (name)
-        );",
-        (), // empty list of parameters.
-    )?;
+/// Brings the database schema up to date, see [migrations]
+pub fn migrate() -> std::result::Result<(), MigrationError> {
+    let mut conn = connection()?;
+    migrations::run(&mut conn, &db_path())?;
+    // databases filled before the index existed are indexed once
+    let indexed: i64 =
+        conn.query_row("SELECT COUNT(*) FROM materials_fts", (), |row| row.get(0))?;
+    let materials: i64 = conn.query_row("SELECT COUNT(*) FROM materials", (), |row| row.get(0))?;
+    if indexed != materials {
+        rebuild_index(&conn)?;
+    }
 
     Ok(())
 }
-pub fn write(materials: &Vec<Material>, parent: &str) -> Result<()> {
-    let conn = connection()?;
 
+/// Selects the indexed columns of the materials in `materials_fts` column order.
+/// Every material is indexed, including those whose category is not stored,
+/// so the index and the materials table have the same number of rows.
+const SELECT_FTS: &str = r"SELECT materials.id, materials.name, materials.description,
+            COALESCE(manufacturers.name, ''),
+            COALESCE(categories.display_name || ' ' || categories.name || ' ' || COALESCE(categories.parent_id, ''), '')
+        FROM materials
+        LEFT JOIN categories ON materials.category_id = categories.id
+        LEFT JOIN manufacturers ON materials.manufacturer_name = manufacturers.name";
+
+/// Rebuilds the full-text index from the materials table
+fn rebuild_index(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM materials_fts;", ())?;
+    conn.execute(&format!("INSERT INTO materials_fts {SELECT_FTS};"), ())?;
+    Ok(())
+}
+
+/// Number of materials written by [write] per kind of change
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChangeReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl ChangeReport {
+    pub fn total(&self) -> usize {
+        self.inserted + self.updated + self.unchanged
+    }
+}
+
+impl std::ops::AddAssign for ChangeReport {
+    fn add_assign(&mut self, rhs: Self) {
+        self.inserted += rhs.inserted;
+        self.updated += rhs.updated;
+        self.unchanged += rhs.unchanged;
+    }
+}
+
+impl std::fmt::Display for ChangeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} inserted, {} updated, {} unchanged",
+            self.inserted, self.updated, self.unchanged
+        )
+    }
+}
+
+/// Inserts the materials with their categories and manufacturers, or updates the rows
+/// which already exist, in a single transaction.
+/// `parent` is the category the materials were fetched from, it is only set on new categories.
+pub fn write(materials: &[Material], parent: &str) -> Result<ChangeReport> {
+    let mut conn = connection()?;
+    let tx = conn.transaction()?;
+    let report = write_batch(&tx, materials, parent)?;
+    tx.commit()?;
+    Ok(report)
+}
+
+fn write_batch(conn: &Connection, materials: &[Material], parent: &str) -> Result<ChangeReport> {
     // create a set of categories
     let categories: Vec<&ec3api::models::Category> = materials
         .iter()
@@ -168,24 +357,28 @@ pub fn write(materials: &Vec<Material>, parent: &str) -> Result<()> {
         .into_iter()
         .collect();
 
-    let mut stmt = conn.prepare("INSERT INTO categories (id, name, display_name, description, parent_id) VALUES (?1, ?2, ?3, ?4, ?5);",
+    // a category keeps the parent of the category tree or else the one it was first
+    // fetched from, materials of subcategories are also returned when fetching their parents
+    let mut stmt = conn.prepare(
+        "
+INSERT INTO categories (id, name, display_name, description, parent_id)
+VALUES (?1, ?2, ?3, ?4, NULLIF(?5, ?2))
+ON CONFLICT(id) DO UPDATE SET
+    name = excluded.name,
+    display_name = excluded.display_name,
+    description = excluded.description,
+    parent_id = CASE WHEN categories.position IS NOT NULL THEN categories.parent_id
+        ELSE COALESCE(categories.parent_id, excluded.parent_id) END;
+",
     )?;
     for any in categories {
-        match stmt.execute([
+        stmt.execute([
             &any.id,
             &any.name,
             &any.display_name,
             &any.description,
             parent,
-        ]) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!(
-                    "WARNING: Not possible to write category {:?} to db. {e}",
-                    &any
-                );
-            }
-        };
+        ])?;
     }
 
     // create set of manufacurers
@@ -195,107 +388,602 @@ pub fn write(materials: &Vec<Material>, parent: &str) -> Result<()> {
         .collect::<HashSet<_>>()
         .into_iter()
         .collect();
-    // write
-    let mut stmt = conn.prepare("INSERT INTO manufacturers (name, country) VALUES (?1, ?2)")?;
+    // write, a missing country does not replace a known one
+    let mut stmt = conn.prepare(
+        "
+INSERT INTO manufacturers (name, country) VALUES (?1, ?2)
+ON CONFLICT(name) DO UPDATE SET
+    country = CASE WHEN excluded.country = 'Unknown' THEN manufacturers.country ELSE excluded.country END;
+",
+    )?;
 
     for manu in manufacturers {
-        let country = &manu.country.clone().unwrap_or("Unknown".to_string());
-        match stmt.execute([&manu.name, &country]) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!(
-                    "WARNING: Not possible to write manufacturer {:?} to db. {}",
-                    &manu, e
-                );
-            }
-        };
+        let country = manu.country.as_deref().unwrap_or("Unknown");
+        stmt.execute([&manu.name, country])?;
     }
     println!("Inserting materials");
 
+    let mut existing = conn.prepare(
+        "
+SELECT name, description, category_id, gwp, gwp_unit, manufacturer_name, declared_value, declared_unit
+FROM materials WHERE id = ?1;
+",
+    )?;
     let mut stmt = conn.prepare(
-            "INSERT INTO materials (id, name, description, category_id, gwp, gwp_unit, manufacturer_name, declared_value, declared_unit) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "
+INSERT INTO materials (id, name, description, category_id, gwp, gwp_unit, manufacturer_name, declared_value, declared_unit)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+ON CONFLICT(id) DO UPDATE SET
+    name = excluded.name,
+    description = excluded.description,
+    category_id = excluded.category_id,
+    gwp = excluded.gwp,
+    gwp_unit = excluded.gwp_unit,
+    manufacturer_name = excluded.manufacturer_name,
+    declared_value = excluded.declared_value,
+    declared_unit = excluded.declared_unit;
+",
+    )?;
+    // refresh the full-text index of the written materials
+    let mut delete = conn.prepare("DELETE FROM materials_fts WHERE material_id = ?1;")?;
+    let mut insert = conn.prepare(&format!(
+        "INSERT INTO materials_fts {SELECT_FTS} WHERE materials.id = ?1;"
+    ))?;
+
+    let mut history = conn.prepare(
+        "
+INSERT INTO material_history (material_id, gwp, declared_value, declared_unit, recorded_at)
+VALUES (?1, ?2, ?3, ?4, ?5);
+",
     )?;
+    let recorded_at = now();
+
+    let mut report = ChangeReport::default();
     for material in materials {
-        let _ = stmt
-            .execute([
+        let row = MaterialRow::from(material);
+        let previous = existing
+            .query_row([&material.id], |r| {
+                Ok(MaterialRow {
+                    name: r.get(0)?,
+                    description: r.get(1)?,
+                    category_id: r.get(2)?,
+                    gwp: r.get(3)?,
+                    gwp_unit: r.get(4)?,
+                    manufacturer_name: r.get(5)?,
+                    declared_value: r.get(6)?,
+                    declared_unit: r.get(7)?,
+                })
+            })
+            .optional()?;
+        let gwp_changed = match previous {
+            Some(previous) if previous == row => {
+                report.unchanged += 1;
+                continue;
+            }
+            Some(previous) => {
+                report.updated += 1;
+                !previous.same_gwp(&row)
+            }
+            None => {
+                report.inserted += 1;
+                true
+            }
+        };
+        stmt.execute(rusqlite::params![
+            &material.id,
+            row.name,
+            row.description,
+            row.category_id,
+            row.gwp,
+            row.gwp_unit,
+            row.manufacturer_name,
+            row.declared_value,
+            row.declared_unit,
+        ])?;
+        delete.execute([&material.id])?;
+        insert.execute([&material.id])?;
+        if gwp_changed {
+            history.execute(rusqlite::params![
                 &material.id,
-                &material.name,
-                &material.description,
-                &material.category.id,
-                &material.gwp.value.to_string(),
-                &format!("{:?}", &material.gwp.unit),
-                &material.manufacturer.name,
-                &material.declared_unit.value.to_string(),
-                &format!("{:?}", &material.declared_unit.unit),
-            ])
-            .map_err(|e| {
-                eprintln!(
-                    "WARNING: Not possible to write material {:?} into db. {e}",
-                    material.name
-                )
-            });
+                row.gwp,
+                row.declared_value,
+                row.declared_unit,
+                recorded_at
+            ])?;
+        }
     }
 
-    Ok(())
+    Ok(report)
 }
 
-/// Searches database for materials by name, category or parent category
-pub fn query_materials(input: &str) -> Result<Vec<ec3api::models::Ec3Material>> {
-    let conn = connection()?;
-    let mut query = String::from(input);
-    query.insert(0, '%');
-    query.push('%');
+/// Stored columns of a material, compared to skip writing unchanged rows
+#[derive(PartialEq)]
+struct MaterialRow {
+    name: String,
+    description: Option<String>,
+    category_id: String,
+    gwp: Option<f64>,
+    gwp_unit: Option<String>,
+    manufacturer_name: Option<String>,
+    declared_value: Option<f64>,
+    declared_unit: Option<String>,
+}
 
-    let mut stmt = conn.prepare(
-        r"SELECT 
-            materials.id, materials.name, materials.description, materials.gwp, materials.gwp_unit, categories.name, categories.display_name, categories.id, categories.description, manufacturers.name, manufacturers.country, materials.declared_value, materials.declared_unit FROM materials
-        JOIN categories ON materials.category_id = categories.id
-        LEFT JOIN manufacturers ON materials.manufacturer_name = manufacturers.name
-        WHERE materials.name LIKE (?1)
-        OR categories.parent_id LIKE (?1)
-        OR categories.name LIKE (?1)
-        LIMIT 200;
-        "
-    )?;
+impl MaterialRow {
+    /// True when the GWP and the declared unit are the same, the values kept in `material_history`
+    fn same_gwp(&self, other: &MaterialRow) -> bool {
+        self.gwp == other.gwp
+            && self.declared_value == other.declared_value
+            && self.declared_unit == other.declared_unit
+    }
+}
 
-    let mut materials = Vec::new();
-    let rows = stmt.query_map([&query], f)?;
+impl From<&Material> for MaterialRow {
+    fn from(material: &Material) -> Self {
+        Self {
+            name: material.name.clone(),
+            description: Some(material.description.clone()),
+            category_id: material.category.id.clone(),
+            gwp: Some(material.gwp.value),
+            gwp_unit: Some(format!("{:?}", &material.gwp.unit)),
+            manufacturer_name: Some(material.manufacturer.name.clone()),
+            declared_value: Some(material.declared_unit.value),
+            declared_unit: Some(format!("{:?}", &material.declared_unit.unit)),
+        }
+    }
+}
+
+/// Columns read by [f]
+const MATERIAL_COLUMNS: &str = r"materials.id, materials.name, materials.description, materials.gwp, materials.gwp_unit, categories.name, categories.display_name, categories.id, categories.description, manufacturers.name, manufacturers.country, materials.declared_value, materials.declared_unit";
+
+/// Joins categories and manufacturers to the materials
+const JOIN_MATERIALS: &str = r"JOIN categories ON materials.category_id = categories.id
+        LEFT JOIN manufacturers ON materials.manufacturer_name = manufacturers.name";
+
+/// Marks the start of a matched term in [SearchHit::snippet]
+pub const HIGHLIGHT_START: char = '\u{2}';
+/// Marks the end of a matched term in [SearchHit::snippet]
+pub const HIGHLIGHT_END: char = '\u{3}';
+
+/// Default number of results of a [SearchQuery]
+pub const SEARCH_LIMIT: usize = 200;
+
+/// Comparison of a numeric field against a value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+impl Comparison {
+    fn sql(&self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+            Comparison::Eq => "=",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Gwp,
+    Category,
+    Manufacturer,
+    /// Best full-text match first, by name when the query has no text
+    Relevance,
+}
+
+impl SortKey {
+    fn sql(&self) -> &'static str {
+        match self {
+            // name matches weigh most, then category, manufacturer and description
+            SortKey::Relevance => "bm25(materials_fts, 0, 10, 1, 2, 4)",
+            SortKey::Name => "materials.name",
+            SortKey::Gwp => "materials.gwp",
+            SortKey::Category => "categories.name",
+            SortKey::Manufacturer => "manufacturers.name",
+        }
+    }
+}
+
+/// Filters of a material search, compiled by [search] into a single parameterized query.
+/// Filters which are not set are left out of the query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchQuery {
+    /// Every text has to match the material name, description, manufacturer, category
+    /// or parent category, as a word or the start of a word
+    pub text: Vec<String>,
+    pub country: Option<String>,
+    pub manufacturer: Option<String>,
+    pub category: Option<String>,
+    pub gwp: Vec<(Comparison, f64)>,
+    /// Whether the GWP is compared and sorted per declared unit, e.g. per 1 m³ for a
+    /// material declared per 2 m³. Materials without a declared value are left out.
+    pub per_declared_unit: bool,
+    pub unit: Option<QuantityUnit>,
+    /// Sort key and whether the order is descending
+    pub sort: (SortKey, bool),
+    pub limit: usize,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchQuery {
+    pub fn new() -> Self {
+        Self {
+            text: Vec::new(),
+            country: None,
+            manufacturer: None,
+            category: None,
+            gwp: Vec::new(),
+            per_declared_unit: false,
+            unit: None,
+            sort: (SortKey::Relevance, false),
+            limit: SEARCH_LIMIT,
+        }
+    }
+
+    /// Matches the words of the material name, description, manufacturer or category
+    pub fn text(mut self, text: &str) -> Self {
+        self.text.extend(non_empty(text));
+        self
+    }
+
+    /// Matches the country of the manufacturer, ignoring case
+    pub fn country(mut self, country: &str) -> Self {
+        self.country = non_empty(country).or(self.country);
+        self
+    }
+
+    /// Matches part of the manufacturer name
+    pub fn manufacturer(mut self, manufacturer: &str) -> Self {
+        self.manufacturer = non_empty(manufacturer).or(self.manufacturer);
+        self
+    }
+
+    /// Matches materials of a category or any of its subcategories
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = non_empty(category).or(self.category);
+        self
+    }
+
+    /// Matches materials with a GWP within the given bounds, both inclusive
+    pub fn gwp_range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.gwp.extend(min.map(|min| (Comparison::Ge, min)));
+        self.gwp.extend(max.map(|max| (Comparison::Le, max)));
+        self
+    }
+
+    /// Matches materials with a GWP which compares to `value`
+    pub fn gwp(mut self, cmp: Comparison, value: f64) -> Self {
+        self.gwp.push((cmp, value));
+        self
+    }
+
+    /// Compares and sorts the GWP per declared unit instead of per declared value
+    pub fn per_declared_unit(mut self) -> Self {
+        self.per_declared_unit = true;
+        self
+    }
+
+    /// Matches materials declared in the given unit
+    pub fn unit(mut self, unit: QuantityUnit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    pub fn sort(mut self, key: SortKey, descending: bool) -> Self {
+        self.sort = (key, descending);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// True when no filter is set
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+            && self.country.is_none()
+            && self.manufacturer.is_none()
+            && self.category.is_none()
+            && self.gwp.is_empty()
+            && self.unit.is_none()
+    }
+
+    /// Builds the SQL statement and the values of its parameters
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut params: Vec<Value> = Vec::new();
+        let mut param = |value: Value| {
+            params.push(value);
+            format!("?{}", params.len())
+        };
+        let mut with = String::new();
+        let mut conditions: Vec<String> = Vec::new();
+
+        if let Some(category) = &self.category {
+            with = format!(
+                "WITH RECURSIVE subtree(name) AS (
+            SELECT {p}
+            UNION SELECT categories.name FROM categories
+            JOIN subtree ON categories.parent_id = subtree.name
+        )
+        ",
+                p = param(category.clone().into())
+            );
+            conditions.push("categories.name IN subtree".into());
+        }
+        let fts = self.match_expression();
+        if let Some(expression) = &fts {
+            let p = param(expression.clone().into());
+            conditions.push(format!("materials_fts MATCH {p}"));
+        }
+        if let Some(country) = &self.country {
+            let p = param(country.clone().into());
+            conditions.push(format!("manufacturers.country = {p} COLLATE NOCASE"));
+        }
+        if let Some(manufacturer) = &self.manufacturer {
+            let p = param(format!("%{manufacturer}%").into());
+            conditions.push(format!("manufacturers.name LIKE {p}"));
+        }
+        let gwp = match self.per_declared_unit {
+            true => {
+                conditions.push("materials.declared_value > 0".into());
+                "materials.gwp / materials.declared_value"
+            }
+            false => SortKey::Gwp.sql(),
+        };
+        for (cmp, value) in &self.gwp {
+            let p = param((*value).into());
+            conditions.push(format!("{gwp} {op} {p}", op = cmp.sql()));
+        }
+        if let Some(unit) = &self.unit {
+            // declared units are stored as written by ec3api, so any spelling of the unit matches
+            let aliases: Vec<String> = unit
+                .aliases()
+                .iter()
+                .map(|alias| param(alias.to_string().into()))
+                .collect();
+            conditions.push(format!(
+                "LOWER(materials.declared_unit) IN ({})",
+                aliases.join(", ")
+            ));
+        }
+
+        let mut sql = match fts {
+            Some(_) => format!(
+                "{with}SELECT {MATERIAL_COLUMNS},
+            snippet(materials_fts, -1, '{HIGHLIGHT_START}', '{HIGHLIGHT_END}', '…', 12)
+        FROM materials_fts
+        JOIN materials ON materials.id = materials_fts.material_id
+        {JOIN_MATERIALS}"
+            ),
+            None => format!(
+                "{with}SELECT {MATERIAL_COLUMNS}, NULL FROM materials\n        {JOIN_MATERIALS}"
+            ),
+        };
+        if !conditions.is_empty() {
+            sql.push_str("\n        WHERE ");
+            sql.push_str(&conditions.join("\n        AND "));
+        }
+        let (key, descending) = match self.sort {
+            (SortKey::Relevance, descending) if fts.is_none() => (SortKey::Name, descending),
+            sort => sort,
+        };
+        let p = param((self.limit as i64).into());
+        sql.push_str(&format!(
+            "\n        ORDER BY {key} {order}\n        LIMIT {p};",
+            key = match key {
+                SortKey::Gwp => gwp,
+                key => key.sql(),
+            },
+            order = if descending { "DESC" } else { "ASC" },
+        ));
+        (sql, params)
+    }
+
+    /// FTS5 expression matching every text as a phrase, its last word as a prefix
+    fn match_expression(&self) -> Option<String> {
+        let phrases: Vec<String> = self
+            .text
+            .iter()
+            // the tokenizer drops punctuation, a text without any word can not match
+            .filter(|text| text.chars().any(char::is_alphanumeric))
+            .map(|text| format!("\"{}\"*", text.replace('"', "\"\"")))
+            .collect();
+        (!phrases.is_empty()).then(|| phrases.join(" AND "))
+    }
+}
+
+fn non_empty(input: &str) -> Option<String> {
+    let input = input.trim();
+    (!input.is_empty()).then(|| input.to_string())
+}
+
+/// A material found by [search_hits]
+pub struct SearchHit {
+    pub material: Material,
+    /// Excerpt of the best matching column, matched terms are enclosed in
+    /// [HIGHLIGHT_START] and [HIGHLIGHT_END]. `None` when the query has no text.
+    pub snippet: Option<String>,
+}
+
+/// Searches the database for materials matching all the filters of the [SearchQuery],
+/// ranked by relevance unless the query sorts otherwise
+pub fn search_hits(query: &SearchQuery) -> Result<Vec<SearchHit>> {
+    let conn = connection()?;
+    let (sql, params) = query.to_sql();
+    let mut stmt = conn.prepare(&sql)?;
+
+    let mut hits = Vec::new();
+    let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+        Ok(SearchHit {
+            material: f(row)?,
+            snippet: row.get(13)?,
+        })
+    })?;
 
     for row in rows {
-        materials.push(row?);
+        hits.push(row?);
     }
-    Ok(materials)
+    Ok(hits)
+}
+
+/// Searches the database for materials matching all the filters of the [SearchQuery]
+pub fn search(query: &SearchQuery) -> Result<Vec<Material>> {
+    Ok(search_hits(query)?
+        .into_iter()
+        .map(|hit| hit.material)
+        .collect())
 }
 
-pub fn get_category_stats(category: &ec3api::models::Category) -> Result<f64> {
+/// Materials sharing any word with `text`, best matches first, for callers which rank them
+/// by their own measure of similarity. With a category only its subtree is searched.
+pub fn similar_materials(
+    text: &str,
+    category: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Material>> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(|word| format!("\"{word}\"*"))
+        .collect();
+    if words.is_empty() {
+        return Ok(Vec::new());
+    }
     let conn = connection()?;
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
+        "{SUBTREE}
+        SELECT {MATERIAL_COLUMNS} FROM materials_fts
+        JOIN materials ON materials.id = materials_fts.material_id
+        {JOIN_MATERIALS}
+        WHERE materials_fts MATCH ?2 AND (?1 IS NULL OR categories.name IN subtree)
+        ORDER BY bm25(materials_fts, 0, 10, 1, 2, 4)
+        LIMIT ?3;"
+    ))?;
+    let rows = stmt.query_map(
+        rusqlite::params![category, words.join(" OR "), limit as i64],
+        f,
+    )?;
+    rows.collect()
+}
+
+/// Searches database for materials by name, description, manufacturer or category
+pub fn query_materials(input: &str) -> Result<Vec<ec3api::models::Ec3Material>> {
+    search(&SearchQuery::new().text(input))
+}
+
+/// Distribution of the GWP of the materials of a category, in KgCO2e per declared unit
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CategoryStats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub p75: f64,
+    pub p90: f64,
+}
+
+/// SQL aggregate of the `p` percentile of `ranked`, interpolated linearly between the
+/// two closest values like numpy's default method
+fn percentile_sql(p: f64) -> String {
+    let h = format!("({p} * (n - 1))");
+    format!(
+        "SUM(CASE
+            WHEN i = CAST({h} AS INTEGER) THEN gwp * (1 - ({h} - CAST({h} AS INTEGER)))
+            WHEN i = CAST({h} AS INTEGER) + 1 THEN gwp * ({h} - CAST({h} AS INTEGER))
+            ELSE 0 END)"
+    )
+}
+
+/// Computes the [CategoryStats] of the materials of a category and its subcategories,
+/// all zero when it has none
+pub fn get_category_stats(category: &str) -> Result<CategoryStats> {
+    let conn = connection()?;
+    let sql = format!(
         "
-SELECT avg(gwp) from materials
-WHERE category_id = (?1);
+{SUBTREE}, ranked AS (
+    SELECT gwp,
+        ROW_NUMBER() OVER (ORDER BY gwp) - 1 AS i,
+        COUNT(*) OVER () AS n
+    FROM materials
+    JOIN categories ON categories.id = materials.category_id
+    WHERE categories.name IN subtree AND gwp IS NOT NULL
+)
+SELECT COUNT(*), MIN(gwp), MAX(gwp), AVG(gwp), {median}, {p10}, {p25}, {p75}, {p90}
+FROM ranked;
 ",
-    )?;
-    let mut response: f64 = 0.;
-    if let Some(row) = stmt.query_map([&category.id], |row| row.get(0))?.next() {
-        response = row?;
-    }
-    Ok(response)
+        median = percentile_sql(0.5),
+        p10 = percentile_sql(0.1),
+        p25 = percentile_sql(0.25),
+        p75 = percentile_sql(0.75),
+        p90 = percentile_sql(0.9),
+    );
+    conn.query_row(&sql, [category], |row| {
+        let value = |i| -> Result<f64> { Ok(row.get::<_, Option<f64>>(i)?.unwrap_or_default()) };
+        Ok(CategoryStats {
+            count: row.get::<_, i64>(0)? as usize,
+            min: value(1)?,
+            max: value(2)?,
+            mean: value(3)?,
+            median: value(4)?,
+            p10: value(5)?,
+            p25: value(6)?,
+            p75: value(7)?,
+            p90: value(8)?,
+        })
+    })
+}
+
+/// GWP values of the materials of a category and its subcategories, sorted ascending.
+/// With a country only materials of manufacturers from that country are returned.
+pub fn get_category_gwps(category: &str, country: Option<&str>) -> Result<Vec<f64>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare(&format!(
+        "{SUBTREE}
+SELECT gwp FROM materials
+JOIN categories ON categories.id = materials.category_id
+LEFT JOIN manufacturers ON materials.manufacturer_name = manufacturers.name
+WHERE categories.name IN subtree AND gwp IS NOT NULL
+    AND (?2 IS NULL OR manufacturers.country = ?2 COLLATE NOCASE)
+ORDER BY gwp;
+"
+    ))?;
+    let rows = stmt.query_map(rusqlite::params![category, country], |row| row.get(0))?;
+    rows.collect()
 }
 
+/// Average GWP of the materials of a category and its subcategories, zero when it has none
 pub fn get_category_avg(category: &str) -> Result<f64> {
     let conn = connection()?;
-    let mut stmt = conn.prepare(
-        "
+    let avg: Option<f64> = conn.query_row(
+        &format!(
+            "{SUBTREE}
 SELECT avg(gwp) from materials
 JOIN categories on categories.id = materials.category_id
-WHERE categories.name = (?1);
-",
+WHERE categories.name IN subtree;
+"
+        ),
+        [category],
+        |row| row.get(0),
     )?;
-    let mut response = 0.;
-    if let Some(row) = stmt.query_map([category], |row| row.get(0))?.next() {
-        response = row?;
-    }
-    Ok(response)
+    Ok(avg.unwrap_or_default())
 }
 
 pub fn get_category_unit(category: &str) -> Result<DeclaredUnit> {
@@ -306,9 +994,7 @@ SELECT declared_value, declared_unit FROM categories
 WHERE categories.name = (?1);
 ",
     )?;
-    let row = stmt.query_map([category], g)?.next().unwrap();
-    let unit: DeclaredUnit = row?;
-    Ok(unit)
+    stmt.query_row([category], g)
 }
 
 pub fn get_category(name: &str) -> Result<ec3api::models::Category> {
@@ -319,7 +1005,374 @@ SELECT id, name, display_name, description FROM categories
 WHERE categories.name = (?1);
 ",
     )?;
-    let row = stmt.query_map([name], h)?.next().unwrap();
-    let unit: ec3api::models::Category = row?;
-    Ok(unit)
+    stmt.query_row([name], h)
+}
+
+/// Gets the conversion factors saved for a material id, falling back to the ones of its category
+pub fn get_conversion_factors(
+    material_id: Option<&str>,
+    category: &str,
+) -> Result<ConversionFactors> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare(
+        "
+SELECT density, thickness FROM conversion_factors
+WHERE key = (?1);
+",
+    )?;
+    for key in material_id.into_iter().chain([category]) {
+        if let Some(row) = stmt
+            .query_map([key], |row| {
+                Ok(ConversionFactors {
+                    density: row.get(0)?,
+                    thickness: row.get(1)?,
+                })
+            })?
+            .next()
+        {
+            return row;
+        }
+    }
+    Ok(ConversionFactors::default())
+}
+
+/// Every saved set of conversion factors by material id or category name, to look up
+/// many materials with one query
+pub fn get_all_conversion_factors() -> Result<HashMap<String, ConversionFactors>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare("SELECT key, density, thickness FROM conversion_factors;")?;
+    let factors = stmt
+        .query_map((), |row| {
+            Ok((
+                row.get(0)?,
+                ConversionFactors {
+                    density: row.get(1)?,
+                    thickness: row.get(2)?,
+                },
+            ))
+        })?
+        .collect();
+    factors
+}
+
+/// Saves the conversion factors of a material id or category name
+pub fn set_conversion_factors(key: &str, factors: &ConversionFactors) -> Result<()> {
+    let conn = connection()?;
+    conn.execute(
+        "
+INSERT INTO conversion_factors (key, density, thickness) VALUES (?1, ?2, ?3)
+ON CONFLICT(key) DO UPDATE SET density = excluded.density, thickness = excluded.thickness;
+",
+        rusqlite::params![key, factors.density, factors.thickness],
+    )?;
+    Ok(())
+}
+
+/// Categories saved for the materials of IFC models, by material name
+pub fn get_ifc_mappings() -> Result<HashMap<String, String>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare("SELECT ifc_material, category FROM ifc_material_mappings;")?;
+    let mappings = stmt
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect();
+    mappings
+}
+
+/// Saves the category the material of IFC models with this name is added as
+pub fn set_ifc_mapping(ifc_material: &str, category: &str) -> Result<()> {
+    let conn = connection()?;
+    conn.execute(
+        "
+INSERT INTO ifc_material_mappings (ifc_material, category, updated_at) VALUES (?1, ?2, ?3)
+ON CONFLICT(ifc_material) DO UPDATE SET category = excluded.category, updated_at = excluded.updated_at;
+",
+        rusqlite::params![ifc_material, category, now()],
+    )?;
+    Ok(())
+}
+
+pub fn get_material(id: &str) -> Result<Material> {
+    let conn = connection()?;
+    conn.query_row(
+        &format!("SELECT {MATERIAL_COLUMNS} FROM materials\n        {JOIN_MATERIALS}\n        WHERE materials.id = ?1;"),
+        [id],
+        f,
+    )
+}
+
+/// Assemblies of the library with their layers, by name
+pub fn load_assemblies() -> Result<Vec<Assembly>> {
+    let conn = connection()?;
+    let mut stmt =
+        conn.prepare("SELECT id, name, description FROM assemblies ORDER BY name COLLATE NOCASE;")?;
+    let mut assemblies = stmt
+        .query_map((), |row| {
+            Ok(Assembly {
+                id: Some(row.get(0)?),
+                name: row.get(1)?,
+                description: row.get(2)?,
+                layers: Vec::new(),
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    let mut stmt = conn.prepare(
+        "
+SELECT material_id, name, category, thickness, quantity, unit FROM assembly_layers
+WHERE assembly_id = ?1
+ORDER BY position;
+",
+    )?;
+    for assembly in assemblies.iter_mut() {
+        let layers = stmt.query_map([assembly.id], |row| {
+            let material = match row.get::<_, Option<String>>(0)? {
+                Some(id) => LayerMaterial::Ec3 {
+                    id,
+                    name: row.get(1)?,
+                },
+                None => LayerMaterial::Generic(row.get(2)?),
+            };
+            let amount = match row.get::<_, Option<f64>>(3)? {
+                Some(mm) => LayerAmount::Thickness { mm },
+                None => LayerAmount::PerArea {
+                    quantity: row.get::<_, Option<f64>>(4)?.unwrap_or_default(),
+                    unit: row
+                        .get::<_, Option<String>>(5)?
+                        .and_then(|unit| unit.parse().ok())
+                        .unwrap_or(QuantityUnit::M2),
+                },
+            };
+            Ok(Layer { material, amount })
+        })?;
+        assembly.layers = layers.collect::<Result<_>>()?;
+    }
+    Ok(assemblies)
+}
+
+/// Saves an assembly with its layers, replacing the saved version, and returns its id
+pub fn save_assembly(assembly: &Assembly) -> Result<i64> {
+    let mut conn = connection()?;
+    let tx = conn.transaction()?;
+    let id = match assembly.id {
+        Some(id) => {
+            tx.execute(
+                "UPDATE assemblies SET name = ?2, description = ?3, updated_at = ?4 WHERE id = ?1;",
+                rusqlite::params![id, assembly.name, assembly.description, now()],
+            )?;
+            tx.execute("DELETE FROM assembly_layers WHERE assembly_id = ?1;", [id])?;
+            id
+        }
+        None => {
+            tx.execute(
+                "INSERT INTO assemblies (name, description, updated_at) VALUES (?1, ?2, ?3);",
+                rusqlite::params![assembly.name, assembly.description, now()],
+            )?;
+            tx.last_insert_rowid()
+        }
+    };
+    {
+        let mut stmt = tx.prepare(
+            "
+INSERT INTO assembly_layers (assembly_id, position, material_id, name, category, thickness, quantity, unit)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);
+",
+        )?;
+        for (position, layer) in assembly.layers.iter().enumerate() {
+            let (material_id, category) = match &layer.material {
+                LayerMaterial::Ec3 { id, .. } => (Some(id.as_str()), None),
+                LayerMaterial::Generic(category) => (None, Some(category.as_str())),
+            };
+            let (thickness, quantity, unit) = match layer.amount {
+                LayerAmount::Thickness { mm } => (Some(mm), None, None),
+                LayerAmount::PerArea { quantity, unit } => {
+                    (None, Some(quantity), Some(unit.as_str()))
+                }
+            };
+            stmt.execute(rusqlite::params![
+                id,
+                position,
+                material_id,
+                layer.material.name(),
+                category,
+                thickness,
+                quantity,
+                unit
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(id)
+}
+
+pub fn delete_assembly(id: i64) -> Result<()> {
+    let mut conn = connection()?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM assembly_layers WHERE assembly_id = ?1;", [id])?;
+    tx.execute("DELETE FROM assemblies WHERE id = ?1;", [id])?;
+    tx.commit()
+}
+
+/// Seconds since the unix epoch, the timestamps of `category_updates`
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Marks a category as being updated. A category left in this state was interrupted
+/// and is fetched again by the next update.
+pub fn begin_category_update(name: &str) -> Result<()> {
+    let conn = connection()?;
+    conn.execute(
+        "
+INSERT INTO category_updates (name, status, started_at) VALUES (?1, 'running', ?2)
+ON CONFLICT(name) DO UPDATE SET status = 'running', started_at = excluded.started_at, error = NULL;
+",
+        rusqlite::params![name, now()],
+    )?;
+    Ok(())
+}
+
+/// Records that all materials of a category were written
+pub fn complete_category_update(name: &str, materials: usize) -> Result<()> {
+    let conn = connection()?;
+    conn.execute(
+        "
+UPDATE category_updates SET status = 'done', refreshed_at = ?2, materials = ?3
+WHERE name = ?1;
+",
+        rusqlite::params![name, now(), materials as i64],
+    )?;
+    Ok(())
+}
+
+/// Records why a category could not be updated, keeping the time of its last refresh
+pub fn fail_category_update(name: &str, error: &str) -> Result<()> {
+    let conn = connection()?;
+    conn.execute(
+        "UPDATE category_updates SET status = 'failed', error = ?2 WHERE name = ?1;",
+        rusqlite::params![name, error],
+    )?;
+    Ok(())
+}
+
+/// Time of the last successful update of a category, `None` if it was never completed
+/// or its last update was interrupted, so that it is fetched again whatever its age
+pub fn category_refreshed_at(name: &str) -> Result<Option<i64>> {
+    let conn = connection()?;
+    let refreshed = conn
+        .query_row(
+            "SELECT refreshed_at FROM category_updates WHERE name = ?1 AND status != 'running';",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(refreshed.flatten())
+}
+
+/// Formats a timestamp of the database as `YYYY-MM-DD` (UTC)
+pub fn format_date(timestamp: i64) -> String {
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let days = timestamp.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// A GWP value of a material, as it was after a database update
+#[derive(Clone, Debug)]
+pub struct HistoryPoint {
+    pub gwp: f64,
+    pub declared_value: f64,
+    pub declared_unit: String,
+    pub recorded_at: i64,
+}
+
+/// Every distinct GWP and declared unit of a material, oldest first
+pub fn get_material_history(material_id: &str) -> Result<Vec<HistoryPoint>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare(
+        "
+SELECT gwp, declared_value, declared_unit, recorded_at FROM material_history
+WHERE material_id = ?1
+ORDER BY recorded_at, rowid;
+",
+    )?;
+    let rows = stmt.query_map([material_id], |row| {
+        Ok(HistoryPoint {
+            gwp: row.get::<_, Option<f64>>(0)?.unwrap_or_default(),
+            declared_value: row.get::<_, Option<f64>>(1)?.unwrap_or(1.),
+            declared_unit: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            recorded_at: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Change of the GWP of a material between its last two recorded values
+#[derive(Clone, Debug)]
+pub struct GwpChange {
+    pub material_id: String,
+    pub name: String,
+    pub category: String,
+    /// GWP per declared unit before the change
+    pub previous: f64,
+    /// GWP per declared unit after the change
+    pub current: f64,
+    pub declared_unit: String,
+    pub changed_at: i64,
+}
+
+impl GwpChange {
+    /// Relative change in percent, positive when the GWP increased
+    pub fn percent(&self) -> f64 {
+        (self.current - self.previous) / self.previous.abs() * 100.
+    }
+}
+
+/// Materials whose GWP per declared unit moved by more than `threshold` percent
+/// the last time it changed, largest changes first.
+/// Changes of the declared unit are left out since their values can not be compared.
+pub fn gwp_changes(threshold: f64, since: Option<i64>) -> Result<Vec<GwpChange>> {
+    let conn = connection()?;
+    let mut stmt = conn.prepare(
+        "
+WITH ranked AS (
+    SELECT material_id, gwp / declared_value AS gwp, declared_unit, recorded_at,
+        ROW_NUMBER() OVER (PARTITION BY material_id ORDER BY recorded_at DESC, rowid DESC) AS n
+    FROM material_history
+    WHERE declared_value > 0 AND gwp IS NOT NULL
+)
+SELECT cur.material_id, materials.name, categories.name, prev.gwp, cur.gwp, cur.declared_unit, cur.recorded_at
+FROM ranked cur
+JOIN ranked prev ON prev.material_id = cur.material_id AND prev.n = 2
+JOIN materials ON materials.id = cur.material_id
+JOIN categories ON materials.category_id = categories.id
+WHERE cur.n = 1
+    AND cur.declared_unit IS prev.declared_unit
+    AND prev.gwp <> 0
+    AND ABS(cur.gwp - prev.gwp) * 100 > ?1 * ABS(prev.gwp)
+    AND cur.recorded_at >= ?2
+ORDER BY ABS(cur.gwp - prev.gwp) / ABS(prev.gwp) DESC;
+",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![threshold, since.unwrap_or(0)], |row| {
+        Ok(GwpChange {
+            material_id: row.get(0)?,
+            name: row.get(1)?,
+            category: row.get(2)?,
+            previous: row.get(3)?,
+            current: row.get(4)?,
+            declared_unit: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+            changed_at: row.get(6)?,
+        })
+    })?;
+    rows.collect()
 }
@@ -0,0 +1,348 @@
+//! Versioned schema of `carbon.db`.
+//!
+//! The version of a database is stored in `PRAGMA user_version`. Each entry of [MIGRATIONS]
+//! upgrades the schema by one version and is applied once, in order, in its own transaction.
+//! Steps are only ever appended: a released step must not change, since databases which
+//! already applied it will not run it again.
+use std::{fmt::Display, path::Path};
+
+use rusqlite::{Connection, DatabaseName};
+
+pub struct Migration {
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Schema steps, the step at index `i` upgrades a database from version `i` to `i + 1`.
+/// The first steps use `IF NOT EXISTS` because databases created before versioning
+/// already have some of these tables at version 0.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "materials, categories and manufacturers",
+        sql: r"
+CREATE TABLE IF NOT EXISTS categories (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    display_name TEXT,
+    description TEXT,
+    parent_id TEXT,
+    declared_value REAL,
+    declared_unit TEXT
+);
+CREATE TABLE IF NOT EXISTS manufacturers (
+    name TEXT PRIMARY KEY,
+    country TEXT
+);
+CREATE TABLE IF NOT EXISTS materials (
+    id                  TEXT PRIMARY KEY,
+    name                TEXT NOT NULL,
+    description         TEXT,
+    category_id         TEXT NOT NULL,
+    gwp                 REAL,
+    gwp_unit            TEXT,
+    manufacturer_name   TEXT,
+    declared_value      REAL,
+    declared_unit       TEXT,
+    FOREIGN KEY(category_id)
+      REFERENCES categories (id),
+    FOREIGN KEY(manufacturer_name)
+      REFERENCES manufacturers (name)
+);",
+    },
+    Migration {
+        description: "conversion factors",
+        sql: r"
+CREATE TABLE IF NOT EXISTS conversion_factors (
+    key         TEXT PRIMARY KEY,
+    density     REAL,
+    thickness   REAL
+);",
+    },
+    Migration {
+        description: "full-text index of materials",
+        sql: r"
+CREATE VIRTUAL TABLE IF NOT EXISTS materials_fts USING fts5(
+    material_id UNINDEXED,
+    name,
+    description,
+    manufacturer,
+    category,
+    tokenize = 'unicode61 remove_diacritics 2'
+);",
+    },
+    Migration {
+        description: "progress of category updates",
+        sql: r"
+CREATE TABLE IF NOT EXISTS category_updates (
+    name            TEXT PRIMARY KEY,
+    status          TEXT NOT NULL,
+    started_at      INTEGER NOT NULL,
+    refreshed_at    INTEGER,
+    materials       INTEGER,
+    error           TEXT
+);",
+    },
+    Migration {
+        description: "history of GWP values",
+        sql: r"
+CREATE TABLE material_history (
+    material_id     TEXT NOT NULL,
+    gwp             REAL,
+    declared_value  REAL,
+    declared_unit   TEXT,
+    recorded_at     INTEGER NOT NULL
+);
+CREATE INDEX material_history_material ON material_history (material_id, recorded_at);
+INSERT INTO material_history
+    SELECT id, gwp, declared_value, declared_unit, CAST(strftime('%s', 'now') AS INTEGER)
+    FROM materials;",
+    },
+    Migration {
+        description: "category tree",
+        sql: r"
+ALTER TABLE categories ADD COLUMN position INTEGER;",
+    },
+    Migration {
+        description: "IFC material mappings",
+        sql: r"
+CREATE TABLE ifc_material_mappings (
+    ifc_material    TEXT PRIMARY KEY,
+    category        TEXT NOT NULL,
+    updated_at      INTEGER NOT NULL
+);",
+    },
+    Migration {
+        description: "assemblies",
+        sql: r"
+CREATE TABLE assemblies (
+    id          INTEGER PRIMARY KEY,
+    name        TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    updated_at  INTEGER NOT NULL
+);
+CREATE TABLE assembly_layers (
+    assembly_id INTEGER NOT NULL,
+    position    INTEGER NOT NULL,
+    material_id TEXT,
+    name        TEXT NOT NULL,
+    category    TEXT,
+    thickness   REAL,
+    quantity    REAL,
+    unit        TEXT,
+    PRIMARY KEY (assembly_id, position),
+    FOREIGN KEY(assembly_id)
+      REFERENCES assemblies (id)
+);",
+    },
+];
+
+/// Schema version written by this build
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Db(rusqlite::Error),
+    /// The database could not be copied before upgrading it, nothing was changed
+    Backup(rusqlite::Error),
+    /// The database was written by a newer version of the app
+    NewerVersion(u32),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Db(e) => write!(f, "{e}"),
+            MigrationError::Backup(e) => {
+                write!(f, "could not back up the database before upgrading it: {e}")
+            }
+            MigrationError::NewerVersion(v) => write!(
+                f,
+                "the database has version {v}, newer than the supported version {SCHEMA_VERSION}. Update the app to open it"
+            ),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Db(e)
+    }
+}
+
+pub fn user_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", (), |row| row.get(0))
+}
+
+/// Applies the missing migrations to the database at `path`.
+/// An existing database is backed up next to it before it is upgraded, through the open
+/// connection so the copy is consistent.
+pub fn run(conn: &mut Connection, path: &Path) -> Result<(), MigrationError> {
+    let version = user_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(MigrationError::NewerVersion(version));
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    let tables: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master", (), |row| row.get(0))?;
+    if tables > 0 {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{version}.bak"));
+        let backup = std::path::PathBuf::from(backup);
+        conn.backup(DatabaseName::Main, &backup, None)
+            .map_err(MigrationError::Backup)?;
+        println!("Backed up the database to {}", backup.display());
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let to = i as u32 + 1;
+        println!(
+            "Migrating the database to version {to}: {}",
+            migration.description
+        );
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.pragma_update(None, "user_version", to)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A database file in the temporary directory, removed with its backups when dropped
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "carbon-migrations-{}-{name}.db",
+                std::process::id()
+            ));
+            let db = Self(path);
+            db.remove();
+            db
+        }
+
+        fn backup(&self, version: u32) -> PathBuf {
+            let mut backup = self.0.as_os_str().to_owned();
+            backup.push(format!(".v{version}.bak"));
+            PathBuf::from(backup)
+        }
+
+        fn remove(&self) {
+            let _ = std::fs::remove_file(&self.0);
+            for version in 0..=SCHEMA_VERSION {
+                let _ = std::fs::remove_file(self.backup(version));
+            }
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    /// Applies the first `version` migrations, as an older build would have
+    fn migrate_to(conn: &Connection, version: u32) {
+        for migration in &MIGRATIONS[..version as usize] {
+            conn.execute_batch(migration.sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), (), |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    const INSERT_MATERIAL: &str = "
+INSERT INTO categories (id, name) VALUES ('c1', 'Concrete');
+INSERT INTO materials (id, name, category_id, gwp, declared_value, declared_unit)
+VALUES ('m1', 'Concrete mix', 'c1', 250, 1, 'M3');";
+
+    #[test]
+    fn creates_a_new_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn, Path::new(":memory:")).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(count(&conn, "assemblies"), 0);
+    }
+
+    #[test]
+    fn upgrades_an_unversioned_database() {
+        let db = TempDb::new("unversioned");
+        let mut conn = Connection::open(&db.0).unwrap();
+        // databases created before versioning have the first tables at version 0
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch(INSERT_MATERIAL).unwrap();
+
+        run(&mut conn, &db.0).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert_eq!(count(&conn, "materials"), 1);
+        assert_eq!(count(&conn, "material_history"), 1);
+        assert!(db.backup(0).exists());
+    }
+
+    #[test]
+    fn upgrades_from_an_intermediate_version() {
+        let db = TempDb::new("intermediate");
+        let mut conn = Connection::open(&db.0).unwrap();
+        migrate_to(&conn, 4);
+        conn.execute_batch(INSERT_MATERIAL).unwrap();
+
+        run(&mut conn, &db.0).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SCHEMA_VERSION);
+        // the history starts with the materials at the time of the upgrade
+        assert_eq!(count(&conn, "material_history"), 1);
+        assert_eq!(count(&conn, "ifc_material_mappings"), 0);
+
+        // running again changes nothing and takes no new backup
+        std::fs::remove_file(db.backup(4)).unwrap();
+        run(&mut conn, &db.0).unwrap();
+        assert!(!db.backup(4).exists());
+    }
+
+    #[test]
+    fn backs_up_the_database_before_upgrading() {
+        let db = TempDb::new("backup");
+        let mut conn = Connection::open(&db.0).unwrap();
+        migrate_to(&conn, 4);
+        conn.execute_batch(INSERT_MATERIAL).unwrap();
+
+        run(&mut conn, &db.0).unwrap();
+        let backup = Connection::open(db.backup(4)).unwrap();
+        assert_eq!(user_version(&backup).unwrap(), 4);
+        assert_eq!(count(&backup, "materials"), 1);
+        let tables: i64 = backup
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'material_history'",
+                (),
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 0);
+    }
+
+    #[test]
+    fn refuses_a_newer_database() {
+        let db = TempDb::new("newer");
+        let mut conn = Connection::open(&db.0).unwrap();
+        migrate_to(&conn, SCHEMA_VERSION);
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+
+        let result = run(&mut conn, &db.0);
+        assert!(matches!(result, Err(MigrationError::NewerVersion(v)) if v == SCHEMA_VERSION + 1));
+        assert_eq!(user_version(&conn).unwrap(), SCHEMA_VERSION + 1);
+        assert!(!db.backup(SCHEMA_VERSION + 1).exists());
+    }
+}
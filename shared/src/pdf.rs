@@ -0,0 +1,212 @@
+//! Minimal PDF writer for the project report: A4 pages with text in the standard
+//! Helvetica fonts, filled rectangles and lines. No fonts are embedded, so any PDF
+//! reader renders the file without network access or extra files.
+
+/// A4 in points
+pub const PAGE_WIDTH: f64 = 595.;
+pub const PAGE_HEIGHT: f64 = 842.;
+
+#[derive(Clone, Copy)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(&self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+/// RGB color with components from 0 to 1
+pub type Rgb = (f64, f64, f64);
+
+pub const BLACK: Rgb = (0., 0., 0.);
+
+/// A document being written page by page, coordinates start at the bottom left corner
+#[derive(Default)]
+pub struct Document {
+    pages: Vec<Vec<u8>>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new page, the following drawing goes to it
+    pub fn add_page(&mut self) {
+        self.pages.push(Vec::new());
+    }
+
+    fn content(&mut self) -> &mut Vec<u8> {
+        if self.pages.is_empty() {
+            self.add_page();
+        }
+        self.pages.last_mut().unwrap()
+    }
+
+    pub fn text(&mut self, x: f64, y: f64, size: f64, font: Font, color: Rgb, text: &str) {
+        let content = self.content();
+        content.extend(
+            format!(
+                "BT {:.3} {:.3} {:.3} rg /{} {size:.1} Tf {x:.2} {y:.2} Td (",
+                color.0,
+                color.1,
+                color.2,
+                font.resource()
+            )
+            .as_bytes(),
+        );
+        content.extend(encode(text));
+        content.extend(b") Tj ET\n");
+    }
+
+    /// Writes text ending at `x`
+    pub fn text_right(&mut self, x: f64, y: f64, size: f64, font: Font, color: Rgb, text: &str) {
+        self.text(x - text_width(text, size), y, size, font, color, text);
+    }
+
+    pub fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, color: Rgb) {
+        let (r, g, b) = color;
+        self.content().extend(
+            format!("{r:.3} {g:.3} {b:.3} rg {x:.2} {y:.2} {width:.2} {height:.2} re f\n")
+                .as_bytes(),
+        );
+    }
+
+    pub fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, width: f64, color: Rgb) {
+        let (r, g, b) = color;
+        self.content().extend(
+            format!("{r:.3} {g:.3} {b:.3} RG {width:.2} w {x1:.2} {y1:.2} m {x2:.2} {y2:.2} l S\n")
+                .as_bytes(),
+        );
+    }
+
+    /// Serializes the document with its cross-reference table
+    pub fn finish(self) -> Vec<u8> {
+        let mut pages = self.pages;
+        if pages.is_empty() {
+            pages.push(Vec::new());
+        }
+        // 1 catalog, 2 page tree, 3 and 4 fonts, then a page and its content per page
+        let page_ids: Vec<usize> = (0..pages.len()).map(|i| 5 + 2 * i).collect();
+        let mut objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids
+                    .iter()
+                    .map(|id| format!("{id} 0 R"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                pages.len()
+            )
+            .into_bytes(),
+            font_object("Helvetica"),
+            font_object("Helvetica-Bold"),
+        ];
+        for (content, id) in pages.into_iter().zip(page_ids) {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                     /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    id + 1
+                )
+                .into_bytes(),
+            );
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend(content);
+            stream.extend(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend(object);
+            out.extend(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        out.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+        out
+    }
+}
+
+fn font_object(name: &str) -> Vec<u8> {
+    format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>")
+        .into_bytes()
+}
+
+/// Encodes text as an escaped string in WinAnsiEncoding, characters it lacks become `?`
+fn encode(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let byte = match c {
+            '(' | ')' | '\\' => {
+                bytes.push(b'\\');
+                c as u8
+            }
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            _ => b'?',
+        };
+        bytes.push(byte);
+    }
+    bytes
+}
+
+/// Approximate width of text in Helvetica, good enough to align and truncate columns
+pub fn text_width(text: &str, size: f64) -> f64 {
+    let em: f64 = text
+        .chars()
+        .map(|c| match c {
+            'i' | 'j' | 'l' | 'I' | '.' | ',' | ':' | ';' | '\'' | '|' | '!' => 0.24,
+            ' ' | 'f' | 't' | 'r' | '(' | ')' | '-' | '/' => 0.32,
+            '0'..='9' => 0.556,
+            'm' | 'w' | 'M' | 'W' | '%' | '@' => 0.85,
+            'A'..='Z' => 0.68,
+            _ => 0.53,
+        })
+        .sum();
+    em * size
+}
+
+/// Shortens text with an ellipsis to fit in `width`
+pub fn truncate(text: &str, size: f64, width: f64) -> String {
+    if text_width(text, size) <= width {
+        return text.to_string();
+    }
+    let mut out: String = String::new();
+    for c in text.chars() {
+        if text_width(&out, size) + text_width(&format!("{c}…"), size) > width {
+            break;
+        }
+        out.push(c);
+    }
+    out.push('…');
+    out
+}
@@ -1,10 +1,42 @@
-use ec3api::models::{DeclaredUnit, Ec3Material, Gwp, GwpUnits};
+use std::collections::BTreeMap;
 
-use crate::material_db;
+use ec3api::models::{DeclaredUnit, Ec3Material, Gwp, GwpUnits, Manufacturer};
+
+use crate::{
+    assembly::ProjectAssembly,
+    benchmark::{Distribution, Thresholds},
+    lifecycle::{LifeCycle, ModuleBreakdown, STUDY_PERIOD},
+    material_db,
+    project_file::MaterialRecord,
+    recommend::Substitution,
+    scenario::ComponentOverride,
+    sensitivity::SensitivitySettings,
+    uncertainty::UncertaintySettings,
+    units::{self, ConversionError, ConversionFactors, QuantityUnit},
+};
 
 pub struct Project {
     pub components: Vec<Component>,
+    /// Assemblies with their area, see [crate::assembly]
+    pub assemblies: Vec<ProjectAssembly>,
+    /// Names of the design options compared to the baseline, see [crate::scenario]
+    pub scenarios: Vec<String>,
+    /// Materials replaced by lower-carbon alternatives, see [crate::recommend]
+    pub history: Vec<Substitution>,
+    /// Parameters of the Monte Carlo simulation, see [crate::uncertainty]
+    pub uncertainty: UncertaintySettings,
+    /// Variation of the inputs of the sensitivity analysis, see [crate::sensitivity]
+    pub sensitivity: SensitivitySettings,
+    /// Total of modules A to C in KgCO2e
     pub calculated_gwp: f64,
+    /// Totals of all components per life-cycle module
+    pub modules: ModuleBreakdown,
+    /// Reference study period in years, used for replacements (B4)
+    pub study_period: f64,
+    /// Percentile ranks used by [Component::cmp_to_benchmark]
+    pub thresholds: Thresholds,
+    /// Country the components are benchmarked in, `None` for all countries
+    pub benchmark_country: Option<String>,
 }
 impl Default for Project {
     fn default() -> Self {
@@ -15,11 +47,53 @@ impl Project {
     pub fn new() -> Self {
         Self {
             components: Vec::new(),
+            assemblies: Vec::new(),
+            scenarios: Vec::new(),
+            history: Vec::new(),
+            uncertainty: UncertaintySettings::default(),
+            sensitivity: SensitivitySettings::default(),
             calculated_gwp: 0.,
+            modules: ModuleBreakdown::default(),
+            study_period: STUDY_PERIOD,
+            thresholds: Thresholds::default(),
+            benchmark_country: None,
         }
     }
+    /// Calculates every component and assembly, and the project totals
     pub fn calculate(&mut self) {
-        self.calculated_gwp = self.components.iter().map(|c| c.calculated).sum::<f64>()
+        let mut modules = ModuleBreakdown::default();
+        for c in self.components.iter_mut() {
+            c.calculate(self.study_period);
+            modules += c.modules;
+        }
+        for a in self.assemblies.iter_mut() {
+            a.calculate(self.study_period);
+            modules += a.modules;
+        }
+        self.modules = modules;
+        self.calculated_gwp = modules.total();
+    }
+
+    /// Components followed by the layers of the assemblies, with the name of their assembly
+    pub fn all_components(&self) -> impl Iterator<Item = (Option<&str>, &Component)> {
+        let layers = self.assemblies.iter().flat_map(|a| {
+            a.layers
+                .iter()
+                .map(move |layer| (Some(a.name.as_str()), &layer.component))
+        });
+        self.components.iter().map(|c| (None, c)).chain(layers)
+    }
+
+    /// Reloads the distribution of every component for [Project::benchmark_country]
+    pub fn refresh_benchmarks(&mut self) {
+        let layers = self
+            .assemblies
+            .iter_mut()
+            .flat_map(|a| a.layers.iter_mut().map(|layer| &mut layer.component));
+        for c in self.components.iter_mut().chain(layers) {
+            c.benchmark =
+                Distribution::load(c.material.get_category(), self.benchmark_country.as_deref());
+        }
     }
 }
 
@@ -27,6 +101,14 @@ pub trait Material {
     fn get_unit(&self) -> &DeclaredUnit;
     fn get_gwp(&self) -> &Gwp;
     fn get_name(&self) -> &str;
+    /// EC3 id, generic materials have none
+    fn get_id(&self) -> Option<&str>;
+    /// Name of the category the material belongs to
+    fn get_category(&self) -> &str;
+    /// Generic materials have no manufacturer
+    fn get_manufacturer(&self) -> Option<&Manufacturer>;
+    /// Snapshot used to store the material in a project file
+    fn to_record(&self) -> MaterialRecord;
 }
 
 impl Material for Ec3Material {
@@ -41,9 +123,26 @@ impl Material for Ec3Material {
     fn get_name(&self) -> &str {
         &self.name
     }
+
+    fn get_id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+
+    fn get_category(&self) -> &str {
+        &self.category.name
+    }
+
+    fn get_manufacturer(&self) -> Option<&Manufacturer> {
+        Some(&self.manufacturer)
+    }
+
+    fn to_record(&self) -> MaterialRecord {
+        MaterialRecord::from_ec3(self)
+    }
 }
 pub struct UMaterial {
     pub name: String,
+    pub category: String,
     pub gwp: Gwp,
     pub unit: DeclaredUnit,
 }
@@ -56,6 +155,7 @@ impl UMaterial {
         name.push_str(" (Generic)");
         Self {
             name,
+            category: category.to_string(),
             gwp: Gwp {
                 value: cat_avg,
                 unit: GwpUnits::KgCO2e,
@@ -76,25 +176,126 @@ impl Material for UMaterial {
     fn get_name(&self) -> &str {
         &self.name
     }
+
+    fn get_id(&self) -> Option<&str> {
+        None
+    }
+
+    fn get_category(&self) -> &str {
+        &self.category
+    }
+
+    fn get_manufacturer(&self) -> Option<&Manufacturer> {
+        None
+    }
+
+    fn to_record(&self) -> MaterialRecord {
+        MaterialRecord::from_generic(self)
+    }
 }
 
 pub struct Component {
     pub quantity: f64,
+    /// Total of modules A to C in KgCO2e
     pub calculated: f64,
     pub material: Box<dyn Material>,
     pub category_avg: f64,
+    pub life_cycle: LifeCycle,
+    pub modules: ModuleBreakdown,
+    /// Unit the quantity is entered in, `None` means the declared unit of the material
+    pub unit: Option<QuantityUnit>,
+    pub factors: ConversionFactors,
+    /// Set by [Component::calculate] when the quantity can not be converted
+    pub conversion_error: Option<ConversionError>,
+    /// GWP distribution of the category, `None` when the category has no materials
+    pub benchmark: Option<Distribution>,
+    /// GlobalIds of the IFC elements the quantity was taken from, see [crate::ifc]
+    pub ifc_elements: Vec<String>,
+    /// Changes of the component by scenario name
+    pub overrides: BTreeMap<String, ComponentOverride>,
+    /// ± range of the GWP in percent, `None` for the default of the project
+    pub gwp_range: Option<f64>,
 }
 pub enum CmpResult {
     AlmostEqual,
     Greater,
     Smaller,
 }
+impl CmpResult {
+    /// Result as written in exports, a smaller GWP is better
+    pub fn label(&self) -> &'static str {
+        match self {
+            CmpResult::AlmostEqual => "typical",
+            CmpResult::Greater => "worse",
+            CmpResult::Smaller => "better",
+        }
+    }
+}
 impl Component {
-    pub fn calculate(&mut self) {
+    pub fn new(material: Box<dyn Material>, category_avg: f64) -> Self {
+        Self {
+            quantity: 0.,
+            calculated: 0.,
+            material,
+            category_avg,
+            life_cycle: LifeCycle::default(),
+            modules: ModuleBreakdown::default(),
+            unit: None,
+            factors: ConversionFactors::default(),
+            conversion_error: None,
+            benchmark: None,
+            ifc_elements: Vec::new(),
+            overrides: BTreeMap::new(),
+            gwp_range: None,
+        }
+    }
+
+    /// Quantity converted to the declared unit of the material
+    pub fn declared_quantity(&self) -> Result<f64, ConversionError> {
+        let Some(unit) = self.unit else {
+            return Ok(self.quantity);
+        };
+        let declared = QuantityUnit::from_declared(&self.material.get_unit().unit)?;
+        units::convert(self.quantity, unit, declared, &self.factors)
+    }
+
+    pub fn calculate(&mut self, study_period: f64) {
         // to normalize: since the GWP value inside of material is per declared_unit
         // If the declared_unit is 1.5 Kg means the calculated value is qt * gwp / 1.5 kg
-        self.calculated =
-            self.quantity * self.material.get_gwp().value / self.material.get_unit().value;
+        let units = match self.declared_quantity() {
+            Ok(quantity) => {
+                self.conversion_error = None;
+                quantity / self.material.get_unit().value
+            }
+            Err(e) => {
+                self.conversion_error = Some(e);
+                0.
+            }
+        };
+        self.modules = ModuleBreakdown::calculate(
+            units,
+            self.material.get_gwp().value,
+            &self.life_cycle,
+            study_period,
+        );
+        self.calculated = self.modules.total();
+    }
+
+    /// Percentage of the materials of the category with a lower GWP
+    pub fn percentile_rank(&self) -> Option<f64> {
+        let benchmark = self.benchmark.as_ref()?;
+        Some(benchmark.rank(self.material.get_gwp().value))
+    }
+
+    /// Compares the material to the distribution of its category,
+    /// or to the category average when the distribution is not known
+    pub fn cmp_to_benchmark(&self, thresholds: &Thresholds) -> CmpResult {
+        match self.percentile_rank() {
+            Some(rank) if rank < thresholds.better => CmpResult::Smaller,
+            Some(rank) if rank > thresholds.worse => CmpResult::Greater,
+            Some(_) => CmpResult::AlmostEqual,
+            None => self.cmp_to_average(),
+        }
     }
 
     pub fn cmp_to_average(&self) -> CmpResult {
@@ -108,24 +309,49 @@ impl Component {
     }
 }
 
+impl Component {
+    /// Component of an EC3 material with the conversion factors and distribution of `carbon.db`
+    pub fn from_ec3(selected: Ec3Material, category_avg: f64, country: Option<&str>) -> Self {
+        let factors =
+            material_db::get_conversion_factors(Some(&selected.id), &selected.category.name)
+                .unwrap_or_default();
+        let mut component = Component::new(Box::new(selected), category_avg);
+        component.factors = factors;
+        component.benchmark = Distribution::load(component.material.get_category(), country);
+        component
+    }
+
+    /// Component of the generic material of a category, see [UMaterial]
+    pub fn generic(cat: &str, country: Option<&str>) -> Self {
+        let material = UMaterial::get_from_db(cat);
+        let category_avg = material.gwp.value;
+        let mut component = Component::new(Box::new(material), category_avg);
+        component.factors = material_db::get_conversion_factors(None, cat).unwrap_or_default();
+        component.benchmark = Distribution::load(cat, country);
+        component
+    }
+}
+
 impl Project {
     pub fn add_component(&mut self, selected: Ec3Material, category_avg: f64) {
-        self.components.push(Component {
-            quantity: 0.,
-            calculated: 0.,
-            material: Box::new(selected),
-            category_avg,
-        });
+        let component =
+            Component::from_ec3(selected, category_avg, self.benchmark_country.as_deref());
+        self.components.push(component);
     }
 
     pub fn add_generic_comp(&mut self, cat: &str) {
-        let material = UMaterial::get_from_db(cat);
-        let category_avg = material.gwp.value;
-        self.components.push(Component {
-            quantity: 0.,
-            calculated: 0.,
-            material: Box::new(material),
-            category_avg,
-        });
+        let component = Component::generic(cat, self.benchmark_country.as_deref());
+        self.components.push(component);
+    }
+
+    /// Adds an assembly of the library with an area in m²
+    pub fn add_assembly(
+        &mut self,
+        assembly: &crate::assembly::Assembly,
+        area: f64,
+    ) -> rusqlite::Result<()> {
+        let assembly = assembly.instantiate(area, self.benchmark_country.as_deref())?;
+        self.assemblies.push(assembly);
+        Ok(())
     }
 }
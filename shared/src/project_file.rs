@@ -0,0 +1,649 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path, str::FromStr};
+
+use ec3api::models::{Category, DeclaredUnit, Ec3Material, Gwp, GwpUnits, Manufacturer, Unit};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assembly::{AssemblyLayer, LayerAmount, ProjectAssembly},
+    benchmark::{Distribution, Thresholds},
+    lifecycle::{LifeCycle, ModuleBreakdown, STUDY_PERIOD},
+    project::{Component, Project, UMaterial},
+    recommend::Substitution,
+    scenario::{ComponentOverride, Substitute},
+    sensitivity::SensitivitySettings,
+    uncertainty::UncertaintySettings,
+    units::{ConversionFactors, QuantityUnit},
+};
+
+/// Version of the project format written by this build.
+/// Files with a greater version are refused instead of being partially read.
+pub const FORMAT_VERSION: u32 = 10;
+
+/// File extension used by the Open/Save dialogs
+pub const EXTENSION: &str = "carbon";
+
+#[derive(Debug)]
+pub enum ProjectFileError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl Display for ProjectFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectFileError::Io(e) => write!(f, "could not access project file: {e}"),
+            ProjectFileError::Parse(e) => write!(f, "invalid project file: {e}"),
+            ProjectFileError::UnsupportedVersion(v) => write!(
+                f,
+                "project file version {v} is newer than the supported version {FORMAT_VERSION}"
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for ProjectFileError {
+    fn from(e: std::io::Error) -> Self {
+        ProjectFileError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ProjectFileError {
+    fn from(e: serde_json::Error) -> Self {
+        ProjectFileError::Parse(e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    version: u32,
+    /// Added in version 2
+    #[serde(default = "default_study_period")]
+    study_period: f64,
+    /// Added in version 4
+    #[serde(default)]
+    thresholds: Thresholds,
+    #[serde(default)]
+    benchmark_country: Option<String>,
+    components: Vec<ComponentRecord>,
+    /// Added in version 6
+    #[serde(default)]
+    assemblies: Vec<AssemblyRecord>,
+    /// Added in version 7
+    #[serde(default)]
+    scenarios: Vec<String>,
+    /// Added in version 8
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<Substitution>,
+    /// Added in version 9
+    #[serde(default)]
+    uncertainty: UncertaintySettings,
+    /// Added in version 10
+    #[serde(default)]
+    sensitivity: SensitivitySettings,
+}
+
+fn default_study_period() -> f64 {
+    STUDY_PERIOD
+}
+
+#[derive(Serialize, Deserialize)]
+struct ComponentRecord {
+    quantity: f64,
+    category_avg: f64,
+    material: MaterialRecord,
+    /// Added in version 2, version 1 files only account for A1-A3
+    #[serde(default)]
+    life_cycle: LifeCycle,
+    /// Added in version 3, older files use the declared unit of the material
+    #[serde(default)]
+    unit: Option<QuantityUnit>,
+    #[serde(default)]
+    factors: ConversionFactors,
+    /// Added in version 4, older files are benchmarked against the category average
+    #[serde(default)]
+    benchmark: Option<Distribution>,
+    /// Added in version 5
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ifc_elements: Vec<String>,
+    /// Added in version 7, by scenario name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    overrides: BTreeMap<String, OverrideRecord>,
+    /// Added in version 9
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gwp_range: Option<f64>,
+}
+
+impl ComponentRecord {
+    fn from_component(c: &Component) -> Self {
+        Self {
+            quantity: c.quantity,
+            category_avg: c.category_avg,
+            material: c.material.to_record(),
+            life_cycle: c.life_cycle.clone(),
+            unit: c.unit,
+            factors: c.factors.clone(),
+            benchmark: c.benchmark.clone(),
+            ifc_elements: c.ifc_elements.clone(),
+            overrides: c
+                .overrides
+                .iter()
+                .filter(|(_, o)| !o.is_empty())
+                .map(|(name, o)| (name.clone(), OverrideRecord::from_override(o)))
+                .collect(),
+            gwp_range: c.gwp_range,
+        }
+    }
+
+    fn into_component(self) -> Component {
+        let mut component = Component::new(self.material.into_material(), self.category_avg);
+        component.quantity = self.quantity;
+        component.life_cycle = self.life_cycle;
+        component.unit = self.unit;
+        component.factors = self.factors;
+        component.benchmark = self.benchmark;
+        component.ifc_elements = self.ifc_elements;
+        component.overrides = self
+            .overrides
+            .into_iter()
+            .map(|(name, o)| (name, o.into_override()))
+            .collect();
+        component.gwp_range = self.gwp_range;
+        component
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct OverrideRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<MaterialRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    factors: Option<ConversionFactors>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quantity: Option<f64>,
+}
+
+impl OverrideRecord {
+    fn from_override(o: &ComponentOverride) -> Self {
+        Self {
+            material: o.substitute.as_ref().map(|s| s.material.to_record()),
+            factors: o.substitute.as_ref().map(|s| s.factors.clone()),
+            quantity: o.quantity,
+        }
+    }
+
+    fn into_override(self) -> ComponentOverride {
+        let factors = self.factors.unwrap_or_default();
+        ComponentOverride {
+            substitute: self.material.map(|m| Substitute {
+                material: m.into_material(),
+                factors,
+            }),
+            quantity: self.quantity,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AssemblyRecord {
+    name: String,
+    area: f64,
+    layers: Vec<LayerRecord>,
+}
+
+/// The quantity of the component of a layer is recalculated from the area of its assembly
+#[derive(Serialize, Deserialize)]
+struct LayerRecord {
+    amount: LayerAmount,
+    component: ComponentRecord,
+}
+
+/// Snapshot of a material as it was when the project was saved.
+/// Values are stored in the file instead of being looked up in `carbon.db`,
+/// so a project reloads with the same numbers after the database is updated.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MaterialRecord {
+    Ec3 {
+        id: String,
+        name: String,
+        description: String,
+        category: CategoryRecord,
+        manufacturer: ManufacturerRecord,
+        gwp: ValueRecord,
+        declared_unit: ValueRecord,
+    },
+    Generic {
+        name: String,
+        /// Added in version 3, older files only have it in the name
+        #[serde(default)]
+        category: String,
+        gwp: ValueRecord,
+        declared_unit: ValueRecord,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CategoryRecord {
+    id: String,
+    name: String,
+    display_name: String,
+    description: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ManufacturerRecord {
+    name: String,
+    country: Option<String>,
+}
+
+/// A value with its unit, the unit is stored the same way as in `carbon.db`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ValueRecord {
+    value: f64,
+    unit: String,
+}
+
+impl ValueRecord {
+    fn from_gwp(gwp: &Gwp) -> Self {
+        Self {
+            value: gwp.value,
+            unit: format!("{:?}", gwp.unit),
+        }
+    }
+    fn from_unit(unit: &DeclaredUnit) -> Self {
+        Self {
+            value: unit.value,
+            unit: format!("{:?}", unit.unit),
+        }
+    }
+    fn to_gwp(&self) -> Gwp {
+        Gwp {
+            value: self.value,
+            unit: GwpUnits::from_str(&self.unit).unwrap_or(GwpUnits::Unknown),
+        }
+    }
+    fn to_unit(&self) -> DeclaredUnit {
+        DeclaredUnit {
+            value: self.value,
+            unit: Unit::from_str(&self.unit).unwrap_or(Unit::Unknown),
+        }
+    }
+}
+
+impl MaterialRecord {
+    pub fn from_ec3(material: &Ec3Material) -> Self {
+        MaterialRecord::Ec3 {
+            id: material.id.clone(),
+            name: material.name.clone(),
+            description: material.description.clone(),
+            category: CategoryRecord {
+                id: material.category.id.clone(),
+                name: material.category.name.clone(),
+                display_name: material.category.display_name.clone(),
+                description: material.category.description.clone(),
+            },
+            manufacturer: ManufacturerRecord {
+                name: material.manufacturer.name.clone(),
+                country: material.manufacturer.country.clone(),
+            },
+            gwp: ValueRecord::from_gwp(&material.gwp),
+            declared_unit: ValueRecord::from_unit(&material.declared_unit),
+        }
+    }
+
+    pub fn from_generic(material: &UMaterial) -> Self {
+        MaterialRecord::Generic {
+            name: material.name.clone(),
+            category: material.category.clone(),
+            gwp: ValueRecord::from_gwp(&material.gwp),
+            declared_unit: ValueRecord::from_unit(&material.unit),
+        }
+    }
+
+    fn into_material(self) -> Box<dyn crate::project::Material> {
+        match self {
+            MaterialRecord::Ec3 {
+                id,
+                name,
+                description,
+                category,
+                manufacturer,
+                gwp,
+                declared_unit,
+            } => Box::new(Ec3Material {
+                name,
+                gwp: gwp.to_gwp(),
+                image: None,
+                manufacturer: Manufacturer {
+                    name: manufacturer.name,
+                    country: manufacturer.country,
+                },
+                description,
+                category: Category {
+                    description: category.description,
+                    name: category.name,
+                    display_name: category.display_name,
+                    id: category.id,
+                },
+                id,
+                declared_unit: declared_unit.to_unit(),
+            }),
+            MaterialRecord::Generic {
+                name,
+                category,
+                gwp,
+                declared_unit,
+            } => Box::new(UMaterial {
+                category: match category.is_empty() {
+                    true => name.trim_end_matches(" (Generic)").to_string(),
+                    false => category,
+                },
+                name,
+                gwp: gwp.to_gwp(),
+                unit: declared_unit.to_unit(),
+            }),
+        }
+    }
+}
+
+impl Project {
+    /// Writes the project as JSON to the given path
+    pub fn save(&self, path: &Path) -> Result<(), ProjectFileError> {
+        let file = ProjectFile {
+            version: FORMAT_VERSION,
+            study_period: self.study_period,
+            thresholds: self.thresholds,
+            benchmark_country: self.benchmark_country.clone(),
+            components: self
+                .components
+                .iter()
+                .map(ComponentRecord::from_component)
+                .collect(),
+            assemblies: self
+                .assemblies
+                .iter()
+                .map(|a| AssemblyRecord {
+                    name: a.name.clone(),
+                    area: a.area,
+                    layers: a
+                        .layers
+                        .iter()
+                        .map(|layer| LayerRecord {
+                            amount: layer.amount,
+                            component: ComponentRecord::from_component(&layer.component),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            scenarios: self.scenarios.clone(),
+            history: self.history.clone(),
+            uncertainty: self.uncertainty,
+            sensitivity: self.sensitivity,
+        };
+        let json = serde_json::to_string_pretty(&file)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Reads a project saved with [Project::save] and recalculates it
+    pub fn load(path: &Path) -> Result<Project, ProjectFileError> {
+        let content = std::fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        let version = value
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .unwrap_or_default();
+        // versions beyond u32 are newer than any supported one
+        let version = u32::try_from(version).unwrap_or(u32::MAX);
+        if version > FORMAT_VERSION {
+            return Err(ProjectFileError::UnsupportedVersion(version));
+        }
+        let file: ProjectFile = serde_json::from_value(value)?;
+
+        let mut project = Project::new();
+        project.study_period = file.study_period;
+        project.thresholds = file.thresholds;
+        project.benchmark_country = file.benchmark_country;
+        project.components = file
+            .components
+            .into_iter()
+            .map(ComponentRecord::into_component)
+            .collect();
+        project.assemblies = file
+            .assemblies
+            .into_iter()
+            .map(|a| ProjectAssembly {
+                name: a.name,
+                area: a.area,
+                layers: a
+                    .layers
+                    .into_iter()
+                    .map(|layer| AssemblyLayer {
+                        amount: layer.amount,
+                        component: layer.component.into_component(),
+                    })
+                    .collect(),
+                calculated: 0.,
+                modules: ModuleBreakdown::default(),
+            })
+            .collect();
+        project.scenarios = file.scenarios;
+        project.history = file.history;
+        project.uncertainty = file.uncertainty;
+        project.sensitivity = file.sensitivity;
+        project.calculate();
+        Ok(project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!(
+                "carbon-project-{}-{name}.{EXTENSION}",
+                std::process::id()
+            )))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn ec3_material() -> Ec3Material {
+        Ec3Material {
+            name: "Low carbon mix".into(),
+            gwp: Gwp {
+                value: 240.,
+                unit: GwpUnits::KgCO2e,
+            },
+            image: None,
+            manufacturer: Manufacturer {
+                name: "Acme".into(),
+                country: Some("SE".into()),
+            },
+            description: "Ready mix".into(),
+            category: Category {
+                description: "Cast in place concrete".into(),
+                name: "ReadyMix".into(),
+                display_name: "Ready Mix".into(),
+                id: "c1".into(),
+            },
+            id: "m1".into(),
+            declared_unit: DeclaredUnit {
+                value: 1.,
+                unit: Unit::M3,
+            },
+        }
+    }
+
+    fn generic_material(gwp: f64) -> UMaterial {
+        UMaterial {
+            name: "Steel (Generic)".into(),
+            category: "Steel".into(),
+            gwp: Gwp {
+                value: gwp,
+                unit: GwpUnits::KgCO2e,
+            },
+            unit: DeclaredUnit {
+                value: 1000.,
+                unit: Unit::Kg,
+            },
+        }
+    }
+
+    fn write(file: &TempFile, json: &str) {
+        std::fs::write(&file.0, json).unwrap();
+    }
+
+    #[test]
+    fn saved_projects_load_the_same() {
+        let mut project = Project::new();
+        project.study_period = 50.;
+        project.thresholds = Thresholds {
+            better: 10.,
+            worse: 90.,
+        };
+        project.benchmark_country = Some("SE".into());
+        project.scenarios.push("Timber".into());
+
+        let mut concrete = Component::new(Box::new(ec3_material()), 300.);
+        concrete.quantity = 12.;
+        concrete.life_cycle = LifeCycle {
+            transport_km: 40.,
+            transport_factor: 0.1,
+            waste_rate: 0.05,
+            service_life: 30.,
+            end_of_life: 15.,
+            beyond_boundary: -5.,
+        };
+        concrete.benchmark = Distribution::from_sorted("ReadyMix", Some("SE"), &[200., 300.]);
+        concrete.ifc_elements.push("2O2Fr$t4X7Zf8NOew3FLOH".into());
+        concrete.gwp_range = Some(15.);
+        let mut steel = Component::new(Box::new(generic_material(1800.)), 1800.);
+        steel.quantity = 3.;
+        steel.overrides.insert(
+            "Timber".into(),
+            ComponentOverride {
+                substitute: Some(Substitute {
+                    material: Box::new(generic_material(900.)),
+                    factors: ConversionFactors::default(),
+                }),
+                quantity: Some(2.),
+            },
+        );
+        project.components = vec![concrete, steel];
+        project.calculate();
+
+        let file = TempFile::new("round-trip");
+        project.save(&file.0).unwrap();
+        let loaded = Project::load(&file.0).unwrap();
+
+        assert_eq!(loaded.study_period, 50.);
+        assert_eq!(loaded.thresholds, project.thresholds);
+        assert_eq!(loaded.benchmark_country.as_deref(), Some("SE"));
+        assert_eq!(loaded.scenarios, ["Timber"]);
+        assert_eq!(loaded.calculated_gwp, project.calculated_gwp);
+        assert_eq!(loaded.modules, project.modules);
+        assert_eq!(loaded.components.len(), 2);
+        for (loaded, saved) in loaded.components.iter().zip(&project.components) {
+            assert_eq!(loaded.material.get_name(), saved.material.get_name());
+            assert_eq!(loaded.material.get_id(), saved.material.get_id());
+            assert_eq!(
+                loaded.material.get_category(),
+                saved.material.get_category()
+            );
+            assert_eq!(
+                loaded.material.get_gwp().value,
+                saved.material.get_gwp().value
+            );
+            assert_eq!(
+                loaded.material.get_unit().value,
+                saved.material.get_unit().value
+            );
+            assert_eq!(loaded.quantity, saved.quantity);
+            assert_eq!(loaded.category_avg, saved.category_avg);
+            assert_eq!(loaded.life_cycle, saved.life_cycle);
+            assert_eq!(loaded.benchmark, saved.benchmark);
+            assert_eq!(loaded.ifc_elements, saved.ifc_elements);
+            assert_eq!(loaded.gwp_range, saved.gwp_range);
+            assert_eq!(loaded.calculated, saved.calculated);
+        }
+        let manufacturer = loaded.components[0].material.get_manufacturer().unwrap();
+        assert_eq!(manufacturer.country.as_deref(), Some("SE"));
+        assert!(loaded.components[1].material.get_manufacturer().is_none());
+        let timber = &loaded.components[1].overrides["Timber"];
+        assert_eq!(timber.quantity, Some(2.));
+        let substitute = timber.substitute.as_ref().unwrap();
+        assert_eq!(substitute.material.get_gwp().value, 900.);
+    }
+
+    #[test]
+    fn older_versions_load_with_defaults() {
+        let file = TempFile::new("version-1");
+        write(
+            &file,
+            r#"{
+                "version": 1,
+                "components": [{
+                    "quantity": 2.0,
+                    "category_avg": 100.0,
+                    "material": {
+                        "kind": "generic",
+                        "name": "Concrete (Generic)",
+                        "gwp": { "value": 100.0, "unit": "KgCO2e" },
+                        "declared_unit": { "value": 1.0, "unit": "M3" }
+                    }
+                }]
+            }"#,
+        );
+        let project = Project::load(&file.0).unwrap();
+
+        assert_eq!(project.study_period, STUDY_PERIOD);
+        assert_eq!(project.thresholds, Thresholds::default());
+        assert_eq!(project.benchmark_country, None);
+        assert!(project.assemblies.is_empty() && project.scenarios.is_empty());
+        assert!(project.history.is_empty());
+        assert_eq!(project.uncertainty, UncertaintySettings::default());
+        assert_eq!(project.sensitivity, SensitivitySettings::default());
+        let component = &project.components[0];
+        // the category of version 1 generic materials is taken from their name
+        assert_eq!(component.material.get_category(), "Concrete");
+        assert_eq!(component.life_cycle, LifeCycle::default());
+        assert_eq!(component.unit, None);
+        assert_eq!(component.benchmark, None);
+        assert!(component.overrides.is_empty());
+        assert_eq!(component.gwp_range, None);
+        assert_eq!(project.calculated_gwp, 200.);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let cases = [
+            (u64::from(FORMAT_VERSION) + 1, FORMAT_VERSION + 1),
+            (u64::from(u32::MAX) + 1, u32::MAX),
+        ];
+        for (version, expected) in cases {
+            let file = TempFile::new(&format!("version-{version}"));
+            write(
+                &file,
+                &format!(r#"{{ "version": {version}, "components": [] }}"#),
+            );
+            let result = Project::load(&file.0);
+            assert!(
+                matches!(result, Err(ProjectFileError::UnsupportedVersion(v)) if v == expected),
+                "version {version}"
+            );
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Search query language, e.g. `concrete gwp<250 country:DE manufacturer:"Holcim" unit:m3 sort:gwp`.
+//!
+//! Bare words and quoted phrases are looked up in the full-text index, `field:value` and
+//! `gwp<value` terms filter the results. The query is parsed into a [Query] and compiled
+//! into a [SearchQuery].
+use std::{fmt::Display, ops::Range, str::FromStr};
+
+use crate::{
+    material_db::{Comparison, SearchQuery, SortKey},
+    units::QuantityUnit,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Spanned<Term>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    /// Byte range of the term in the input
+    pub span: Range<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Term {
+    Text(String),
+    Country(String),
+    Manufacturer(String),
+    Category(String),
+    Unit(QuantityUnit),
+    Gwp(Comparison, f64),
+    Sort(SortKey, bool),
+    Limit(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Byte range of the offending token in the input
+    pub span: Range<usize>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+const FIELDS: &str = "country, manufacturer, category, unit, gwp, sort, limit";
+
+const SORT_KEYS: &str = "name, gwp, category, manufacturer, relevance";
+
+/// Parses a search query
+pub fn parse(input: &str) -> Result<Query, ParseError> {
+    let mut parser = Parser { input, pos: 0 };
+    let mut terms: Vec<Spanned<Term>> = Vec::new();
+    while let Some(term) = parser.term()? {
+        // a field may only be given once, except gwp which can be bounded on both sides
+        if let Some(previous) = terms.iter().find(|t| same_field(&t.value, &term.value)) {
+            return Err(ParseError {
+                message: format!(
+                    "`{}` is given twice, the first one is `{}`",
+                    &input[term.span.clone()],
+                    &input[previous.span.clone()]
+                ),
+                span: term.span,
+            });
+        }
+        terms.push(term);
+    }
+    Ok(Query { terms })
+}
+
+fn same_field(a: &Term, b: &Term) -> bool {
+    !matches!(a, Term::Text(_) | Term::Gwp(..))
+        && std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    /// Reads a word up to whitespace, a quote or one of `stop`
+    fn word(&mut self, stop: &[char]) -> Range<usize> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && c != '"' && !stop.contains(&c))
+        {
+            self.bump();
+        }
+        start..self.pos
+    }
+
+    /// Reads a quoted string, returning its content and the span including the quotes
+    fn quoted(&mut self) -> Result<(String, Range<usize>), ParseError> {
+        let start = self.pos;
+        self.bump();
+        let content_start = self.pos;
+        while let Some(c) = self.bump() {
+            if c == '"' {
+                let content = self.input[content_start..self.pos - 1].to_string();
+                return Ok((content, start..self.pos));
+            }
+        }
+        Err(ParseError {
+            message: "missing closing quote".into(),
+            span: start..self.pos,
+        })
+    }
+
+    /// Reads a quoted string or a bare word as the value of a field
+    fn value(&mut self) -> Result<(String, Range<usize>), ParseError> {
+        if self.peek() == Some('"') {
+            return self.quoted();
+        }
+        let span = self.word(&[]);
+        Ok((self.input[span.clone()].to_string(), span))
+    }
+
+    fn term(&mut self) -> Result<Option<Spanned<Term>>, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            None => return Ok(None),
+            Some('"') => {
+                let (text, span) = self.quoted()?;
+                return Ok(Some(Spanned {
+                    value: Term::Text(text),
+                    span,
+                }));
+            }
+            _ => {}
+        }
+
+        let name_span = self.word(&[':', '<', '>', '=']);
+        let name = &self.input[name_span.clone()];
+        if name.is_empty() {
+            let op_span = self.word(&[]);
+            return Err(ParseError {
+                message: format!("expected a field before `{}`", &self.input[op_span.clone()]),
+                span: op_span,
+            });
+        }
+
+        let cmp = match self.peek() {
+            Some(':') => None,
+            Some('<' | '>' | '=') => Some(self.comparison()),
+            _ => {
+                return Ok(Some(Spanned {
+                    value: Term::Text(name.to_string()),
+                    span: name_span,
+                }))
+            }
+        };
+        if cmp.is_none() {
+            self.bump();
+        }
+        let (value, value_span) = self.value()?;
+        let span = start..self.pos;
+        if value.is_empty() {
+            return Err(ParseError {
+                message: format!("missing value for `{name}`"),
+                span,
+            });
+        }
+
+        let field = name.to_lowercase();
+        let term = match (field.as_str(), cmp) {
+            ("gwp", Some(cmp)) => Term::Gwp(cmp, number(&value, value_span)?),
+            ("gwp", None) => {
+                return Err(ParseError {
+                    message: "compare gwp with <, <=, >, >= or =, e.g. gwp<250".into(),
+                    span,
+                })
+            }
+            ("country" | "manufacturer" | "category" | "unit" | "sort" | "limit", Some(_)) => {
+                return Err(ParseError {
+                    message: format!("`{name}` only supports `{name}:value`"),
+                    span,
+                })
+            }
+            ("country", None) => Term::Country(value),
+            ("manufacturer", None) => Term::Manufacturer(value),
+            ("category", None) => Term::Category(value),
+            ("unit", None) => {
+                Term::Unit(QuantityUnit::from_str(&value).map_err(|_| ParseError {
+                    message: format!("unknown unit `{value}`"),
+                    span: value_span,
+                })?)
+            }
+            ("sort", None) => sort(&value, value_span)?,
+            ("limit", None) => Term::Limit(value.parse().map_err(|_| ParseError {
+                message: format!("`{value}` is not a valid limit"),
+                span: value_span,
+            })?),
+            _ => {
+                return Err(ParseError {
+                    message: format!("unknown field `{name}`, expected one of {FIELDS}"),
+                    span: name_span,
+                })
+            }
+        };
+        Ok(Some(Spanned { value: term, span }))
+    }
+
+    fn comparison(&mut self) -> Comparison {
+        let first = self.bump();
+        let or_equal = self.peek() == Some('=') && first != Some('=');
+        if or_equal {
+            self.bump();
+        }
+        match (first, or_equal) {
+            (Some('<'), false) => Comparison::Lt,
+            (Some('<'), true) => Comparison::Le,
+            (Some('>'), false) => Comparison::Gt,
+            (Some('>'), true) => Comparison::Ge,
+            _ => Comparison::Eq,
+        }
+    }
+}
+
+fn number(value: &str, span: Range<usize>) -> Result<f64, ParseError> {
+    value.parse().map_err(|_| ParseError {
+        message: format!("`{value}` is not a number"),
+        span,
+    })
+}
+
+/// `sort:gwp` sorts ascending, `sort:-gwp` descending
+fn sort(value: &str, span: Range<usize>) -> Result<Term, ParseError> {
+    let (descending, key) = match value.strip_prefix('-') {
+        Some(key) => (true, key),
+        None => (false, value),
+    };
+    let key = match key.to_lowercase().as_str() {
+        "name" => SortKey::Name,
+        "gwp" => SortKey::Gwp,
+        "category" => SortKey::Category,
+        "manufacturer" => SortKey::Manufacturer,
+        "relevance" => SortKey::Relevance,
+        _ => {
+            return Err(ParseError {
+                message: format!("can not sort by `{key}`, expected one of {SORT_KEYS}"),
+                span,
+            })
+        }
+    };
+    Ok(Term::Sort(key, descending))
+}
+
+impl Query {
+    /// Compiles the query into a [SearchQuery] for [crate::material_db::search]
+    pub fn to_search(&self) -> SearchQuery {
+        self.terms
+            .iter()
+            .fold(SearchQuery::new(), |query, term| match &term.value {
+                Term::Text(text) => query.text(text),
+                Term::Country(country) => query.country(country),
+                Term::Manufacturer(manufacturer) => query.manufacturer(manufacturer),
+                Term::Category(category) => query.category(category),
+                Term::Unit(unit) => query.unit(*unit),
+                Term::Gwp(cmp, value) => query.gwp(*cmp, *value),
+                Term::Sort(key, descending) => query.sort(*key, *descending),
+                Term::Limit(limit) => query.limit(*limit),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(input: &str) -> Vec<Term> {
+        parse(input)
+            .unwrap()
+            .terms
+            .into_iter()
+            .map(|t| t.value)
+            .collect()
+    }
+
+    #[test]
+    fn parses_terms() {
+        let text = |s: &str| Term::Text(s.to_string());
+        let cases = [
+            ("", vec![]),
+            ("concrete", vec![text("concrete")]),
+            ("\"ready mix\" slab", vec![text("ready mix"), text("slab")]),
+            ("country:DE", vec![Term::Country("DE".into())]),
+            (
+                "manufacturer:\"Holcim AG\"",
+                vec![Term::Manufacturer("Holcim AG".into())],
+            ),
+            ("Category:Steel", vec![Term::Category("Steel".into())]),
+            ("unit:m³", vec![Term::Unit(QuantityUnit::M3)]),
+            (
+                "gwp>=100 gwp<250.5",
+                vec![
+                    Term::Gwp(Comparison::Ge, 100.),
+                    Term::Gwp(Comparison::Lt, 250.5),
+                ],
+            ),
+            ("gwp=3", vec![Term::Gwp(Comparison::Eq, 3.)]),
+            ("gwp<=3", vec![Term::Gwp(Comparison::Le, 3.)]),
+            ("gwp>3", vec![Term::Gwp(Comparison::Gt, 3.)]),
+            ("sort:-gwp", vec![Term::Sort(SortKey::Gwp, true)]),
+            ("sort:name", vec![Term::Sort(SortKey::Name, false)]),
+            ("limit:20", vec![Term::Limit(20)]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(values(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn spans_cover_the_terms() {
+        let input = "concrete  manufacturer:\"Holcim AG\" gwp<250";
+        let spans: Vec<&str> = parse(input)
+            .unwrap()
+            .terms
+            .into_iter()
+            .map(|t| &input[t.span])
+            .collect();
+        assert_eq!(spans, ["concrete", "manufacturer:\"Holcim AG\"", "gwp<250"]);
+    }
+
+    #[test]
+    fn reports_errors_at_the_offending_token() {
+        // input, offending token, start of the message
+        let cases = [
+            (
+                "concrete \"ready mix",
+                "\"ready mix",
+                "missing closing quote",
+            ),
+            ("<250", "<250", "expected a field"),
+            ("steel country:", "country:", "missing value"),
+            ("gwp:250", "gwp:250", "compare gwp"),
+            ("gwp<abc", "abc", "`abc` is not a number"),
+            ("country=DE", "country=DE", "`country` only supports"),
+            ("unit:ft", "ft", "unknown unit"),
+            ("sort:price", "price", "can not sort by `price`"),
+            ("limit:-1", "-1", "`-1` is not a valid limit"),
+            ("colour:red", "colour", "unknown field `colour`"),
+            (
+                "country:DE country:FR",
+                "country:FR",
+                "`country:FR` is given twice",
+            ),
+        ];
+        for (input, token, message) in cases {
+            let error = parse(input).unwrap_err();
+            assert_eq!(&input[error.span.clone()], token, "{input}");
+            assert!(
+                error.message.starts_with(message),
+                "{input}: {}",
+                error.message
+            );
+        }
+    }
+}
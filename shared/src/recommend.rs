@@ -0,0 +1,190 @@
+//! Lower-carbon alternatives of a component: materials of the same category subtree and
+//! declared unit with a lower GWP, ranked by the emissions they save for its quantity.
+use ec3api::models::Ec3Material;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    benchmark::Distribution,
+    material_db::{self, Comparison, SearchQuery, SortKey},
+    project::{Component, Project},
+    units::{ConversionFactors, QuantityUnit},
+};
+
+/// Number of alternatives listed for a component
+pub const LIMIT: usize = 10;
+
+/// Materials with a lower GWP per declared unit which are compared, the lowest first
+const CANDIDATES: usize = 200;
+
+/// A material which could replace the one of a component
+pub struct Recommendation {
+    pub material: Ec3Material,
+    /// Conversion factors of `carbon.db`, the thickness of the component takes precedence
+    pub factors: ConversionFactors,
+    /// Total of modules A to C in KgCO2e with the quantity and life cycle of the component
+    pub total: f64,
+    /// Reduction of the total of the component in KgCO2e
+    pub savings: f64,
+}
+
+impl Recommendation {
+    /// Savings in percent of the total of the component
+    pub fn percent(&self) -> f64 {
+        match self.savings + self.total {
+            current if current > 0. => self.savings / current * 100.,
+            _ => 0.,
+        }
+    }
+}
+
+/// Materials which lower the total of the component, the greatest savings first.
+/// Limited to the manufacturers of a country when one is given.
+pub fn recommend(
+    component: &Component,
+    country: Option<&str>,
+    study_period: f64,
+) -> rusqlite::Result<Vec<Recommendation>> {
+    let material = component.material.as_ref();
+    let declared = material.get_unit();
+    let Ok(unit) = QuantityUnit::from_declared(&declared.unit) else {
+        return Ok(Vec::new());
+    };
+    if declared.value <= 0. {
+        return Ok(Vec::new());
+    }
+    // materials may be declared per a different amount of the same unit
+    let mut query = SearchQuery::new()
+        .category(material.get_category())
+        .unit(unit)
+        .per_declared_unit()
+        .gwp(Comparison::Lt, material.get_gwp().value / declared.value)
+        .sort(SortKey::Gwp, false)
+        .limit(CANDIDATES);
+    if let Some(country) = country {
+        query = query.country(country);
+    }
+    let saved_factors = material_db::get_all_conversion_factors()?;
+    let mut recommendations = Vec::new();
+    for candidate in material_db::search(&query)? {
+        if Some(candidate.id.as_str()) == material.get_id() {
+            continue;
+        }
+        // as in [material_db::get_conversion_factors], the material before its category
+        let saved = saved_factors
+            .get(&candidate.id)
+            .or_else(|| saved_factors.get(&candidate.category.name))
+            .cloned()
+            .unwrap_or_default();
+        // the thickness belongs to the component, the density to the material
+        let factors = ConversionFactors {
+            density: saved.density.or(component.factors.density),
+            thickness: component.factors.thickness.or(saved.thickness),
+        };
+        let mut alternative = Component::new(Box::new(candidate.clone()), component.category_avg);
+        alternative.quantity = component.quantity;
+        alternative.unit = component.unit;
+        alternative.factors = factors;
+        alternative.life_cycle = component.life_cycle.clone();
+        alternative.calculate(study_period);
+        let savings = component.calculated - alternative.calculated;
+        if alternative.conversion_error.is_some() || savings <= 0. {
+            continue;
+        }
+        recommendations.push(Recommendation {
+            total: alternative.calculated,
+            savings,
+            factors: alternative.factors,
+            material: candidate,
+        });
+    }
+    recommendations.sort_by(|a, b| b.savings.total_cmp(&a.savings));
+    recommendations.truncate(LIMIT);
+    Ok(recommendations)
+}
+
+/// Alternatives listed for a component of the project
+pub struct Alternatives {
+    /// Index of the component in [Project::components]
+    pub component: usize,
+    /// Id of the material of the component, `None` for generic materials
+    pub material: Option<String>,
+    /// Total of the component the savings were computed for
+    pub calculated: f64,
+    /// Country of the manufacturer of the component, or the benchmark country of the project
+    pub country: Option<String>,
+    /// Whether the alternatives are limited to [Alternatives::country]
+    pub same_country: bool,
+    pub recommendations: Vec<Recommendation>,
+}
+
+impl Alternatives {
+    /// Lists the alternatives of a component, none if the database can not be read
+    pub fn load(project: &Project, component: usize, same_country: bool) -> Self {
+        let c = &project.components[component];
+        let country = c
+            .material
+            .get_manufacturer()
+            .and_then(|m| m.country.clone())
+            .filter(|country| !country.is_empty())
+            .or_else(|| project.benchmark_country.clone());
+        let scope = country.as_deref().filter(|_| same_country);
+        let recommendations = recommend(c, scope, project.study_period).unwrap_or_else(|e| {
+            eprintln!("ERROR: could not load the alternatives: {e}");
+            Vec::new()
+        });
+        Self {
+            component,
+            material: c.material.get_id().map(str::to_string),
+            calculated: c.calculated,
+            recommendations,
+            country,
+            same_country,
+        }
+    }
+
+    /// Whether the alternatives were listed for the material of this component, the
+    /// savings are out of date when its total is no longer [Alternatives::calculated]
+    pub fn is_for(&self, project: &Project, component: usize) -> bool {
+        self.component == component
+            && self.material.as_deref() == project.components[component].material.get_id()
+    }
+}
+
+/// A material replaced by one of its alternatives
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Substitution {
+    /// Unix timestamp
+    pub at: i64,
+    pub from: String,
+    pub from_id: Option<String>,
+    pub to: String,
+    pub to_id: String,
+    /// KgCO2e saved at the time of the substitution
+    pub savings: f64,
+}
+
+impl Project {
+    /// Replaces the material of a component, keeping its quantity, and records it in
+    /// [Project::history]
+    pub fn swap_material(&mut self, index: usize, recommendation: Recommendation) {
+        let Some(component) = self.components.get_mut(index) else {
+            return;
+        };
+        let material = recommendation.material;
+        self.history.push(Substitution {
+            at: material_db::now(),
+            from: component.material.get_name().to_string(),
+            from_id: component.material.get_id().map(str::to_string),
+            to: material.name.clone(),
+            to_id: material.id.clone(),
+            savings: recommendation.savings,
+        });
+        component.category_avg = material_db::get_category_avg(&material.category.name)
+            .unwrap_or(component.category_avg);
+        component.benchmark =
+            Distribution::load(&material.category.name, self.benchmark_country.as_deref());
+        component.factors = recommendation.factors;
+        component.material = Box::new(material);
+        self.calculate();
+    }
+}
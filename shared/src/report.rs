@@ -0,0 +1,912 @@
+//! Printable carbon report of a [Project], as a self-contained HTML page or a PDF.
+//!
+//! Both formats have the same sections: a title page, the assumptions, the components and
+//! assemblies, a breakdown per category, a tornado chart of the sensitivity analysis,
+//! benchmarks against the category statistics of `carbon.db` and an appendix with the EC3 id
+//! of every material and where its quantity comes from. Layers of assemblies are listed as
+//! components named after their assembly.
+//! Neither needs a browser or network access to render: the HTML inlines its style and
+//! chart, the PDF only uses standard fonts.
+use std::collections::HashMap;
+
+use crate::{
+    benchmark::Thresholds,
+    export::{Report, ReportRow},
+    lifecycle::{LifeCycle, Module},
+    material_db::{self, CategoryStats},
+    pdf::{self, Document, Font, Rgb, BLACK, PAGE_HEIGHT, PAGE_WIDTH},
+    project::Project,
+    sensitivity::{GwpVariation, TORNADO_ROWS},
+};
+
+/// A component compared to the statistics of its category
+pub struct BenchmarkRow {
+    pub material: String,
+    pub category: String,
+    /// KgCO2e per declared unit
+    pub gwp: f64,
+    /// Statistics of the category in the database, `None` when it has no materials
+    pub stats: Option<CategoryStats>,
+    pub percentile: Option<f64>,
+    /// e.g. `better than 80% of ReadyMix in DE (42 materials)`
+    pub description: Option<String>,
+}
+
+/// Content of the report, rendered by [CarbonReport::to_html] and [CarbonReport::to_pdf]
+pub struct CarbonReport {
+    pub title: String,
+    /// Date the report was generated, `YYYY-MM-DD`
+    pub date: String,
+    pub report: Report,
+    /// Assumptions of every component, in the order of [Report::components]
+    pub life_cycles: Vec<LifeCycle>,
+    pub thresholds: Thresholds,
+    /// Total A-C in KgCO2e per category, largest first
+    pub categories: Vec<(String, f64)>,
+    pub benchmarks: Vec<BenchmarkRow>,
+}
+
+impl CarbonReport {
+    /// Collects the content of a project, which must have been calculated
+    pub fn new(project: &Project, title: &str) -> Self {
+        let report = Report::new(project);
+
+        let mut totals: HashMap<&str, f64> = HashMap::new();
+        for row in &report.components {
+            *totals.entry(&row.category).or_default() += row.total;
+        }
+        let mut categories: Vec<(String, f64)> = totals
+            .into_iter()
+            .map(|(category, total)| (category.to_string(), total))
+            .collect();
+        categories.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let benchmarks = project
+            .all_components()
+            .zip(&report.components)
+            .map(|((_, component), row)| BenchmarkRow {
+                material: row.label(),
+                category: row.category.clone(),
+                gwp: row.gwp,
+                stats: material_db::get_category_stats(&row.category)
+                    .ok()
+                    .filter(|s| s.count > 0),
+                percentile: row.percentile,
+                description: component.benchmark.as_ref().map(|b| b.describe(row.gwp)),
+            })
+            .collect();
+
+        Self {
+            title: title.to_string(),
+            date: material_db::format_date(material_db::now()),
+            life_cycles: project
+                .all_components()
+                .map(|(_, c)| c.life_cycle.clone())
+                .collect(),
+            thresholds: project.thresholds,
+            categories,
+            benchmarks,
+            report,
+        }
+    }
+
+    /// General assumptions as label and value
+    fn assumptions(&self) -> Vec<(&'static str, String)> {
+        let summary = &self.report.summary;
+        vec![
+            (
+                "Scope",
+                "EN 15978 modules A1-A3, A4, A5, B4 and C1-C4. Module D is reported separately."
+                    .to_string(),
+            ),
+            (
+                "Reference study period",
+                format!("{} years", summary.study_period),
+            ),
+            (
+                "Benchmark scope",
+                summary.benchmark_country.as_deref().map_or(
+                    "Manufacturers of all countries".to_string(),
+                    |c| {
+                        format!(
+                            "Manufacturers in {c}, or all countries when fewer materials are known"
+                        )
+                    },
+                ),
+            ),
+            (
+                "Benchmark thresholds",
+                format!(
+                    "Better than the category below P{:.0}, worse above P{:.0}",
+                    self.thresholds.better, self.thresholds.worse
+                ),
+            ),
+            (
+                "Data source",
+                "EC3 by Building Transparency, as stored in the local database. \
+                 Generic materials use the average GWP of their category."
+                    .to_string(),
+            ),
+        ]
+    }
+
+    /// Columns and cells of the components table
+    fn component_table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let headers = vec![
+            "Material",
+            "Category",
+            "Quantity",
+            "GWP / declared unit",
+            "A-C (KgCO2e)",
+            "D (KgCO2e)",
+            "Rank",
+        ];
+        let rows = self
+            .report
+            .components
+            .iter()
+            .map(|row| {
+                vec![
+                    row.label(),
+                    row.category.clone(),
+                    format!("{} {}", number(row.quantity), row.unit),
+                    format!("{} / {}", number(row.gwp), row.declared_unit),
+                    number(row.total),
+                    number(row.modules.d),
+                    rank(row),
+                ]
+            })
+            .collect();
+        (headers, rows)
+    }
+
+    /// Columns and cells of the assemblies table
+    fn assembly_table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let headers = vec![
+            "Assembly",
+            "Layers",
+            "Area (m²)",
+            "KgCO2e / m²",
+            "A-C (KgCO2e)",
+        ];
+        let rows = self
+            .report
+            .assemblies
+            .iter()
+            .map(|a| {
+                vec![
+                    a.name.clone(),
+                    a.layers.join(" + "),
+                    number(a.area),
+                    a.gwp_per_m2.map_or("-".to_string(), number),
+                    number(a.total),
+                ]
+            })
+            .collect();
+        (headers, rows)
+    }
+
+    /// Columns and cells of the life-cycle assumptions of every component
+    fn life_cycle_table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let headers = vec![
+            "Material",
+            "Transport (km)",
+            "Waste",
+            "Service life",
+            "End of life / unit",
+            "D / unit",
+        ];
+        let rows = self
+            .report
+            .components
+            .iter()
+            .zip(&self.life_cycles)
+            .map(|(row, lc)| {
+                vec![
+                    row.label(),
+                    number(lc.transport_km),
+                    format!("{:.1}%", lc.waste_rate * 100.),
+                    match lc.service_life {
+                        life if life > 0. => format!("{life} years"),
+                        _ => "Study period".to_string(),
+                    },
+                    number(lc.end_of_life),
+                    number(lc.beyond_boundary),
+                ]
+            })
+            .collect();
+        (headers, rows)
+    }
+
+    /// Columns and cells of the benchmarks table
+    fn benchmark_table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let headers = vec![
+            "Material", "Category", "GWP", "P10", "P25", "Median", "P75", "P90", "Rank",
+        ];
+        let rows = self
+            .benchmarks
+            .iter()
+            .map(|b| {
+                let stat = |f: fn(&CategoryStats) -> f64| {
+                    b.stats.as_ref().map_or("-".to_string(), |s| number(f(s)))
+                };
+                vec![
+                    b.material.clone(),
+                    b.category.clone(),
+                    number(b.gwp),
+                    stat(|s| s.p10),
+                    stat(|s| s.p25),
+                    stat(|s| s.median),
+                    stat(|s| s.p75),
+                    stat(|s| s.p90),
+                    b.percentile.map_or("-".to_string(), |p| format!("P{p:.0}")),
+                ]
+            })
+            .collect();
+        (headers, rows)
+    }
+
+    /// How the inputs of the sensitivity analysis were varied
+    fn sensitivity_text(&self) -> String {
+        let s = &self.report.sensitivity;
+        let p = s.settings.percent;
+        let variation = match s.settings.gwp {
+            GwpVariation::Percent => {
+                format!("The quantity and GWP of every component vary by ±{p}%")
+            }
+            GwpVariation::Quartiles => format!(
+                "The quantity of every component varies by ±{p}% and its GWP from P25 to P75 of \
+                 its category, or by ±{p}% when the category has no materials"
+            ),
+        };
+        format!(
+            "Change of the total of {} KgCO2e when one input varies and the others keep their \
+             value. {variation}. Bars show the low value of the input in blue and the high value \
+             in red, for the {} inputs with the largest effect.",
+            number(s.baseline),
+            TORNADO_ROWS.min(s.swings.len())
+        )
+    }
+
+    /// Columns and cells of the sensitivity table
+    fn sensitivity_table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let headers = vec![
+            "Input",
+            "Low",
+            "High",
+            "Total low",
+            "Total high",
+            "Range (KgCO2e)",
+        ];
+        let rows = self
+            .report
+            .sensitivity
+            .swings
+            .iter()
+            .map(|s| {
+                vec![
+                    s.label(),
+                    number(s.low_input),
+                    number(s.high_input),
+                    number(s.low),
+                    number(s.high),
+                    number(s.range()),
+                ]
+            })
+            .collect();
+        (headers, rows)
+    }
+
+    /// Columns and cells of the data sources appendix
+    fn source_table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let headers = vec!["Material", "EC3 id", "Manufacturer", "Country", "Quantity"];
+        let rows = self
+            .report
+            .components
+            .iter()
+            .map(|row| {
+                vec![
+                    row.label(),
+                    row.ec3_id
+                        .clone()
+                        .unwrap_or_else(|| format!("Generic, average of {}", row.category)),
+                    row.manufacturer.clone().unwrap_or_else(|| "-".to_string()),
+                    row.country.clone().unwrap_or_else(|| "-".to_string()),
+                    match row.ifc_elements.len() {
+                        0 => "Entered".to_string(),
+                        n => format!("IFC, {n} elements"),
+                    },
+                ]
+            })
+            .collect();
+        (headers, rows)
+    }
+
+    /// Self-contained HTML page, printable to A4
+    pub fn to_html(&self) -> String {
+        let summary = &self.report.summary;
+        let mut html = String::new();
+        html.push_str(&format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+             <section class=\"title\">\n<p class=\"kicker\">Embodied carbon report</p>\n\
+             <h1>{title}</h1>\n<p class=\"total\">{total} <span>KgCO2e, modules A-C</span></p>\n\
+             <p>{components} components &middot; {period} year study period &middot; \
+             Module D {d} KgCO2e</p>\n<p class=\"date\">Generated on {date}</p>\n</section>\n",
+            title = escape(&self.title),
+            total = number(summary.total),
+            components = summary.components,
+            period = summary.study_period,
+            d = number(summary.modules.d),
+            date = self.date,
+        ));
+
+        html.push_str("<section>\n<h2>Assumptions</h2>\n<dl>\n");
+        for (label, value) in self.assumptions() {
+            html.push_str(&format!("<dt>{label}</dt><dd>{}</dd>\n", escape(&value)));
+        }
+        html.push_str("</dl>\n<h3>Life cycle of the components</h3>\n");
+        html_table(&mut html, self.life_cycle_table());
+        html.push_str("</section>\n");
+
+        html.push_str("<section>\n<h2>Components</h2>\n");
+        html_table(&mut html, self.component_table());
+        html.push_str("<h3>Modules</h3>\n<table>\n<tr>");
+        for module in Module::ALL {
+            html.push_str(&format!("<th>{module}</th>"));
+        }
+        html.push_str("</tr>\n<tr>");
+        for module in Module::ALL {
+            html.push_str(&format!(
+                "<td class=\"num\">{}</td>",
+                number(summary.modules.get(module))
+            ));
+        }
+        html.push_str("</tr>\n</table>\n");
+        if !self.report.assemblies.is_empty() {
+            html.push_str("<h3>Assemblies</h3>\n");
+            html_table(&mut html, self.assembly_table());
+        }
+        html.push_str("</section>\n");
+
+        html.push_str("<section>\n<h2>Breakdown by category</h2>\n");
+        html.push_str(&self.breakdown_svg());
+        html.push_str("</section>\n");
+
+        if !self.report.sensitivity.swings.is_empty() {
+            html.push_str(&format!(
+                "<section>\n<h2>Sensitivity</h2>\n<p>{}</p>\n",
+                escape(&self.sensitivity_text())
+            ));
+            html.push_str(&self.tornado_svg());
+            html_table(&mut html, self.sensitivity_table());
+            html.push_str("</section>\n");
+        }
+
+        html.push_str(&format!(
+            "<section>\n<h2>Benchmarks</h2>\n<p>GWP per declared unit compared to the materials \
+             of the same category in the local database. {} components are better, {} typical \
+             and {} worse than their category.</p>\n",
+            summary.better, summary.typical, summary.worse
+        ));
+        html_table(&mut html, self.benchmark_table());
+        for b in self.benchmarks.iter().filter(|b| b.description.is_some()) {
+            html.push_str(&format!(
+                "<p class=\"note\">{}: {}</p>\n",
+                escape(&b.material),
+                escape(b.description.as_deref().unwrap_or_default())
+            ));
+        }
+        html.push_str("</section>\n");
+
+        html.push_str("<section>\n<h2>Appendix: data sources</h2>\n");
+        html_table(&mut html, self.source_table());
+        html.push_str("</section>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Horizontal bar chart of [CarbonReport::categories]
+    fn breakdown_svg(&self) -> String {
+        const WIDTH: f64 = 640.;
+        const LABEL: f64 = 170.;
+        const BAR: f64 = 22.;
+        const GAP: f64 = 8.;
+        let max = self
+            .categories
+            .iter()
+            .map(|(_, total)| *total)
+            .fold(0., f64::max);
+        let height = self.categories.len() as f64 * (BAR + GAP) + GAP;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {WIDTH} {height}\" \
+             width=\"{WIDTH}\" height=\"{height}\" font-family=\"Helvetica, Arial, sans-serif\" \
+             font-size=\"12\">\n"
+        );
+        for (i, (category, total)) in self.categories.iter().enumerate() {
+            let y = GAP + i as f64 * (BAR + GAP);
+            let width = match max > 0. {
+                true => (total.max(0.) / max) * (WIDTH - LABEL - 90.),
+                false => 0.,
+            };
+            svg.push_str(&format!(
+                "<text x=\"{x}\" y=\"{ty}\" text-anchor=\"end\">{name}</text>\n\
+                 <rect x=\"{LABEL}\" y=\"{y}\" width=\"{width:.1}\" height=\"{BAR}\" fill=\"{color}\"/>\n\
+                 <text x=\"{vx:.1}\" y=\"{ty}\">{value}</text>\n",
+                x = LABEL - 8.,
+                ty = y + BAR * 0.7,
+                name = escape(category),
+                color = CHART_COLORS[i % CHART_COLORS.len()].0,
+                vx = LABEL + width + 6.,
+                value = number(*total),
+            ));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Tornado chart of the inputs with the largest effect on the total, centred on it
+    fn tornado_svg(&self) -> String {
+        const WIDTH: f64 = 640.;
+        const LABEL: f64 = 230.;
+        const BAR: f64 = 18.;
+        const GAP: f64 = 6.;
+        let s = &self.report.sensitivity;
+        let swings = &s.swings[..TORNADO_ROWS.min(s.swings.len())];
+        let half = (WIDTH - LABEL) / 2. - 8.;
+        let centre = LABEL + half + 4.;
+        let max = swings
+            .iter()
+            .map(|w| (w.low - s.baseline).abs().max((w.high - s.baseline).abs()))
+            .fold(0., f64::max);
+        let height = swings.len() as f64 * (BAR + GAP) + GAP;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {WIDTH} {height}\" \
+             width=\"{WIDTH}\" height=\"{height}\" font-family=\"Helvetica, Arial, sans-serif\" \
+             font-size=\"12\">\n"
+        );
+        for (i, swing) in swings.iter().enumerate() {
+            let y = GAP + i as f64 * (BAR + GAP);
+            svg.push_str(&format!(
+                "<text x=\"{x}\" y=\"{ty}\" text-anchor=\"end\">{name}</text>\n",
+                x = LABEL - 8.,
+                ty = y + BAR * 0.7,
+                name = escape(&swing.label()),
+            ));
+            for (total, color) in [(swing.low, LOW_COLOR.0), (swing.high, HIGH_COLOR.0)] {
+                let width = match max > 0. {
+                    true => (total - s.baseline) / max * half,
+                    false => 0.,
+                };
+                svg.push_str(&format!(
+                    "<rect x=\"{x:.1}\" y=\"{y}\" width=\"{w:.1}\" height=\"{BAR}\" fill=\"{color}\"/>\n",
+                    x = centre + width.min(0.),
+                    w = width.abs(),
+                ));
+            }
+        }
+        svg.push_str(&format!(
+            "<line x1=\"{centre}\" y1=\"0\" x2=\"{centre}\" y2=\"{height}\" stroke=\"#1d1d1f\"/>\n</svg>\n"
+        ));
+        svg
+    }
+
+    /// PDF of A4 pages
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let summary = &self.report.summary;
+        let mut doc = PdfLayout::new();
+
+        // title page
+        doc.y = PAGE_HEIGHT - 260.;
+        doc.line_of(11., Font::Regular, GREY, "EMBODIED CARBON REPORT");
+        doc.y -= 14.;
+        doc.line_of(28., Font::Bold, BLACK, &self.title);
+        doc.y -= 30.;
+        doc.line_of(
+            22.,
+            Font::Bold,
+            ACCENT,
+            &format!("{} KgCO2e", number(summary.total)),
+        );
+        doc.line_of(11., Font::Regular, GREY, "Modules A-C");
+        doc.y -= 10.;
+        doc.line_of(
+            11.,
+            Font::Regular,
+            BLACK,
+            &format!(
+                "{} components, {} year study period, module D {} KgCO2e",
+                summary.components,
+                summary.study_period,
+                number(summary.modules.d)
+            ),
+        );
+        doc.line_of(
+            11.,
+            Font::Regular,
+            GREY,
+            &format!("Generated on {}", self.date),
+        );
+
+        doc.new_page();
+        doc.heading("Assumptions");
+        for (label, value) in self.assumptions() {
+            doc.ensure(30.);
+            doc.line_of(10., Font::Bold, BLACK, label);
+            doc.paragraph(&value);
+        }
+        doc.subheading("Life cycle of the components");
+        doc.table(self.life_cycle_table(), &[150., 70., 55., 75., 75., 70.]);
+
+        doc.new_page();
+        doc.heading("Components");
+        doc.table(
+            self.component_table(),
+            &[110., 70., 55., 90., 60., 50., 60.],
+        );
+        doc.subheading("Modules");
+        let modules: Vec<String> = Module::ALL
+            .iter()
+            .map(|m| number(summary.modules.get(*m)))
+            .collect();
+        let names: Vec<String> = Module::ALL.iter().map(|m| m.to_string()).collect();
+        doc.table(
+            (names.iter().map(String::as_str).collect(), vec![modules]),
+            &[82.5; 6],
+        );
+        if !self.report.assemblies.is_empty() {
+            doc.subheading("Assemblies");
+            doc.table(self.assembly_table(), &[100., 190., 60., 70., 75.]);
+        }
+
+        doc.subheading("Breakdown by category");
+        self.breakdown_pdf(&mut doc);
+
+        if !self.report.sensitivity.swings.is_empty() {
+            doc.new_page();
+            doc.heading("Sensitivity");
+            doc.paragraph(&self.sensitivity_text());
+            self.tornado_pdf(&mut doc);
+            doc.table(self.sensitivity_table(), &[175., 60., 60., 70., 70., 60.]);
+        }
+
+        doc.new_page();
+        doc.heading("Benchmarks");
+        doc.paragraph(&format!(
+            "GWP per declared unit compared to the materials of the same category in the local \
+             database. {} components are better, {} typical and {} worse than their category.",
+            summary.better, summary.typical, summary.worse
+        ));
+        doc.table(
+            self.benchmark_table(),
+            &[105., 75., 45., 45., 45., 45., 45., 45., 45.],
+        );
+        for b in &self.benchmarks {
+            if let Some(description) = &b.description {
+                doc.paragraph(&format!("{}: {description}", b.material));
+            }
+        }
+
+        doc.new_page();
+        doc.heading("Appendix: data sources");
+        doc.table(self.source_table(), &[130., 135., 100., 50., 80.]);
+
+        doc.finish()
+    }
+
+    fn breakdown_pdf(&self, doc: &mut PdfLayout) {
+        const LABEL: f64 = 130.;
+        const BAR: f64 = 14.;
+        let max = self
+            .categories
+            .iter()
+            .map(|(_, total)| *total)
+            .fold(0., f64::max);
+        let room = PAGE_WIDTH - 2. * MARGIN - LABEL - 70.;
+        for (i, (category, total)) in self.categories.iter().enumerate() {
+            doc.ensure(BAR + 6.);
+            let y = doc.y - BAR;
+            let label = pdf::truncate(category, 9., LABEL - 8.);
+            doc.doc.text_right(
+                MARGIN + LABEL - 8.,
+                y + 4.,
+                9.,
+                Font::Regular,
+                BLACK,
+                &label,
+            );
+            let width = match max > 0. {
+                true => total.max(0.) / max * room,
+                false => 0.,
+            };
+            doc.doc.rect(
+                MARGIN + LABEL,
+                y,
+                width,
+                BAR,
+                CHART_COLORS[i % CHART_COLORS.len()].1,
+            );
+            doc.doc.text(
+                MARGIN + LABEL + width + 5.,
+                y + 4.,
+                9.,
+                Font::Regular,
+                BLACK,
+                &number(*total),
+            );
+            doc.y -= BAR + 6.;
+        }
+    }
+
+    /// The chart of [CarbonReport::tornado_svg] drawn on the page
+    fn tornado_pdf(&self, doc: &mut PdfLayout) {
+        const LABEL: f64 = 170.;
+        const BAR: f64 = 12.;
+        let s = &self.report.sensitivity;
+        let swings = &s.swings[..TORNADO_ROWS.min(s.swings.len())];
+        let half = (PAGE_WIDTH - 2. * MARGIN - LABEL) / 2. - 4.;
+        let centre = MARGIN + LABEL + half + 4.;
+        let max = swings
+            .iter()
+            .map(|w| (w.low - s.baseline).abs().max((w.high - s.baseline).abs()))
+            .fold(0., f64::max);
+        doc.ensure(swings.len() as f64 * (BAR + 5.));
+        let top = doc.y;
+        for swing in swings {
+            let y = doc.y - BAR;
+            let label = pdf::truncate(&swing.label(), 8., LABEL - 8.);
+            doc.doc.text_right(
+                MARGIN + LABEL - 8.,
+                y + 3.,
+                8.,
+                Font::Regular,
+                BLACK,
+                &label,
+            );
+            for (total, color) in [(swing.low, LOW_COLOR.1), (swing.high, HIGH_COLOR.1)] {
+                let width = match max > 0. {
+                    true => (total - s.baseline) / max * half,
+                    false => 0.,
+                };
+                doc.doc
+                    .rect(centre + width.min(0.), y, width.abs(), BAR, color);
+            }
+            doc.y -= BAR + 5.;
+        }
+        doc.doc.line(centre, top, centre, doc.y, 0.75, BLACK);
+        doc.y -= 10.;
+    }
+}
+
+/// Percentile rank and benchmark result of a component, e.g. `P18 better`
+fn rank(row: &ReportRow) -> String {
+    match row.percentile {
+        Some(p) => format!("P{p:.0} {}", row.benchmark),
+        None => row.benchmark.to_string(),
+    }
+}
+
+/// Formats a value with two decimals and thousands separators
+fn number(value: f64) -> String {
+    let text = format!("{:.2}", value.abs());
+    let (int, dec) = text.split_once('.').unwrap_or((&text, "00"));
+    let mut grouped = String::new();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    let sign = if value < 0. && text != "0.00" {
+        "-"
+    } else {
+        ""
+    };
+    format!("{sign}{grouped}.{dec}")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_table(html: &mut String, (headers, rows): (Vec<&str>, Vec<Vec<String>>)) {
+    html.push_str("<table>\n<tr>");
+    for header in headers {
+        html.push_str(&format!("<th>{header}</th>"));
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for (i, cell) in row.iter().enumerate() {
+            let class = if i > 0 && is_numeric(cell) {
+                " class=\"num\""
+            } else {
+                ""
+            };
+            html.push_str(&format!("<td{class}>{}</td>", escape(cell)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+/// Cells starting with a number, like `1,250.00 M3` or `5.0%`, are aligned right in tables
+fn is_numeric(cell: &str) -> bool {
+    let first = cell.split_whitespace().next().unwrap_or_default();
+    first.replace([',', '%'], "").parse::<f64>().is_ok()
+}
+
+const STYLE: &str = "
+@page { size: A4; margin: 18mm; }
+body { font-family: Helvetica, Arial, sans-serif; color: #1d1d1f; max-width: 900px; margin: 0 auto; padding: 24px; font-size: 13px; }
+section { margin-bottom: 32px; }
+section.title { min-height: 60vh; display: flex; flex-direction: column; justify-content: center; break-after: page; }
+.kicker { letter-spacing: 0.2em; color: #6e6e73; text-transform: uppercase; }
+h1 { font-size: 36px; margin: 8px 0 24px; }
+h2 { border-bottom: 2px solid #2f7d5b; padding-bottom: 4px; }
+.total { font-size: 28px; font-weight: bold; color: #2f7d5b; margin: 0; }
+.total span { font-size: 14px; font-weight: normal; color: #6e6e73; }
+.date, .note { color: #6e6e73; }
+dt { font-weight: bold; margin-top: 8px; }
+dd { margin-left: 0; }
+table { border-collapse: collapse; width: 100%; margin: 8px 0 16px; }
+th, td { border-bottom: 1px solid #d2d2d7; padding: 4px 6px; text-align: left; vertical-align: top; }
+th { background: #f5f5f7; }
+td.num { text-align: right; white-space: nowrap; }
+tr { break-inside: avoid; }
+";
+
+/// Colors of the category bars as HTML and PDF colors
+const CHART_COLORS: [(&str, Rgb); 4] = [
+    ("#2f7d5b", (0.184, 0.490, 0.357)),
+    ("#5b8def", (0.357, 0.553, 0.937)),
+    ("#e0a030", (0.878, 0.627, 0.188)),
+    ("#c8553d", (0.784, 0.333, 0.239)),
+];
+
+const ACCENT: Rgb = CHART_COLORS[0].1;
+/// Colors of the low and high value of the inputs in tornado charts
+const LOW_COLOR: (&str, Rgb) = CHART_COLORS[1];
+const HIGH_COLOR: (&str, Rgb) = CHART_COLORS[3];
+const GREY: Rgb = (0.43, 0.43, 0.45);
+const RULE: Rgb = (0.82, 0.82, 0.84);
+const MARGIN: f64 = 50.;
+
+/// Flows text and tables down the pages of a [Document]
+struct PdfLayout {
+    doc: Document,
+    /// Baseline of the next line
+    y: f64,
+}
+
+impl PdfLayout {
+    fn new() -> Self {
+        let mut doc = Document::new();
+        doc.add_page();
+        Self {
+            doc,
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.doc.add_page();
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Starts a new page unless `height` fits above the bottom margin.
+    /// Returns whether a page was started.
+    fn ensure(&mut self, height: f64) -> bool {
+        if self.y - height < MARGIN {
+            self.new_page();
+            return true;
+        }
+        false
+    }
+
+    fn line_of(&mut self, size: f64, font: Font, color: Rgb, text: &str) {
+        self.ensure(size * 1.4);
+        self.y -= size;
+        self.doc.text(MARGIN, self.y, size, font, color, text);
+        self.y -= size * 0.4;
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.ensure(60.);
+        self.line_of(18., Font::Bold, BLACK, text);
+        self.doc
+            .line(MARGIN, self.y, PAGE_WIDTH - MARGIN, self.y, 1.5, ACCENT);
+        self.y -= 12.;
+    }
+
+    fn subheading(&mut self, text: &str) {
+        self.y -= 8.;
+        self.ensure(50.);
+        self.line_of(12., Font::Bold, BLACK, text);
+        self.y -= 4.;
+    }
+
+    /// Writes text wrapped to the width of the page
+    fn paragraph(&mut self, text: &str) {
+        let width = PAGE_WIDTH - 2. * MARGIN;
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            let candidate = match line.is_empty() {
+                true => word.to_string(),
+                false => format!("{line} {word}"),
+            };
+            if pdf::text_width(&candidate, 10.) > width && !line.is_empty() {
+                self.line_of(10., Font::Regular, BLACK, &line);
+                line = word.to_string();
+            } else {
+                line = candidate;
+            }
+        }
+        if !line.is_empty() {
+            self.line_of(10., Font::Regular, BLACK, &line);
+        }
+        self.y -= 6.;
+    }
+
+    /// Writes a table with one line per row, the header is repeated on every page
+    fn table(&mut self, (headers, rows): (Vec<&str>, Vec<Vec<String>>), widths: &[f64]) {
+        const SIZE: f64 = 8.;
+        const ROW: f64 = 15.;
+        self.ensure(2. * ROW);
+        self.table_header(&headers, widths);
+        for row in rows {
+            if self.ensure(ROW) {
+                self.table_header(&headers, widths);
+            }
+            self.y -= ROW;
+            let mut x = MARGIN;
+            for (i, (cell, width)) in row.iter().zip(widths).enumerate() {
+                let text = pdf::truncate(cell, SIZE, width - 6.);
+                if i > 0 && is_numeric(cell) {
+                    self.doc.text_right(
+                        x + width - 3.,
+                        self.y + 4.,
+                        SIZE,
+                        Font::Regular,
+                        BLACK,
+                        &text,
+                    );
+                } else {
+                    self.doc
+                        .text(x + 3., self.y + 4., SIZE, Font::Regular, BLACK, &text);
+                }
+                x += width;
+            }
+            self.doc.line(
+                MARGIN,
+                self.y,
+                MARGIN + widths.iter().sum::<f64>(),
+                self.y,
+                0.5,
+                RULE,
+            );
+        }
+        self.y -= 12.;
+    }
+
+    fn table_header(&mut self, headers: &[&str], widths: &[f64]) {
+        const ROW: f64 = 16.;
+        self.y -= ROW;
+        let total: f64 = widths.iter().sum();
+        self.doc
+            .rect(MARGIN, self.y, total, ROW, (0.96, 0.96, 0.97));
+        let mut x = MARGIN;
+        for (header, width) in headers.iter().zip(widths) {
+            let text = pdf::truncate(header, 8., width - 6.);
+            self.doc
+                .text(x + 3., self.y + 5., 8., Font::Bold, BLACK, &text);
+            x += width;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.doc.finish()
+    }
+}
@@ -0,0 +1,212 @@
+//! Design options of a project, e.g. a baseline against low-carbon options A and B.
+//!
+//! Scenarios share the components of the project. A component may be changed in a scenario
+//! with a [ComponentOverride] of its material and quantity, everything it does not override
+//! is the baseline. Assemblies are the same in every scenario.
+use ec3api::models::Ec3Material;
+use serde::Serialize;
+
+use crate::{
+    lifecycle::ModuleBreakdown,
+    material_db,
+    project::{Component, Material, Project, UMaterial},
+    units::{self, ConversionError, ConversionFactors, QuantityUnit},
+};
+
+/// Name of the components as they are, without overrides
+pub const BASELINE: &str = "Baseline";
+
+/// Material used instead of the one of the component
+pub struct Substitute {
+    pub material: Box<dyn Material>,
+    pub factors: ConversionFactors,
+}
+
+impl Substitute {
+    pub fn ec3(material: Ec3Material) -> Self {
+        let factors =
+            material_db::get_conversion_factors(Some(&material.id), &material.category.name)
+                .unwrap_or_default();
+        Self {
+            material: Box::new(material),
+            factors,
+        }
+    }
+
+    /// The generic material of a category, see [UMaterial]
+    pub fn generic(category: &str) -> Self {
+        Self {
+            material: Box::new(UMaterial::get_from_db(category)),
+            factors: material_db::get_conversion_factors(None, category).unwrap_or_default(),
+        }
+    }
+}
+
+/// Changes of a component in a scenario, `None` keeps the value of the baseline
+#[derive(Default)]
+pub struct ComponentOverride {
+    pub substitute: Option<Substitute>,
+    /// In the unit of the component
+    pub quantity: Option<f64>,
+}
+
+impl ComponentOverride {
+    pub fn is_empty(&self) -> bool {
+        self.substitute.is_none() && self.quantity.is_none()
+    }
+}
+
+impl Component {
+    /// Material of the component in a scenario
+    pub fn scenario_material(&self, scenario: &str) -> &dyn Material {
+        match self
+            .overrides
+            .get(scenario)
+            .and_then(|o| o.substitute.as_ref())
+        {
+            Some(substitute) => substitute.material.as_ref(),
+            None => self.material.as_ref(),
+        }
+    }
+
+    /// Quantity of the component in a scenario
+    pub fn scenario_quantity(&self, scenario: &str) -> f64 {
+        self.overrides
+            .get(scenario)
+            .and_then(|o| o.quantity)
+            .unwrap_or(self.quantity)
+    }
+
+    /// Modules of the component in a scenario, those of [Component::calculate] when the
+    /// scenario does not change it
+    pub fn scenario_modules(
+        &self,
+        scenario: &str,
+        study_period: f64,
+    ) -> Result<ModuleBreakdown, ConversionError> {
+        let Some(o) = self.overrides.get(scenario).filter(|o| !o.is_empty()) else {
+            return match &self.conversion_error {
+                Some(e) => Err(e.clone()),
+                None => Ok(self.modules),
+            };
+        };
+        let quantity = o.quantity.unwrap_or(self.quantity);
+        let quantity = match (&o.substitute, self.unit) {
+            (None, None) => quantity,
+            (None, Some(unit)) => {
+                let declared = QuantityUnit::from_declared(&self.material.get_unit().unit)?;
+                units::convert(quantity, unit, declared, &self.factors)?
+            }
+            // the quantity is converted to the declared unit of the substitute
+            (Some(substitute), unit) => {
+                let from = match unit {
+                    Some(unit) => unit,
+                    None => QuantityUnit::from_declared(&self.material.get_unit().unit)?,
+                };
+                let to = QuantityUnit::from_declared(&substitute.material.get_unit().unit)?;
+                units::convert(quantity, from, to, &substitute.factors)?
+            }
+        };
+        let material = self.scenario_material(scenario);
+        Ok(ModuleBreakdown::calculate(
+            quantity / material.get_unit().value,
+            material.get_gwp().value,
+            &self.life_cycle,
+            study_period,
+        ))
+    }
+}
+
+/// Totals of a scenario compared to the baseline
+#[derive(Clone, Debug, Serialize)]
+pub struct ScenarioResult {
+    pub name: String,
+    pub modules: ModuleBreakdown,
+    /// Total of modules A to C in KgCO2e
+    pub total: f64,
+    /// Difference of the total to the baseline in KgCO2e, negative when lower
+    pub delta: f64,
+    /// Difference of the total to the baseline in percent of its magnitude, negative when
+    /// lower, `None` when the baseline is 0
+    pub delta_percent: Option<f64>,
+    /// Components changed in the scenario
+    pub changes: usize,
+}
+
+impl Project {
+    /// Adds a scenario without changes, returns `false` if the name is empty or taken
+    pub fn add_scenario(&mut self, name: &str) -> bool {
+        let name = name.trim();
+        if name.is_empty() || name == BASELINE || self.scenarios.iter().any(|s| s == name) {
+            return false;
+        }
+        self.scenarios.push(name.to_string());
+        true
+    }
+
+    /// Renames a scenario and its overrides, returns `false` if the new name is empty or taken
+    pub fn rename_scenario(&mut self, from: &str, to: &str) -> bool {
+        let to = to.trim();
+        let Some(i) = self.scenarios.iter().position(|s| s == from) else {
+            return false;
+        };
+        if to.is_empty() || to == BASELINE || self.scenarios.iter().any(|s| s == to) {
+            return false;
+        }
+        self.scenarios[i] = to.to_string();
+        for c in self.components.iter_mut() {
+            if let Some(o) = c.overrides.remove(from) {
+                c.overrides.insert(to.to_string(), o);
+            }
+        }
+        true
+    }
+
+    /// Removes a scenario and its overrides
+    pub fn remove_scenario(&mut self, name: &str) {
+        self.scenarios.retain(|s| s != name);
+        for c in self.components.iter_mut() {
+            c.overrides.remove(name);
+        }
+    }
+
+    /// Modules of the project in a scenario, components which can not be
+    /// converted count as 0 like in [Project::calculate]
+    pub fn scenario_modules(&self, scenario: &str) -> ModuleBreakdown {
+        let mut modules = ModuleBreakdown::default();
+        for c in &self.components {
+            modules += c
+                .scenario_modules(scenario, self.study_period)
+                .unwrap_or_default();
+        }
+        for a in &self.assemblies {
+            modules += a.modules;
+        }
+        modules
+    }
+
+    /// The baseline followed by every scenario, the project must have been calculated
+    pub fn compare_scenarios(&self) -> Vec<ScenarioResult> {
+        let baseline = self.calculated_gwp;
+        let result = |name: &str, modules: ModuleBreakdown| {
+            let total = modules.total();
+            ScenarioResult {
+                name: name.to_string(),
+                modules,
+                total,
+                delta: total - baseline,
+                delta_percent: (baseline != 0.).then(|| (total - baseline) / baseline.abs() * 100.),
+                changes: self
+                    .components
+                    .iter()
+                    .filter(|c| c.overrides.get(name).is_some_and(|o| !o.is_empty()))
+                    .count(),
+            }
+        };
+        let mut results = vec![result(BASELINE, self.modules)];
+        for scenario in &self.scenarios {
+            results.push(result(scenario, self.scenario_modules(scenario)));
+        }
+        results
+    }
+}
@@ -0,0 +1,269 @@
+//! Sensitivity of the project total to the quantity and GWP of every component, shown as a
+//! tornado chart where the inputs which move the total most come first.
+//!
+//! Inputs are varied one at a time, the others keeping their value. Quantities vary by a
+//! ± percentage, GWPs by the same percentage or across P25 to P75 of their category.
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{lifecycle::ModuleBreakdown, project::Project};
+
+/// Number of inputs drawn in tornado charts, the table of the report lists all of them
+pub const TORNADO_ROWS: usize = 15;
+
+/// How the GWP of the components is varied
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GwpVariation {
+    /// By [SensitivitySettings::percent]
+    #[default]
+    Percent,
+    /// From P25 to P75 of the category, by the percentage when its distribution is not known
+    Quartiles,
+}
+
+impl Display for GwpVariation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GwpVariation::Percent => write!(f, "± percentage"),
+            GwpVariation::Quartiles => write!(f, "Category P25-P75"),
+        }
+    }
+}
+
+/// Parameters of the analysis, saved with the project
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SensitivitySettings {
+    /// ± variation of the inputs in percent
+    pub percent: f64,
+    pub gwp: GwpVariation,
+}
+
+impl Default for SensitivitySettings {
+    fn default() -> Self {
+        Self {
+            percent: 20.,
+            gwp: GwpVariation::Percent,
+        }
+    }
+}
+
+/// Input of a component which is varied
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    Quantity,
+    Gwp,
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Parameter::Quantity => write!(f, "quantity"),
+            Parameter::Gwp => write!(f, "GWP"),
+        }
+    }
+}
+
+/// Project totals at the low and high value of one input
+#[derive(Clone, Debug, Serialize)]
+pub struct Swing {
+    /// Material of the component, `Assembly: material` for layers of assemblies
+    pub component: String,
+    pub parameter: Parameter,
+    /// Values of the input, in the unit of the quantity or in KgCO2e per declared unit
+    pub low_input: f64,
+    pub high_input: f64,
+    /// Total of modules A to C in KgCO2e with the low and the high input
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Swing {
+    /// Difference of the totals in KgCO2e
+    pub fn range(&self) -> f64 {
+        (self.high - self.low).abs()
+    }
+
+    /// e.g. `Concrete (Generic) quantity`
+    pub fn label(&self) -> String {
+        format!("{} {}", self.component, self.parameter)
+    }
+}
+
+/// Inputs of a project ranked by their effect on its total
+#[derive(Clone, Debug, Serialize)]
+pub struct Sensitivity {
+    pub settings: SensitivitySettings,
+    /// Total of modules A to C in KgCO2e
+    pub baseline: f64,
+    /// Largest range first, inputs which do not change the total are left out
+    pub swings: Vec<Swing>,
+}
+
+impl Sensitivity {
+    /// Varies the inputs of every component, the project must have been calculated.
+    /// Components which could not be converted are left out.
+    pub fn analyze(project: &Project, settings: SensitivitySettings) -> Self {
+        let baseline = project.calculated_gwp;
+        let f = settings.percent / 100.;
+        let mut swings = Vec::new();
+        for (assembly, c) in project.all_components() {
+            let Ok(quantity) = c.declared_quantity() else {
+                continue;
+            };
+            let units = quantity / c.material.get_unit().value;
+            let gwp = c.material.get_gwp().value;
+            // total of the project with the component calculated again
+            let total = |units: f64, gwp: f64| {
+                baseline - c.calculated
+                    + ModuleBreakdown::calculate(units, gwp, &c.life_cycle, project.study_period)
+                        .total()
+            };
+            let component = match assembly {
+                Some(assembly) => format!("{assembly}: {}", c.material.get_name()),
+                None => c.material.get_name().to_string(),
+            };
+            swings.push(Swing {
+                component: component.clone(),
+                parameter: Parameter::Quantity,
+                low_input: c.quantity * (1. - f),
+                high_input: c.quantity * (1. + f),
+                low: total(units * (1. - f), gwp),
+                high: total(units * (1. + f), gwp),
+            });
+            let (low_input, high_input) = match (settings.gwp, &c.benchmark) {
+                (GwpVariation::Quartiles, Some(b)) => (b.percentile(25.), b.percentile(75.)),
+                _ => (gwp * (1. - f), gwp * (1. + f)),
+            };
+            swings.push(Swing {
+                component,
+                parameter: Parameter::Gwp,
+                low_input,
+                high_input,
+                low: total(units, low_input),
+                high: total(units, high_input),
+            });
+        }
+        swings.retain(|s| s.range() > 0.);
+        swings.sort_by(|a, b| b.range().total_cmp(&a.range()));
+        Self {
+            settings,
+            baseline,
+            swings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ec3api::models::{DeclaredUnit, Gwp, GwpUnits, Unit};
+
+    use super::*;
+    use crate::{
+        benchmark::Distribution,
+        project::{Component, UMaterial},
+    };
+
+    /// A component of `quantity` m³ of a generic material
+    fn component(name: &str, gwp: f64, quantity: f64) -> Component {
+        let material = UMaterial {
+            name: name.into(),
+            category: "Concrete".into(),
+            gwp: Gwp {
+                value: gwp,
+                unit: GwpUnits::KgCO2e,
+            },
+            unit: DeclaredUnit {
+                value: 1.,
+                unit: Unit::M3,
+            },
+        };
+        let mut component = Component::new(Box::new(material), gwp);
+        component.quantity = quantity;
+        component
+    }
+
+    fn project(components: Vec<Component>) -> Project {
+        let mut project = Project::new();
+        project.components = components;
+        project.calculate();
+        project
+    }
+
+    fn settings(gwp: GwpVariation) -> SensitivitySettings {
+        SensitivitySettings { percent: 20., gwp }
+    }
+
+    #[test]
+    fn largest_ranges_come_first() {
+        let project = project(vec![
+            component("Small", 100., 1.),
+            component("Large", 100., 10.),
+        ]);
+        let sensitivity = Sensitivity::analyze(&project, settings(GwpVariation::Percent));
+        assert_eq!(sensitivity.baseline, 1100.);
+        let swings: Vec<(String, f64, f64)> = sensitivity
+            .swings
+            .iter()
+            .map(|s| (s.label(), s.low, s.high))
+            .collect();
+        assert_eq!(
+            swings,
+            [
+                ("Large quantity".to_string(), 900., 1300.),
+                ("Large GWP".to_string(), 900., 1300.),
+                ("Small quantity".to_string(), 1080., 1120.),
+                ("Small GWP".to_string(), 1080., 1120.),
+            ]
+        );
+    }
+
+    #[test]
+    fn inputs_without_effect_are_left_out() {
+        let mut disposal = component("Disposal only", 0., 10.);
+        disposal.life_cycle.end_of_life = 5.;
+        let project = project(vec![component("Unused", 100., 0.), disposal]);
+        let sensitivity = Sensitivity::analyze(&project, settings(GwpVariation::Percent));
+        assert_eq!(sensitivity.swings.len(), 1);
+        let swing = &sensitivity.swings[0];
+        assert_eq!(swing.label(), "Disposal only quantity");
+        assert_eq!((swing.low, swing.high), (40., 60.));
+    }
+
+    #[test]
+    fn gwp_varies_by_percentage_or_across_quartiles() {
+        let mut benchmarked = component("Benchmarked", 300., 2.);
+        // P25 is 250 and P75 is 400
+        benchmarked.benchmark = Distribution::from_sorted("Concrete", None, &[200., 300., 500.]);
+        let project = project(vec![benchmarked, component("Unknown", 100., 1.)]);
+        let gwp_inputs = |variation| {
+            let mut inputs: Vec<(String, f64, f64)> =
+                Sensitivity::analyze(&project, settings(variation))
+                    .swings
+                    .into_iter()
+                    .filter(|s| s.parameter == Parameter::Gwp)
+                    .map(|s| (s.component, s.low_input, s.high_input))
+                    .collect();
+            inputs.sort_by(|a, b| a.0.cmp(&b.0));
+            inputs
+        };
+        let cases = [
+            (GwpVariation::Percent, [240., 360.]),
+            (GwpVariation::Quartiles, [250., 400.]),
+        ];
+        for (variation, benchmarked) in cases {
+            let inputs = gwp_inputs(variation);
+            assert_eq!(inputs.len(), 2, "{variation}");
+            let (_, low, high) = &inputs[0];
+            assert!(
+                (low - benchmarked[0]).abs() < 1e-9 && (high - benchmarked[1]).abs() < 1e-9,
+                "{variation}: {low} to {high}"
+            );
+            // without a distribution the percentage is used
+            assert_eq!(inputs[1], ("Unknown".to_string(), 80., 120.));
+        }
+    }
+}
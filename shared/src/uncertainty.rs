@@ -0,0 +1,326 @@
+//! Uncertainty of the project total, propagated from the GWP of the components with a
+//! Monte Carlo simulation.
+//!
+//! The GWP of a generic material is drawn from the distribution of its category, the GWP of a
+//! specific EPD uniformly within a ± range of its declared value. Draws come from a seeded
+//! generator, so the same project and [UncertaintySettings] always give the same totals.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    benchmark::Distribution,
+    lifecycle::{LifeCycle, ModuleBreakdown},
+    project::{Component, Project},
+};
+
+/// Default ± range of the GWP of specific EPDs, in percent
+pub const EPD_RANGE: f64 = 10.;
+
+/// Number of bars of [Simulation::histogram] in the app
+pub const BINS: usize = 30;
+
+/// Parameters of a simulation, saved with the project so its results can be reproduced
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UncertaintySettings {
+    /// ± range of the GWP of specific EPDs in percent, unless the component sets its own
+    pub epd_range: f64,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for UncertaintySettings {
+    fn default() -> Self {
+        Self {
+            epd_range: EPD_RANGE,
+            iterations: 10_000,
+            seed: 42,
+        }
+    }
+}
+
+/// SplitMix64, small and fast with a fixed output for a seed on every platform
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Spread of the GWP per declared unit of a component
+#[derive(Clone, Debug, PartialEq)]
+pub enum GwpDistribution<'a> {
+    /// GWP of the materials of the category
+    Empirical(&'a Distribution),
+    /// Uniform within `percent` of `gwp`
+    Range { gwp: f64, percent: f64 },
+}
+
+impl GwpDistribution<'_> {
+    pub fn sample(&self, rng: &mut Rng) -> f64 {
+        let u = rng.next_f64();
+        match self {
+            GwpDistribution::Empirical(distribution) => {
+                percentile(&distribution.quantiles, u * 100.)
+            }
+            GwpDistribution::Range { gwp, percent } => gwp * (1. + percent / 100. * (2. * u - 1.)),
+        }
+    }
+}
+
+impl Component {
+    /// The distribution of the category for generic materials when it is known,
+    /// otherwise the ± range of the component or `epd_range`
+    pub fn gwp_distribution(&self, epd_range: f64) -> GwpDistribution<'_> {
+        match (&self.benchmark, self.material.get_id()) {
+            (Some(distribution), None) if !distribution.quantiles.is_empty() => {
+                GwpDistribution::Empirical(distribution)
+            }
+            _ => GwpDistribution::Range {
+                gwp: self.material.get_gwp().value,
+                percent: self.gwp_range.unwrap_or(epd_range),
+            },
+        }
+    }
+}
+
+/// Bar of a histogram, counting the totals from `start` up to `end`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Bin {
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+}
+
+/// Project totals of a Monte Carlo simulation
+pub struct Simulation {
+    pub settings: UncertaintySettings,
+    /// Total of modules A to C in KgCO2e of every iteration, sorted ascending
+    pub totals: Vec<f64>,
+    pub mean: f64,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+}
+
+impl Simulation {
+    /// Draws the GWP of every component for each iteration, the project must have been
+    /// calculated. Components which could not be converted count as 0 like in
+    /// [Project::calculate].
+    pub fn run(project: &Project, settings: UncertaintySettings) -> Self {
+        let inputs: Vec<(f64, &LifeCycle, GwpDistribution)> = project
+            .all_components()
+            .filter_map(|(_, c)| {
+                let units = c.declared_quantity().ok()? / c.material.get_unit().value;
+                Some((units, &c.life_cycle, c.gwp_distribution(settings.epd_range)))
+            })
+            .collect();
+        let mut rng = Rng::new(settings.seed);
+        let mut totals: Vec<f64> = (0..settings.iterations)
+            .map(|_| {
+                inputs
+                    .iter()
+                    .map(|(units, life_cycle, gwp)| {
+                        let gwp = gwp.sample(&mut rng);
+                        ModuleBreakdown::calculate(*units, gwp, life_cycle, project.study_period)
+                            .total()
+                    })
+                    .sum()
+            })
+            .collect();
+        totals.sort_by(f64::total_cmp);
+        let mean = match totals.len() {
+            0 => 0.,
+            n => totals.iter().sum::<f64>() / n as f64,
+        };
+        Self {
+            settings,
+            p10: percentile(&totals, 10.),
+            p50: percentile(&totals, 50.),
+            p90: percentile(&totals, 90.),
+            mean,
+            totals,
+        }
+    }
+
+    /// Splits the range of the totals into `bins` bars of equal width
+    pub fn histogram(&self, bins: usize) -> Vec<Bin> {
+        let (Some(min), Some(max)) = (self.totals.first(), self.totals.last()) else {
+            return Vec::new();
+        };
+        let bins = bins.max(1);
+        let width = match max - min {
+            range if range > 0. => range / bins as f64,
+            _ => 1.,
+        };
+        let mut histogram: Vec<Bin> = (0..bins)
+            .map(|i| Bin {
+                start: min + i as f64 * width,
+                end: min + (i + 1) as f64 * width,
+                count: 0,
+            })
+            .collect();
+        for total in &self.totals {
+            let i = (((total - min) / width) as usize).min(bins - 1);
+            histogram[i].count += 1;
+        }
+        histogram
+    }
+}
+
+/// Value below which `p` percent of the sorted `values` fall, interpolated linearly
+pub fn percentile(values: &[f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0.;
+    }
+    let h = p / 100. * (values.len() - 1) as f64;
+    let lo = h.floor() as usize;
+    let hi = (lo + 1).min(values.len() - 1);
+    values[lo] + (h - lo as f64) * (values[hi] - values[lo])
+}
+
+#[cfg(test)]
+mod tests {
+    use ec3api::models::{DeclaredUnit, Gwp, GwpUnits, Unit};
+
+    use super::*;
+    use crate::project::UMaterial;
+
+    /// A component of `quantity` m³ of a generic material
+    fn component(gwp: f64, quantity: f64) -> Component {
+        let material = UMaterial {
+            name: "Concrete (Generic)".into(),
+            category: "Concrete".into(),
+            gwp: Gwp {
+                value: gwp,
+                unit: GwpUnits::KgCO2e,
+            },
+            unit: DeclaredUnit {
+                value: 1.,
+                unit: Unit::M3,
+            },
+        };
+        let mut component = Component::new(Box::new(material), gwp);
+        component.quantity = quantity;
+        component
+    }
+
+    fn project() -> Project {
+        let mut project = Project::new();
+        project.components.push(component(100., 10.));
+        let mut empirical = component(300., 2.);
+        empirical.benchmark = Distribution::from_sorted("Concrete", None, &[200., 300., 500.]);
+        project.components.push(empirical);
+        project.calculate();
+        project
+    }
+
+    fn simulation(totals: Vec<f64>) -> Simulation {
+        Simulation {
+            settings: UncertaintySettings::default(),
+            mean: 0.,
+            p10: 0.,
+            p50: 0.,
+            p90: 0.,
+            totals,
+        }
+    }
+
+    #[test]
+    fn rng_gives_the_splitmix64_sequence() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(rng.next_u64(), 0x06C4_5D18_8009_454F);
+        let mut rng = Rng::new(42);
+        assert!((0..1000)
+            .map(|_| rng.next_f64())
+            .all(|u| (0. ..1.).contains(&u)));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_totals() {
+        let project = project();
+        let settings = UncertaintySettings {
+            iterations: 500,
+            ..Default::default()
+        };
+        let a = Simulation::run(&project, settings);
+        let b = Simulation::run(&project, settings);
+        assert_eq!(a.totals, b.totals);
+        let c = Simulation::run(
+            &project,
+            UncertaintySettings {
+                seed: 7,
+                ..settings
+            },
+        );
+        assert_ne!(a.totals, c.totals);
+
+        assert_eq!(a.totals.len(), 500);
+        assert!(a.totals.windows(2).all(|w| w[0] <= w[1]));
+        assert!(a.p10 <= a.p50 && a.p50 <= a.p90);
+        // ±10 % of 1000 plus 2 m³ drawn between 200 and 500 per m³
+        assert!(a.totals[0] >= 900. + 400. && a.totals[499] <= 1100. + 1000.);
+    }
+
+    #[test]
+    fn samples_stay_within_their_distribution() {
+        let mut rng = Rng::new(1);
+        let project = project();
+        let range = project.components[0].gwp_distribution(EPD_RANGE);
+        assert_eq!(
+            range,
+            GwpDistribution::Range {
+                gwp: 100.,
+                percent: EPD_RANGE
+            }
+        );
+        let empirical = project.components[1].gwp_distribution(EPD_RANGE);
+        assert!(matches!(empirical, GwpDistribution::Empirical(_)));
+        for _ in 0..1000 {
+            assert!((90. ..=110.).contains(&range.sample(&mut rng)));
+            assert!((200. ..=500.).contains(&empirical.sample(&mut rng)));
+        }
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_values() {
+        let values = [1., 2., 3., 4., 5.];
+        let cases = [(0., 1.), (25., 2.), (50., 3.), (60., 3.4), (100., 5.)];
+        for (p, expected) in cases {
+            assert!((percentile(&values, p) - expected).abs() < 1e-9, "P{p}");
+        }
+        assert_eq!(percentile(&[], 50.), 0.);
+        assert_eq!(percentile(&[7.], 10.), 7.);
+        assert_eq!(percentile(&[7.], 90.), 7.);
+    }
+
+    #[test]
+    fn histogram_counts_every_total() {
+        let histogram = simulation(vec![0., 1., 2., 3., 4.]).histogram(2);
+        let counts: Vec<usize> = histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [2, 3]);
+        assert_eq!((histogram[0].start, histogram[1].end), (0., 4.));
+
+        assert!(simulation(Vec::new()).histogram(BINS).is_empty());
+        // equal totals fall in the first bar
+        let histogram = simulation(vec![5., 5., 5.]).histogram(3);
+        let counts: Vec<usize> = histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [3, 0, 0]);
+        assert_eq!(simulation(vec![5.]).histogram(0).len(), 1);
+    }
+}
@@ -0,0 +1,280 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Units a component quantity can be entered in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuantityUnit {
+    Kg,
+    T,
+    M3,
+    L,
+    M2,
+    M,
+    Mm,
+    Item,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Area,
+    Length,
+    Count,
+}
+
+impl QuantityUnit {
+    pub const ALL: [QuantityUnit; 8] = [
+        QuantityUnit::Kg,
+        QuantityUnit::T,
+        QuantityUnit::M3,
+        QuantityUnit::L,
+        QuantityUnit::M2,
+        QuantityUnit::M,
+        QuantityUnit::Mm,
+        QuantityUnit::Item,
+    ];
+
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            QuantityUnit::Kg | QuantityUnit::T => Dimension::Mass,
+            QuantityUnit::M3 | QuantityUnit::L => Dimension::Volume,
+            QuantityUnit::M2 => Dimension::Area,
+            QuantityUnit::M | QuantityUnit::Mm => Dimension::Length,
+            QuantityUnit::Item => Dimension::Count,
+        }
+    }
+
+    /// Factor to the base unit of the dimension: kg, m3, m2, m or item
+    fn to_base(self) -> f64 {
+        match self {
+            QuantityUnit::T => 1000.,
+            QuantityUnit::L | QuantityUnit::Mm => 0.001,
+            _ => 1.,
+        }
+    }
+
+    /// Reads the declared unit of a material, as stored in `carbon.db`
+    pub fn from_declared(unit: &ec3api::models::Unit) -> Result<Self, ConversionError> {
+        let name = format!("{unit:?}");
+        QuantityUnit::from_str(&name).map_err(|_| ConversionError::UnknownUnit(name))
+    }
+}
+
+impl Display for QuantityUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuantityUnit::Kg => write!(f, "kg"),
+            QuantityUnit::T => write!(f, "t"),
+            QuantityUnit::M3 => write!(f, "m³"),
+            QuantityUnit::L => write!(f, "l"),
+            QuantityUnit::M2 => write!(f, "m²"),
+            QuantityUnit::M => write!(f, "m"),
+            QuantityUnit::Mm => write!(f, "mm"),
+            QuantityUnit::Item => write!(f, "item"),
+        }
+    }
+}
+
+impl FromStr for QuantityUnit {
+    type Err = ();
+
+    /// Accepts the common spellings found in EPDs and bills of quantities, e.g. `m3`, `m³` or `Tonne`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .to_lowercase()
+            .replace('³', "3")
+            .replace('²', "2")
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect();
+        QuantityUnit::ALL
+            .into_iter()
+            .find(|unit| unit.aliases().contains(&normalized.as_str()))
+            .ok_or(())
+    }
+}
+
+impl QuantityUnit {
+    /// Spelling stored in `carbon.db`, read back with [FromStr]
+    pub fn as_str(&self) -> &'static str {
+        self.aliases()[0]
+    }
+
+    /// Lowercase spellings of the unit, without spaces or punctuation, the first one
+    /// being [QuantityUnit::as_str]
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            QuantityUnit::Kg => &["kg", "kilogram", "kilograms"],
+            QuantityUnit::T => &["t", "ton", "tons", "tonne", "tonnes", "metricton"],
+            QuantityUnit::M3 => &["m3", "cubicmeter", "cubicmeters", "cbm"],
+            QuantityUnit::L => &["l", "liter", "liters", "litre", "litres"],
+            QuantityUnit::M2 => &["m2", "sqm", "squaremeter", "squaremeters"],
+            QuantityUnit::M => &["m", "meter", "meters", "lm"],
+            QuantityUnit::Mm => &["mm", "millimeter", "millimeters"],
+            QuantityUnit::Item => &[
+                "item", "items", "unit", "units", "piece", "pieces", "pcs", "each", "ea", "nr",
+            ],
+        }
+    }
+}
+
+/// Material properties needed to convert between dimensions
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversionFactors {
+    /// Density in kg/m³, converts mass and volume
+    pub density: Option<f64>,
+    /// Thickness in m, converts volume and area
+    pub thickness: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConversionError {
+    /// The declared unit of the material is not one of [QuantityUnit]
+    UnknownUnit(String),
+    /// A density or thickness is needed but was not given
+    MissingFactor {
+        factor: &'static str,
+        from: QuantityUnit,
+        to: QuantityUnit,
+    },
+    /// There is no conversion between the dimensions of these units
+    Incompatible {
+        from: QuantityUnit,
+        to: QuantityUnit,
+    },
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::UnknownUnit(unit) => {
+                write!(f, "the declared unit {unit} can not be converted")
+            }
+            ConversionError::MissingFactor { factor, from, to } => {
+                write!(f, "converting {from} to {to} needs a {factor}")
+            }
+            ConversionError::Incompatible { from, to } => {
+                write!(f, "{from} can not be converted to {to}")
+            }
+        }
+    }
+}
+
+/// Converts `value` from one unit to another, using the [ConversionFactors]
+/// when the units measure different dimensions
+pub fn convert(
+    value: f64,
+    from: QuantityUnit,
+    to: QuantityUnit,
+    factors: &ConversionFactors,
+) -> Result<f64, ConversionError> {
+    let base = value * from.to_base();
+    let missing = |factor| ConversionError::MissingFactor { factor, from, to };
+    let density = || {
+        factors
+            .density
+            .filter(|d| *d > 0.)
+            .ok_or(missing("density"))
+    };
+    let thickness = || {
+        factors
+            .thickness
+            .filter(|t| *t > 0.)
+            .ok_or(missing("thickness"))
+    };
+
+    let converted = match (from.dimension(), to.dimension()) {
+        (a, b) if a == b => base,
+        (Dimension::Mass, Dimension::Volume) => base / density()?,
+        (Dimension::Volume, Dimension::Mass) => base * density()?,
+        (Dimension::Volume, Dimension::Area) => base / thickness()?,
+        (Dimension::Area, Dimension::Volume) => base * thickness()?,
+        (Dimension::Mass, Dimension::Area) => base / (density()? * thickness()?),
+        (Dimension::Area, Dimension::Mass) => base * density()? * thickness()?,
+        _ => return Err(ConversionError::Incompatible { from, to }),
+    };
+    Ok(converted / to.to_base())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use QuantityUnit::*;
+
+    fn factors(density: Option<f64>, thickness: Option<f64>) -> ConversionFactors {
+        ConversionFactors { density, thickness }
+    }
+
+    #[test]
+    fn converts_between_dimensions() {
+        let both = factors(Some(2400.), Some(0.2));
+        let cases = [
+            (2.5, T, Kg, 2500.),
+            (1500., L, M3, 1.5),
+            (250., Mm, M, 0.25),
+            (7., Item, Item, 7.),
+            (4800., Kg, M3, 2.),
+            (2., M3, T, 4.8),
+            (10., M2, M3, 2.),
+            (2., M3, M2, 10.),
+            (4800., Kg, M2, 10.),
+            (10., M2, Kg, 4800.),
+        ];
+        for (value, from, to, expected) in cases {
+            let converted = convert(value, from, to, &both).unwrap();
+            assert!(
+                (converted - expected).abs() < 1e-9,
+                "{value} {from} to {to}: {converted} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_missing_factors_and_incompatible_dimensions() {
+        let density = factors(Some(2400.), None);
+        let cases = [
+            (Kg, M3, factors(None, Some(0.2)), Some("density")),
+            (M3, M2, density.clone(), Some("thickness")),
+            (Kg, M2, density.clone(), Some("thickness")),
+            (M2, Kg, factors(None, Some(0.2)), Some("density")),
+            // zero or negative factors count as missing
+            (Kg, M3, factors(Some(0.), None), Some("density")),
+            (M2, M3, factors(None, Some(-1.)), Some("thickness")),
+            (Kg, Item, density.clone(), None),
+            (M, M2, density.clone(), None),
+            (Item, M3, density, None),
+        ];
+        for (from, to, factors, factor) in cases {
+            let expected = match factor {
+                Some(factor) => ConversionError::MissingFactor { factor, from, to },
+                None => ConversionError::Incompatible { from, to },
+            };
+            assert_eq!(convert(1., from, to, &factors), Err(expected));
+        }
+    }
+
+    #[test]
+    fn reads_back_the_stored_spelling() {
+        for unit in QuantityUnit::ALL {
+            assert_eq!(unit.as_str().parse(), Ok(unit));
+        }
+    }
+
+    #[test]
+    fn parses_common_spellings() {
+        let cases = [
+            ("m³", Some(M3)),
+            ("M3", Some(M3)),
+            ("Tonne", Some(T)),
+            ("sq.m", Some(M2)),
+            ("Nr.", Some(Item)),
+            ("ft", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<QuantityUnit>().ok(), expected, "{input}");
+        }
+    }
+}
@@ -7,19 +7,25 @@ use eframe::{
     epaint::Color32,
 };
 use egui_notify::Toast;
-use egui_plot::{Bar, BarChart, Plot};
+use egui_plot::{Bar, BarChart, Legend, Line, Plot, PlotPoints, Points, VLine};
 use shared::{
-    project::{CmpResult, Project},
+    assembly::{AssemblyLibrary, Layer, LayerAmount, LayerMaterial},
+    benchmark::Thresholds,
+    boq::{BoqImport, Confidence},
+    export::ExportFormat,
+    ifc::IfcImport,
+    lifecycle::Module,
+    project::{CmpResult, Component, Project},
+    recommend::Alternatives,
+    scenario::{Substitute, BASELINE},
+    sensitivity::{GwpVariation, Sensitivity, TORNADO_ROWS},
+    uncertainty::{GwpDistribution, Simulation},
+    units::QuantityUnit,
     SortBy, State, Tabs,
 };
 
 /// Renders the view
 pub fn update_view(state: &mut State, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-    let cb = |t: &mut Toast| {
-        //Callback for the toast
-        t.set_closable(true)
-            .set_duration(Some(Duration::from_millis((1000. * 3.5) as u64)));
-    };
     // let loading = state.preload_data(); // do we still need this?
     // Top bar
     let style: Style = (*ctx.style()).clone();
@@ -42,6 +48,49 @@ pub fn update_view(state: &mut State, ctx: &eframe::egui::Context, _frame: &mut
                     .color(style.visuals.text_color());
                 ui.menu_button(logo, |ui| {
                     ui.label("About").on_hover_text("Carbon app - version 0.1");
+                    if ui.button("New project").clicked() {
+                        state.new_project();
+                        state.active_tab = Tabs::Calculate;
+                        ui.close_menu();
+                    }
+                    if ui.button("Open...").clicked() {
+                        open_project(state);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(state.project.is_some(), egui::Button::new("Save"))
+                        .clicked()
+                    {
+                        save_project(state, false);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(state.project.is_some(), egui::Button::new("Save As..."))
+                        .clicked()
+                    {
+                        save_project(state, true);
+                        ui.close_menu();
+                    }
+                    if ui.button("Import quantities...").clicked() {
+                        import_quantities(state);
+                        ui.close_menu();
+                    }
+                    if ui.button("Import IFC model...").clicked() {
+                        import_ifc(state);
+                        ui.close_menu();
+                    }
+                    if ui.button("Assemblies...").clicked() {
+                        open_assemblies(state);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(state.project.is_some(), egui::Button::new("Export..."))
+                        .clicked()
+                    {
+                        export_project(state);
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button("Quit").clicked() {
                         ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
                     }
@@ -67,6 +116,7 @@ pub fn update_view(state: &mut State, ctx: &eframe::egui::Context, _frame: &mut
             // TODO: when toggling dark mode original colors are lost
             // egui::global_dark_light_mode_switch(ui);
             ui.label(format!("{} materials", state.materials.len()));
+            update_progress(ui, state);
         });
     });
     CentralPanel::default().show(ctx, |ui| {
@@ -83,13 +133,801 @@ pub fn update_view(state: &mut State, ctx: &eframe::egui::Context, _frame: &mut
             }
         }
     });
-    if let Some(rx) = &state.job_rx {
-        if rx.try_recv().is_ok() {
-            state.toasts.dismiss_oldest_toast();
-            cb(state.toasts.success("DB Update finished!"))
+    gwp_changes_window(ctx, state);
+    boq_import_window(ctx, state);
+    ifc_import_window(ctx, state);
+    assembly_window(ctx, state);
+    state.toasts.show(ctx);
+}
+
+/// Shows the progress of a running database update with a Cancel button,
+/// and a toast with the summary once it is over
+fn update_progress(ui: &mut egui::Ui, state: &mut State) {
+    let Some(job) = &mut state.update_job else {
+        return;
+    };
+    if let Some(outcome) = job.poll() {
+        state.update_job = None;
+        state.refresh_category_counts();
+        state.load_categories();
+        match outcome {
+            Ok(summary) if summary.failed.is_empty() && !summary.cancelled => toast_done(
+                state
+                    .toasts
+                    .success(format!("DB Update finished! {summary}")),
+            ),
+            Ok(summary) => toast_done(
+                state
+                    .toasts
+                    .warning(format!("DB Update stopped, {summary}")),
+            ),
+            Err(e) => toast_done(state.toasts.error(format!("DB Update failed: {e}"))),
         }
+        return;
+    }
+    ui.separator();
+    let text = match (&job.current, job.total) {
+        _ if job.cancelling => "Cancelling...".to_string(),
+        (_, 0) => "Fetching categories...".to_string(),
+        (Some(category), total) => format!("{category} ({}/{total})", job.done),
+        (None, total) => format!("{}/{total}", job.done),
     };
-    state.toasts.show(ctx);
+    ui.add(
+        egui::ProgressBar::new(job.fraction())
+            .desired_width(240.)
+            .text(text)
+            .animate(true),
+    );
+    ui.label(format!("{} materials written", job.materials));
+    if !job.errors.is_empty() {
+        let errors: Vec<String> = job
+            .errors
+            .iter()
+            .map(|(category, error)| format!("{category}: {error}"))
+            .collect();
+        ui.label(RichText::new(format!("⚠ {} failed", job.errors.len())).color(Color32::LIGHT_RED))
+            .on_hover_text(errors.join("\n"));
+    }
+    if ui
+        .add_enabled(!job.cancelling, egui::Button::new("Cancel"))
+        .clicked()
+    {
+        job.cancel();
+    }
+    // keep polling while nothing else triggers a repaint
+    ui.ctx()
+        .request_repaint_after(std::time::Duration::from_millis(200));
+}
+
+/// Asks for a project file and loads it into [State]
+fn open_project(state: &mut State) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Carbon project", &[shared::project_file::EXTENSION])
+        .pick_file()
+    else {
+        return;
+    };
+    match state.open_project(path) {
+        Ok(()) => {
+            state.active_tab = Tabs::Calculate;
+            toast_done(state.toasts.success("Project opened"));
+        }
+        Err(e) => toast_done(state.toasts.error(format!("Could not open project: {e}"))),
+    }
+}
+
+/// Saves the project to its current path, asking for one if there is none or on `save_as`
+fn save_project(state: &mut State, save_as: bool) {
+    let path = match (&state.project_path, save_as) {
+        (Some(path), false) => Some(path.clone()),
+        _ => rfd::FileDialog::new()
+            .add_filter("Carbon project", &[shared::project_file::EXTENSION])
+            .set_file_name(format!("project.{}", shared::project_file::EXTENSION))
+            .save_file(),
+    };
+    let Some(path) = path else {
+        return;
+    };
+    match state.save_project_as(path) {
+        Ok(()) => toast_done(state.toasts.success("Project saved")),
+        Err(e) => toast_done(state.toasts.error(format!("Could not save project: {e}"))),
+    }
+}
+
+/// Asks for a file and exports the project in the format of its extension
+fn export_project(state: &mut State) {
+    let Some(project) = state.project.as_mut() else {
+        return;
+    };
+    let mut dialog = rfd::FileDialog::new().set_file_name("project.xlsx");
+    for format in ExportFormat::ALL {
+        dialog = dialog.add_filter(format.to_string(), &[format.extension()]);
+    }
+    let Some(path) = dialog.save_file() else {
+        return;
+    };
+    match shared::export::export(project, &path) {
+        Ok(format) => toast_done(
+            state
+                .toasts
+                .success(format!("Project exported as {format}")),
+        ),
+        Err(e) => toast_done(state.toasts.error(format!("Could not export project: {e}"))),
+    }
+}
+
+/// Asks for a CSV or TSV bill of quantities and opens the import window
+fn import_quantities(state: &mut State) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Schedule", &["csv", "tsv", "txt"])
+        .pick_file()
+    else {
+        return;
+    };
+    match BoqImport::open(path) {
+        Ok(import) if import.table.rows.is_empty() => {
+            toast_done(state.toasts.warning("The file has no rows to import"))
+        }
+        Ok(import) => state.boq_import = Some(import),
+        Err(e) => toast_done(state.toasts.error(format!("Could not read the file: {e}"))),
+    }
+}
+
+/// Maps the columns of the imported file, then reviews the matched materials
+fn boq_import_window(ctx: &egui::Context, state: &mut State) {
+    let Some(import) = &mut state.boq_import else {
+        return;
+    };
+    let file = import
+        .path
+        .file_name()
+        .map_or(String::new(), |f| f.to_string_lossy().to_string());
+    let mut open = true;
+    let mut decision = None;
+    egui::Window::new(format!("Import quantities from {file}"))
+        .open(&mut open)
+        .default_size([760., 480.])
+        .show(ctx, |ui| {
+            if import.rows.is_none() {
+                boq_mapping(ui, import);
+            } else {
+                decision = boq_review(ui, import);
+            }
+        });
+    if let Some(add) = decision {
+        if add {
+            let project = state.project.get_or_insert_with(Project::new);
+            let count = import.add_to_project(project);
+            state.active_tab = Tabs::Calculate;
+            toast_done(
+                state
+                    .toasts
+                    .success(format!("{count} components added to the project")),
+            );
+        }
+        open = false;
+    }
+    if !open {
+        state.boq_import = None;
+    }
+}
+
+/// Column mapping step, with a preview of the first rows
+fn boq_mapping(ui: &mut egui::Ui, import: &mut BoqImport) {
+    ui.label("Choose the columns of the file holding each value.");
+    let headers = import.table.headers.clone();
+    let column = |ui: &mut egui::Ui, label: &str, value: &mut Option<usize>| {
+        ui.label(label);
+        let selected = value
+            .and_then(|i| headers.get(i))
+            .map_or("None", |h| h.as_str());
+        ComboBox::from_id_source(label)
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(value, None, "None");
+                for (i, header) in headers.iter().enumerate() {
+                    ui.selectable_value(value, Some(i), header);
+                }
+            });
+        ui.end_row();
+    };
+    let mapping = &mut import.mapping;
+    egui::Grid::new("boq-mapping")
+        .num_columns(2)
+        .show(ui, |ui| {
+            column(ui, "Description", &mut mapping.description);
+            column(ui, "Quantity", &mut mapping.quantity);
+            column(ui, "Unit", &mut mapping.unit);
+            column(ui, "Category", &mut mapping.category);
+        });
+    ui.separator();
+    ui.label(format!(
+        "{} rows, the first ones are:",
+        import.table.rows.len()
+    ));
+    ScrollArea::both().max_height(200.).show(ui, |ui| {
+        egui::Grid::new("boq-preview")
+            .striped(true)
+            .num_columns(headers.len())
+            .show(ui, |ui| {
+                for header in &headers {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for row in import.table.rows.iter().take(5) {
+                    for cell in row {
+                        ui.label(fit_to_width(cell, 30));
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+    ui.separator();
+    if ui
+        .add_enabled(
+            import.mapping.is_complete(),
+            egui::Button::new("Match materials"),
+        )
+        .on_disabled_hover_text("A description and a quantity column are needed")
+        .clicked()
+    {
+        if let Err(e) = import.match_rows() {
+            eprintln!("ERROR: matching the imported rows: {e}");
+        }
+    }
+}
+
+/// Review step, returns `Some(true)` to add the included rows and `Some(false)` to cancel
+fn boq_review(ui: &mut egui::Ui, import: &mut BoqImport) -> Option<bool> {
+    let rows = import.rows.as_mut()?;
+    let count = |confidence| rows.iter().filter(|r| r.confidence() == confidence).count();
+    ui.label(format!(
+        "{} rows matched, {} to review and {} without a match. \
+         Rows to review are only added once ticked.",
+        count(Confidence::High),
+        count(Confidence::Low),
+        count(Confidence::Unmatched)
+    ));
+    ui.separator();
+    ScrollArea::vertical().max_height(360.).show(ui, |ui| {
+        egui::Grid::new("boq-review")
+            .striped(true)
+            .num_columns(5)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.strong("Line");
+                ui.strong("Description");
+                ui.strong("Quantity");
+                ui.strong("Material");
+                ui.end_row();
+                for row in rows.iter_mut() {
+                    ui.add_enabled(
+                        row.can_include(),
+                        egui::Checkbox::without_text(&mut row.include),
+                    );
+                    ui.label(row.line.to_string());
+                    ui.label(fit_to_width(&row.description, 40))
+                        .on_hover_text(&row.description);
+                    match row.quantity {
+                        Some(quantity) => ui.label(format!("{quantity} {}", row.unit_text)),
+                        None => ui.label(RichText::new("not a number").color(Color32::LIGHT_RED)),
+                    };
+                    ui.horizontal(|ui| {
+                        let color = match row.confidence() {
+                            Confidence::High => Color32::LIGHT_GREEN,
+                            Confidence::Low => Color32::LIGHT_YELLOW,
+                            Confidence::Unmatched => Color32::LIGHT_RED,
+                        };
+                        ui.label(RichText::new("●").color(color));
+                        let selected = row
+                            .selected()
+                            .map_or("No match".to_string(), |s| s.candidate.name());
+                        let previous = row.selected;
+                        ComboBox::from_id_source(("boq-candidate", row.line))
+                            .width(260.)
+                            .selected_text(fit_to_width(&selected, 40))
+                            .show_ui(ui, |ui| {
+                                for (i, scored) in row.candidates.iter().enumerate() {
+                                    ui.selectable_value(
+                                        &mut row.selected,
+                                        Some(i),
+                                        format!(
+                                            "{} ({:.0}%)",
+                                            scored.candidate.name(),
+                                            scored.score * 100.
+                                        ),
+                                    )
+                                    .on_hover_text(scored.candidate.category());
+                                }
+                            });
+                        // picking a material is reviewing it
+                        if row.selected != previous {
+                            row.include = row.can_include();
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+    });
+    ui.separator();
+    let included = rows.iter().filter(|r| r.include).count();
+    let mut result = None;
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(
+                included > 0,
+                egui::Button::new(format!("Add {included} components")),
+            )
+            .clicked()
+        {
+            result = Some(true);
+        }
+        if ui.button("Back to columns").clicked() {
+            import.rows = None;
+        }
+        if ui.button("Cancel").clicked() {
+            result = Some(false);
+        }
+    });
+    result
+}
+
+/// Asks for an IFC model and opens the window mapping its materials to categories
+fn import_ifc(state: &mut State) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("IFC model", &["ifc"])
+        .pick_file()
+    else {
+        return;
+    };
+    match IfcImport::open(path) {
+        Ok(import) if import.rows.is_empty() => toast_done(
+            state
+                .toasts
+                .warning("The model has no elements with a material"),
+        ),
+        Ok(import) => state.ifc_import = Some(import),
+        Err(e) => toast_done(
+            state
+                .toasts
+                .error(format!("Could not import the model: {e}")),
+        ),
+    }
+}
+
+/// Maps the materials of the imported model to categories. Mappings are saved when
+/// the materials are added, so the next model with the same materials is mapped already.
+fn ifc_import_window(ctx: &egui::Context, state: &mut State) {
+    let Some(import) = &mut state.ifc_import else {
+        return;
+    };
+    let file = import
+        .path
+        .file_name()
+        .map_or(String::new(), |f| f.to_string_lossy().to_string());
+    let mut categories: Vec<&String> = state.category_counts.keys().collect();
+    categories.sort_unstable();
+    let mut open = true;
+    let mut decision = None;
+    egui::Window::new(format!("Import {file}"))
+        .open(&mut open)
+        .default_size([760., 480.])
+        .show(ctx, |ui| {
+            let mapped = import.rows.iter().filter(|r| r.mapped).count();
+            ui.label(format!(
+                "{schema} model with {count} materials, {mapped} mapped by previous imports. \
+                 Each material is added as the generic material of its category.",
+                schema = import.takeoff.schema,
+                count = import.rows.len(),
+            ));
+            if !import.takeoff.unassigned.is_empty() {
+                ui.label(
+                    RichText::new(format!(
+                        "{} elements with quantities have no material and are left out.",
+                        import.takeoff.unassigned.len()
+                    ))
+                    .color(Color32::LIGHT_YELLOW),
+                )
+                .on_hover_text(global_ids_text(&import.takeoff.unassigned));
+            }
+            ui.separator();
+            ScrollArea::vertical().max_height(360.).show(ui, |ui| {
+                egui::Grid::new("ifc-review")
+                    .striped(true)
+                    .num_columns(6)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        ui.strong("IFC material");
+                        ui.strong("Elements");
+                        ui.strong("Volume (m³)");
+                        ui.strong("Area (m²)");
+                        ui.strong("Category");
+                        ui.end_row();
+                        for (i, row) in import.rows.iter_mut().enumerate() {
+                            ui.add_enabled(
+                                row.category.is_some(),
+                                egui::Checkbox::without_text(&mut row.include),
+                            );
+                            ui.label(fit_to_width(&row.takeoff.material, 40))
+                                .on_hover_text(&row.takeoff.material);
+                            ui.label(row.takeoff.elements.len().to_string())
+                                .on_hover_text(global_ids_text(&row.takeoff.elements));
+                            let quantity = |value: Option<f64>| {
+                                value.map_or("-".into(), |v| format!("{v:.2}"))
+                            };
+                            ui.label(quantity(row.takeoff.volume));
+                            ui.label(quantity(row.takeoff.area));
+                            let selected = match (&row.category, row.mapped) {
+                                (Some(category), true) => category.clone(),
+                                (Some(category), false) => format!("{category}?"),
+                                (None, _) => "Choose a category".to_string(),
+                            };
+                            let previous = row.category.clone();
+                            ComboBox::from_id_source(("ifc-category", i))
+                                .width(220.)
+                                .selected_text(fit_to_width(&selected, 35))
+                                .show_ui(ui, |ui| {
+                                    for category in &categories {
+                                        ui.selectable_value(
+                                            &mut row.category,
+                                            Some(category.to_string()),
+                                            category.as_str(),
+                                        );
+                                    }
+                                });
+                            // picking a category is reviewing it
+                            if row.category != previous {
+                                row.mapped = true;
+                                row.include = true;
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+            ui.separator();
+            let included = import.rows.iter().filter(|r| r.include).count();
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        included > 0,
+                        egui::Button::new(format!("Add {included} components")),
+                    )
+                    .clicked()
+                {
+                    decision = Some(true);
+                }
+                if ui.button("Cancel").clicked() {
+                    decision = Some(false);
+                }
+            });
+        });
+    if let Some(add) = decision {
+        if add {
+            let project = state.project.get_or_insert_with(Project::new);
+            match import.add_to_project(project) {
+                Ok(count) => {
+                    state.active_tab = Tabs::Calculate;
+                    toast_done(
+                        state
+                            .toasts
+                            .success(format!("{count} components added to the project")),
+                    );
+                }
+                Err(e) => toast_done(state.toasts.error(format!("Could not add the model: {e}"))),
+            }
+        }
+        open = false;
+    }
+    if !open {
+        state.ifc_import = None;
+    }
+}
+
+fn open_assemblies(state: &mut State) {
+    match AssemblyLibrary::open() {
+        Ok(library) => state.assembly_library = Some(library),
+        Err(e) => toast_done(
+            state
+                .toasts
+                .error(format!("Could not load the assemblies: {e}")),
+        ),
+    }
+}
+
+/// Library of assemblies: edits their layers, saves them to the database
+/// and adds them to the project with an area
+fn assembly_window(ctx: &egui::Context, state: &mut State) {
+    let Some(library) = &mut state.assembly_library else {
+        return;
+    };
+    let study_period = state
+        .project
+        .as_ref()
+        .map_or(shared::lifecycle::STUDY_PERIOD, |p| p.study_period);
+    let mut categories: Vec<&String> = state.category_counts.keys().collect();
+    categories.sort_unstable();
+    let mut open = true;
+    let mut changed = false;
+    let mut result: Option<Result<String, String>> = None;
+    let mut add_to_project = false;
+    egui::Window::new("Assemblies")
+        .open(&mut open)
+        .default_size([760., 420.])
+        .show(ctx, |ui| {
+            ui.horizontal_top(|ui| {
+                ui.vertical(|ui| {
+                    ui.set_width(180.);
+                    if ui.button("New assembly").clicked() {
+                        library.draft = Default::default();
+                        changed = true;
+                    }
+                    ui.separator();
+                    let mut edit = None;
+                    ScrollArea::vertical()
+                        .id_source("assembly-list")
+                        .max_height(340.)
+                        .show(ui, |ui| {
+                            for (i, assembly) in library.assemblies.iter().enumerate() {
+                                let selected =
+                                    library.draft.id.is_some() && library.draft.id == assembly.id;
+                                if ui.selectable_label(selected, &assembly.name).clicked() {
+                                    edit = Some(i);
+                                }
+                            }
+                        });
+                    if let Some(i) = edit {
+                        library.edit(i, study_period);
+                    }
+                });
+                ui.separator();
+                ui.vertical(|ui| {
+                    let draft = &mut library.draft;
+                    egui::Grid::new("assembly-fields")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Name");
+                            ui.text_edit_singleline(&mut draft.name);
+                            ui.end_row();
+                            ui.label("Description");
+                            ui.text_edit_singleline(&mut draft.description);
+                            ui.end_row();
+                        });
+                    ui.add_space(4.);
+                    ui.strong("Layers per m², from the outside in");
+                    changed |= assembly_layers(ui, &mut draft.layers);
+                    ComboBox::from_id_source("assembly-add-layer")
+                        .width(220.)
+                        .selected_text("Add a generic layer")
+                        .show_ui(ui, |ui| {
+                            for category in &categories {
+                                if ui.selectable_label(false, category.as_str()).clicked() {
+                                    draft.layers.push(Layer {
+                                        material: LayerMaterial::Generic(category.to_string()),
+                                        amount: LayerAmount::default(),
+                                    });
+                                    changed = true;
+                                }
+                            }
+                        });
+                    ui.label(RichText::new("Or select a material and use Add to assembly").weak());
+                    ui.separator();
+                    ui.strong(match library.gwp_per_m2 {
+                        Some(gwp) => format!("{gwp:.2} KgCO2e/m², modules A-C"),
+                        None => "GWP per m² not available".to_string(),
+                    });
+                    ui.horizontal(|ui| {
+                        let draft = &library.draft;
+                        if ui
+                            .add_enabled(
+                                !draft.name.trim().is_empty() && !draft.layers.is_empty(),
+                                egui::Button::new("Save"),
+                            )
+                            .on_disabled_hover_text("An assembly needs a name and layers")
+                            .clicked()
+                        {
+                            let name = draft.name.clone();
+                            result = Some(
+                                library
+                                    .save()
+                                    .map(|_| format!("Saved {name}"))
+                                    .map_err(|e| format!("Could not save {name}: {e}")),
+                            );
+                        }
+                        if library.draft.id.is_some() && ui.button("Delete").clicked() {
+                            result = Some(
+                                library
+                                    .delete()
+                                    .map(|_| "Assembly deleted".to_string())
+                                    .map_err(|e| format!("Could not delete the assembly: {e}")),
+                            );
+                        }
+                        ui.separator();
+                        ui.add(
+                            DragValue::new(&mut library.area)
+                                .clamp_range(0.0..=f64::MAX)
+                                .suffix(" m²"),
+                        );
+                        add_to_project = ui
+                            .add_enabled(
+                                !library.draft.layers.is_empty(),
+                                egui::Button::new("Add to project"),
+                            )
+                            .clicked();
+                    });
+                });
+            });
+        });
+    if changed {
+        library.calculate(study_period);
+    }
+    if add_to_project {
+        let project = state.project.get_or_insert_with(Project::new);
+        result = Some(match project.add_assembly(&library.draft, library.area) {
+            Ok(()) => {
+                project.calculate();
+                state.active_tab = Tabs::Calculate;
+                Ok(format!("Added {} to the project", library.draft.name))
+            }
+            Err(e) => Err(format!("Could not add the assembly: {e}")),
+        });
+    }
+    match result {
+        Some(Ok(message)) => toast_done(state.toasts.success(message)),
+        Some(Err(message)) => toast_done(state.toasts.error(message)),
+        None => {}
+    }
+    if !open {
+        state.assembly_library = None;
+    }
+}
+
+/// Editable layers of an assembly, returns whether they changed
+fn assembly_layers(ui: &mut egui::Ui, layers: &mut Vec<Layer>) -> bool {
+    let mut changed = false;
+    let mut remove = None;
+    let mut move_up = None;
+    egui::Grid::new("assembly-layers")
+        .striped(true)
+        .num_columns(4)
+        .show(ui, |ui| {
+            for (i, layer) in layers.iter_mut().enumerate() {
+                let name = layer.material.name();
+                ui.label(fit_to_width(&name, 35)).on_hover_text(&name);
+                let thickness = matches!(layer.amount, LayerAmount::Thickness { .. });
+                ComboBox::from_id_source(("layer-kind", i))
+                    .width(90.)
+                    .selected_text(if thickness { "Thickness" } else { "Per m²" })
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(thickness, "Thickness").clicked() && !thickness {
+                            layer.amount = LayerAmount::default();
+                            changed = true;
+                        }
+                        if ui.selectable_label(!thickness, "Per m²").clicked() && thickness {
+                            layer.amount = LayerAmount::PerArea {
+                                quantity: 1.,
+                                unit: QuantityUnit::M2,
+                            };
+                            changed = true;
+                        }
+                    });
+                ui.horizontal(|ui| match &mut layer.amount {
+                    LayerAmount::Thickness { mm } => {
+                        changed |= ui
+                            .add(DragValue::new(mm).clamp_range(0.0..=f64::MAX).suffix(" mm"))
+                            .changed();
+                    }
+                    LayerAmount::PerArea { quantity, unit } => {
+                        changed |= ui
+                            .add(DragValue::new(quantity).clamp_range(0.0..=f64::MAX))
+                            .changed();
+                        ComboBox::from_id_source(("layer-unit", i))
+                            .width(50.)
+                            .selected_text(unit.to_string())
+                            .show_ui(ui, |ui| {
+                                for option in QuantityUnit::ALL {
+                                    changed |= ui
+                                        .selectable_value(unit, option, option.to_string())
+                                        .changed();
+                                }
+                            });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if i > 0 && ui.small_button("⏶").on_hover_text("Move up").clicked() {
+                        move_up = Some(i);
+                    }
+                    if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+    if let Some(i) = move_up {
+        layers.swap(i - 1, i);
+    }
+    if let Some(i) = remove {
+        layers.remove(i);
+        changed = true;
+    }
+    changed
+}
+
+/// Assemblies of the project with their area, and their layers
+fn project_assemblies(ui: &mut egui::Ui, project: &mut Project) -> bool {
+    let mut changed = false;
+    let mut remove = None;
+    for (i, assembly) in project.assemblies.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.strong(&assembly.name);
+            changed |= ui
+                .add(
+                    DragValue::new(&mut assembly.area)
+                        .clamp_range(0.0..=f64::MAX)
+                        .suffix(" m²"),
+                )
+                .changed();
+            ui.label(format!("{:.2} KgCO2e", assembly.calculated));
+            if let Some(gwp) = assembly.gwp_per_m2() {
+                ui.label(RichText::new(format!("{gwp:.2} KgCO2e/m²")).weak());
+            }
+            if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                remove = Some(i);
+            }
+        });
+        egui::CollapsingHeader::new("Layers")
+            .id_source(("project-assembly", i))
+            .show(ui, |ui| {
+                egui::Grid::new(("project-assembly-layers", i))
+                    .striped(true)
+                    .num_columns(4)
+                    .show(ui, |ui| {
+                        for layer in &assembly.layers {
+                            let component = &layer.component;
+                            ui.label(component.material.get_name());
+                            ui.label(layer.amount.to_string());
+                            ui.label(format!(
+                                "{:.2} {}",
+                                component.quantity,
+                                component.unit.map_or(String::new(), |u| u.to_string())
+                            ));
+                            match &component.conversion_error {
+                                Some(e) => ui
+                                    .label(RichText::new("⚠").color(Color32::LIGHT_RED))
+                                    .on_hover_text(format!("Not calculated: {e}")),
+                                None => ui.label(format!("{:.2} KgCO2e", component.calculated)),
+                            };
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+    if let Some(i) = remove {
+        project.assemblies.remove(i);
+        changed = true;
+    }
+    changed
+}
+
+/// GlobalIds of IFC elements for a hover text, the first ones of long lists
+fn global_ids_text(ids: &[String]) -> String {
+    const SHOWN: usize = 20;
+    let mut text = ids
+        .iter()
+        .take(SHOWN)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if ids.len() > SHOWN {
+        text.push_str(&format!("\n… and {} more", ids.len() - SHOWN));
+    }
+    text
+}
+
+/// Makes a toast closable and hides it after a few seconds
+fn toast_done(t: &mut Toast) {
+    t.set_closable(true)
+        .set_duration(Some(Duration::from_millis((1000. * 3.5) as u64)));
 }
 
 fn categories_page(state: &mut State, ui: &mut egui::Ui) {
@@ -105,11 +943,92 @@ fn categories_page(state: &mut State, ui: &mut egui::Ui) {
         ui.heading(RichText::new(&state.selected_category));
     });
     ui.add_space(2.0);
-    ui.indent("s-category", |_ui| {
-        // TODO: here render category
+    let Some(detail) = &state.category_detail else {
+        ui.label("This category is not in the local database yet.");
+        return;
+    };
+    ui.indent("s-category", |ui| {
+        ui.label(&detail.category.description);
+        ui.add_space(2.0);
+        let unit = format!(
+            "{} {:?}",
+            detail.declared_unit.value, detail.declared_unit.unit
+        );
+        ui.label(format!("Declared unit: {unit}"));
+        ui.add_space(4.0);
+        let stats = &detail.stats;
+        if stats.count == 0 {
+            ui.label("No materials of this category are in the local database.");
+            return;
+        }
+        egui::Grid::new("category-stats")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Materials");
+                ui.label(stats.count.to_string());
+                ui.end_row();
+                for (label, value) in [
+                    ("Min", stats.min),
+                    ("P10", stats.p10),
+                    ("P25", stats.p25),
+                    ("Median", stats.median),
+                    ("Mean", stats.mean),
+                    ("P75", stats.p75),
+                    ("P90", stats.p90),
+                    ("Max", stats.max),
+                ] {
+                    ui.label(label);
+                    ui.monospace(format!("{value:.2} KgCO2e / {unit}"));
+                    ui.end_row();
+                }
+            });
+        ui.add_space(4.0);
+        render_histogram(&detail.gwps, stats, ui);
     });
 }
 
+/// Plots how many materials fall in each GWP range, with the quartiles marked
+fn render_histogram(gwps: &[f64], stats: &shared::material_db::CategoryStats, ui: &mut egui::Ui) {
+    // square-root rule, the bins stay readable for large categories
+    let bins = ((gwps.len() as f64).sqrt().ceil() as usize).clamp(1, 30);
+    let width = match stats.max - stats.min {
+        range if range > 0. => range / bins as f64,
+        _ => 1.,
+    };
+    let mut counts = vec![0usize; bins];
+    for gwp in gwps {
+        let bin = (((gwp - stats.min) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    let bars: Vec<Bar> = counts
+        .iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let start = stats.min + i as f64 * width;
+            Bar::new(start + width / 2., *count as f64)
+                .width(width * 0.95)
+                .name(format!("{start:.1} - {:.1}", start + width))
+        })
+        .collect();
+    Plot::new("category-histogram")
+        .height(220.)
+        .legend(Legend::default())
+        .allow_scroll(false)
+        .x_axis_label("GWP KgCO2e")
+        .y_axis_label("Materials")
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(bars).name("Materials"));
+            for (name, value) in [
+                ("P25", stats.p25),
+                ("Median", stats.median),
+                ("P75", stats.p75),
+            ] {
+                plot_ui.vline(VLine::new(value).name(name));
+            }
+        });
+}
+
 fn welcome_window(ctx: &egui::Context, state: &mut State) {
     egui::Window::new("Welcome!")
             .collapsible(false)
@@ -138,55 +1057,134 @@ fn calculate_page(state: &mut State, ui: &mut egui::Ui) {
     if state.project.is_none() {
         ui.label("Wow, such emptiness here!\nStart a new project?");
         if ui.button("New project").clicked() {
-            state.project = Some(Project::new());
+            state.new_project();
+        }
+        if ui.button("Open project...").clicked() {
+            open_project(state);
         }
         return;
     }
+    if state.selected_component.is_some() {
+        component_panel(state, ui);
+    }
     ScrollArea::vertical()
         .auto_shrink([false; 2])
         .show(ui, |ui| {
             let mut grid_updated = false;
             let project = state.project.as_mut().unwrap();
             ui.add_space(10.);
+            ui.horizontal(|ui| {
+                ui.label("Reference study period:");
+                if ui
+                    .add(
+                        DragValue::new(&mut project.study_period)
+                            .clamp_range(1.0..=200.0)
+                            .suffix(" years"),
+                    )
+                    .changed()
+                {
+                    grid_updated = true;
+                }
+            });
+            benchmark_settings(project, ui);
+            scenario_bar(
+                ui,
+                project,
+                &mut state.active_scenario,
+                &mut state.scenario_input,
+            );
             egui::Grid::new("my_grid")
-                .num_columns(3)
+                .num_columns(12)
                 .max_col_width(200.)
                 .min_row_height(40.)
-                .spacing([40.0, 4.0])
+                .spacing([24.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
                     ui.label("Material");
                     ui.label("Quantity");
                     ui.label("Unit");
-                    ui.label("GWP (KgCO2e)");
-                    ui.label("-");
+                    for module in Module::ALL {
+                        ui.label(module.to_string());
+                    }
+                    ui.label("Total A-C (KgCO2e)");
+                    ui.label("Rank").on_hover_text(
+                        "Percentile of the GWP within the category, lower is better",
+                    );
+                    ui.label("");
                     ui.end_row();
-                    for comp in project.components.iter_mut() {
-                        ui.label(comp.material.get_name());
+                    for (i, comp) in project.components.iter_mut().enumerate() {
+                        if let Some(scenario) = &state.active_scenario {
+                            let selected = state
+                                .selected
+                                .as_ref()
+                                .map(|m| (m.name.as_str(), || Substitute::ec3(m.clone())));
+                            let thresholds = &project.thresholds;
+                            if scenario_row(
+                                ui,
+                                comp,
+                                scenario,
+                                project.study_period,
+                                thresholds,
+                                selected,
+                            ) {
+                                state.selected_component = Some(i);
+                            }
+                            ui.end_row();
+                            continue;
+                        }
+                        let name = ui.label(comp.material.get_name());
+                        if !comp.ifc_elements.is_empty() {
+                            name.on_hover_text(format!(
+                                "Quantity of {} IFC elements:\n{}",
+                                comp.ifc_elements.len(),
+                                global_ids_text(&comp.ifc_elements)
+                            ));
+                        }
                         let value = DragValue::new(&mut comp.quantity);
                         if ui.add(value).changed() {
-                            comp.calculate();
                             grid_updated = true;
                         }
-                        ui.label(format!("{unit:?}", unit = &comp.material.get_unit().unit));
-                        ui.label(format!("{tots:.2}", tots = &comp.calculated));
-                        match comp.cmp_to_average() {
-                            CmpResult::Smaller => ui
-                                .label(RichText::new("↓").color(Color32::LIGHT_GREEN))
-                                .on_hover_text(
-                                    "This material has a smaller GWP than the category average!",
-                                ),
-                            CmpResult::Greater => ui
-                                .label(RichText::new("↑").color(Color32::LIGHT_RED))
-                                .on_hover_text(
-                                    "This material has a greater GWP than the category average.",
-                                ),
-                            CmpResult::AlmostEqual => ui
-                                .label(RichText::new("=").color(Color32::LIGHT_YELLOW))
-                                .on_hover_text(
-                                    "This material has about the same GWP as the category average.",
-                                ),
+                        let declared = format!("{:?}", &comp.material.get_unit().unit);
+                        ComboBox::from_id_source(("unit", i))
+                            .width(60.)
+                            .selected_text(comp.unit.map_or(declared.clone(), |u| u.to_string()))
+                            .show_ui(ui, |ui| {
+                                grid_updated |= ui
+                                    .selectable_value(
+                                        &mut comp.unit,
+                                        None,
+                                        format!("{declared} (declared)"),
+                                    )
+                                    .changed();
+                                for unit in QuantityUnit::ALL {
+                                    grid_updated |= ui
+                                        .selectable_value(
+                                            &mut comp.unit,
+                                            Some(unit),
+                                            unit.to_string(),
+                                        )
+                                        .changed();
+                                }
+                            });
+                        for module in Module::ALL {
+                            ui.label(format!("{value:.2}", value = comp.modules.get(module)));
+                        }
+                        match &comp.conversion_error {
+                            Some(e) => ui
+                                .label(RichText::new("⚠").color(Color32::LIGHT_RED))
+                                .on_hover_text(format!("Not calculated: {e}")),
+                            None => ui.label(format!("{tots:.2}", tots = &comp.calculated)),
                         };
+                        if benchmark_label(ui, comp, &project.thresholds) {
+                            state.selected_component = Some(i);
+                        }
+                        if ui
+                            .small_button("⚙")
+                            .on_hover_text("Edit conversion factors and life-cycle assumptions")
+                            .clicked()
+                        {
+                            state.selected_component = Some(i);
+                        }
                         ui.end_row();
                     } // end of iterating through components in project
 
@@ -208,6 +1206,11 @@ fn calculate_page(state: &mut State, ui: &mut egui::Ui) {
                     ui.label("Select a category to add a generic component");
                     ui.end_row();
                 });
+            if !project.assemblies.is_empty() {
+                ui.add_space(8.);
+                ui.heading("Assemblies");
+                grid_updated |= project_assemblies(ui, project);
+            }
             if grid_updated {
                 project.calculate();
             };
@@ -223,6 +1226,789 @@ fn calculate_page(state: &mut State, ui: &mut egui::Ui) {
             };
             ui.add_space(4.);
             ui.strong(total);
+            ui.label(format!(
+                "Module D (beyond the system boundary): {d:.2} KgCO2e",
+                d = project.modules.d
+            ));
+            ui.add_space(4.);
+            render_modules_chart(project, ui);
+            ui.add_space(8.);
+            egui::CollapsingHeader::new("Uncertainty").show(ui, |ui| {
+                uncertainty_section(project, &mut state.simulation, ui)
+            });
+            egui::CollapsingHeader::new("Sensitivity")
+                .show(ui, |ui| sensitivity_section(project, ui));
+            if !project.scenarios.is_empty() {
+                ui.add_space(8.);
+                ui.heading("Scenarios");
+                scenario_comparison(project, ui);
+            }
+            if !project.history.is_empty() {
+                ui.add_space(8.);
+                egui::CollapsingHeader::new(format!("Substitutions ({})", project.history.len()))
+                    .show(ui, |ui| {
+                        egui::Grid::new("history-grid")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for s in project.history.iter().rev() {
+                                    ui.label(shared::material_db::format_date(s.at));
+                                    ui.label(format!("{} → {}", s.from, s.to));
+                                    ui.label(format!("-{:.2} KgCO2e", s.savings));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            }
+        });
+}
+
+/// Settings and results of the Monte Carlo simulation of the project total
+fn uncertainty_section(
+    project: &mut Project,
+    simulation: &mut Option<Simulation>,
+    ui: &mut egui::Ui,
+) {
+    let mut run = false;
+    ui.horizontal(|ui| {
+        let settings = &mut project.uncertainty;
+        ui.label("EPD range ±");
+        ui.add(
+            DragValue::new(&mut settings.epd_range)
+                .clamp_range(0.0..=100.0)
+                .suffix(" %"),
+        )
+        .on_hover_text(
+            "Spread of the GWP of specific materials, generic materials vary like their category",
+        );
+        ui.label("Iterations");
+        ui.add(
+            DragValue::new(&mut settings.iterations)
+                .clamp_range(100..=1_000_000)
+                .speed(100),
+        );
+        ui.label("Seed");
+        ui.add(DragValue::new(&mut settings.seed));
+        run = ui.button("Run").clicked();
+    });
+    if run {
+        *simulation = Some(Simulation::run(project, project.uncertainty));
+    }
+    let Some(simulation) = simulation else {
+        return;
+    };
+    if simulation.settings != project.uncertainty {
+        ui.weak("The settings changed since the last run");
+    }
+    ui.label(format!(
+        "P10 {:.2}  P50 {:.2}  P90 {:.2} KgCO2e, mean {:.2} KgCO2e",
+        simulation.p10, simulation.p50, simulation.p90, simulation.mean
+    ));
+    let bars = simulation
+        .histogram(shared::uncertainty::BINS)
+        .into_iter()
+        .map(|bin| {
+            Bar::new((bin.start + bin.end) / 2., bin.count as f64)
+                .width(bin.end - bin.start)
+                .name(format!("{:.0} to {:.0} KgCO2e", bin.start, bin.end))
+        })
+        .collect();
+    Plot::new("simulation-plot")
+        .height(200.)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(bars).name("Iterations"));
+            for (name, value) in [
+                ("P10", simulation.p10),
+                ("P50", simulation.p50),
+                ("P90", simulation.p90),
+            ] {
+                plot_ui.vline(VLine::new(value).name(name));
+            }
+        });
+}
+
+/// Inputs ranked by their effect on the project total, as a tornado chart
+fn sensitivity_section(project: &mut Project, ui: &mut egui::Ui) {
+    let settings = &mut project.sensitivity;
+    ui.horizontal(|ui| {
+        ui.label("Vary inputs by ±");
+        ui.add(
+            DragValue::new(&mut settings.percent)
+                .clamp_range(0.0..=100.0)
+                .suffix(" %"),
+        );
+        ui.label("GWP:");
+        ComboBox::from_id_source("gwp-variation")
+            .selected_text(settings.gwp.to_string())
+            .show_ui(ui, |ui| {
+                for variation in [GwpVariation::Percent, GwpVariation::Quartiles] {
+                    ui.selectable_value(&mut settings.gwp, variation, variation.to_string());
+                }
+            });
+    });
+    let sensitivity = Sensitivity::analyze(project, project.sensitivity);
+    if sensitivity.swings.is_empty() {
+        ui.weak("No input changes the total");
+        return;
+    }
+    let swings = &sensitivity.swings[..TORNADO_ROWS.min(sensitivity.swings.len())];
+    let labels: Vec<String> = swings.iter().rev().map(|s| s.label()).collect();
+    // the largest effect is drawn at the top
+    let bars = |total: fn(&shared::sensitivity::Swing) -> f64| {
+        swings
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(y, s)| {
+                Bar::new(y as f64, total(s) - sensitivity.baseline)
+                    .width(0.7)
+                    .name(format!(
+                        "{}\n{:.2} to {:.2}: {:.2} to {:.2} KgCO2e",
+                        s.label(),
+                        s.low_input,
+                        s.high_input,
+                        s.low,
+                        s.high
+                    ))
+            })
+            .collect()
+    };
+    let low = BarChart::new(bars(|s| s.low))
+        .horizontal()
+        .name("Low input")
+        .color(Color32::from_rgb(90, 129, 255));
+    let high = BarChart::new(bars(|s| s.high))
+        .horizontal()
+        .name("High input")
+        .color(Color32::from_rgb(230, 120, 110));
+    ui.label(format!(
+        "Change of the total of {:.2} KgCO2e, one input at a time",
+        sensitivity.baseline
+    ));
+    Plot::new("tornado-plot")
+        .height(40. + 24. * swings.len() as f32)
+        .legend(Legend::default())
+        .y_axis_width(30)
+        .y_axis_formatter(move |mark, _, _| {
+            let index =
+                (mark.value >= 0. && mark.value.fract() == 0.).then_some(mark.value as usize);
+            index
+                .and_then(|i| labels.get(i))
+                .map_or(String::new(), |label| fit_to_width(label, 30).to_string())
+        })
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(low);
+            plot_ui.bar_chart(high);
+        });
+}
+
+/// Row of the grid for a component in a scenario, where its material and quantity are
+/// overridden instead of changed. `selected` is the name of the material selected in the
+/// Materials tab and its substitute. Returns true when the component panel should open.
+fn scenario_row(
+    ui: &mut egui::Ui,
+    comp: &mut Component,
+    scenario: &str,
+    study_period: f64,
+    thresholds: &Thresholds,
+    selected: Option<(&str, impl FnOnce() -> Substitute)>,
+) -> bool {
+    let overrides = comp.overrides.get(scenario);
+    let substituted = overrides.is_some_and(|o| o.substitute.is_some());
+    let quantity_changed = overrides.is_some_and(|o| o.quantity.is_some());
+    let name = comp.scenario_material(scenario).get_name().to_string();
+    let mut substitute = None;
+    let mut reset_quantity = false;
+    ui.horizontal(|ui| {
+        let name = RichText::new(name);
+        ui.label(match substituted {
+            true => name.color(Color32::LIGHT_BLUE),
+            false => name,
+        });
+        ui.menu_button("⇄", |ui| {
+            if let Some((name, selected)) = selected {
+                if ui.button(format!("Use {name}")).clicked() {
+                    substitute = Some(Some(selected()));
+                    ui.close_menu();
+                }
+            }
+            let category = comp.material.get_category();
+            if ui.button(format!("Use generic {category}")).clicked() {
+                substitute = Some(Some(Substitute::generic(category)));
+                ui.close_menu();
+            }
+            if substituted && ui.button("Use baseline material").clicked() {
+                substitute = Some(None);
+                ui.close_menu();
+            }
+            if quantity_changed && ui.button("Use baseline quantity").clicked() {
+                reset_quantity = true;
+                ui.close_menu();
+            }
+        })
+        .response
+        .on_hover_text(
+            "Change the material in this scenario, select a material in the Materials tab to use it",
+        );
+    });
+    let mut quantity = comp.scenario_quantity(scenario);
+    let response = ui.add(DragValue::new(&mut quantity));
+    let response = match quantity_changed {
+        true => response.on_hover_text(format!("Baseline: {}", comp.quantity)),
+        false => response,
+    };
+    if response.changed() || reset_quantity || substitute.is_some() {
+        let o = comp.overrides.entry(scenario.to_string()).or_default();
+        if response.changed() {
+            o.quantity = Some(quantity);
+        }
+        if reset_quantity {
+            o.quantity = None;
+        }
+        if let Some(substitute) = substitute {
+            o.substitute = substitute;
+        }
+        if o.is_empty() {
+            comp.overrides.remove(scenario);
+        }
+    }
+    ui.label(comp.unit.map_or_else(
+        || format!("{:?}", comp.material.get_unit().unit),
+        |u| u.to_string(),
+    ))
+    .on_hover_text("The unit is the same in every scenario");
+    let modules = comp.scenario_modules(scenario, study_period);
+    for module in Module::ALL {
+        let value = modules.as_ref().map_or(0., |m| m.get(module));
+        ui.label(format!("{value:.2}"));
+    }
+    match &modules {
+        Err(e) => ui
+            .label(RichText::new("⚠").color(Color32::LIGHT_RED))
+            .on_hover_text(format!("Not calculated: {e}")),
+        Ok(m) => ui.label(format!("{:.2}", m.total())),
+    };
+    let alternatives = match substituted {
+        true => {
+            ui.label("");
+            false
+        }
+        false => benchmark_label(ui, comp, thresholds),
+    };
+    let edit = ui
+        .small_button("⚙")
+        .on_hover_text("Edit conversion factors and life-cycle assumptions")
+        .clicked();
+    alternatives || edit
+}
+
+/// Picks the scenario shown in the grid, where changes apply to it instead of the baseline
+fn scenario_bar(
+    ui: &mut egui::Ui,
+    project: &mut Project,
+    active: &mut Option<String>,
+    input: &mut String,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Scenario:");
+        ComboBox::from_id_source("scenario-picker")
+            .selected_text(active.as_deref().unwrap_or(BASELINE))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(active, None, BASELINE);
+                for scenario in &project.scenarios {
+                    ui.selectable_value(active, Some(scenario.clone()), scenario);
+                }
+            });
+        ui.add(
+            egui::TextEdit::singleline(input)
+                .desired_width(120.)
+                .hint_text("Scenario name"),
+        );
+        if ui
+            .button("Add")
+            .on_hover_text("New scenario with the components of the baseline")
+            .clicked()
+            && project.add_scenario(input)
+        {
+            *active = Some(input.trim().to_string());
+            input.clear();
+        }
+        if let Some(name) = active.clone() {
+            if ui.button("Rename").clicked() && project.rename_scenario(&name, input) {
+                *active = Some(input.trim().to_string());
+                input.clear();
+            }
+            if ui.button("Delete").clicked() {
+                project.remove_scenario(&name);
+                *active = None;
+            }
+        }
+    });
+}
+
+/// Totals of the scenarios next to the baseline, and their modules as grouped bars
+fn scenario_comparison(project: &Project, ui: &mut egui::Ui) {
+    let results = project.compare_scenarios();
+    egui::Grid::new("scenario-grid")
+        .num_columns(Module::ALL.len() + 5)
+        .spacing([24.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Scenario");
+            for module in Module::ALL {
+                ui.label(module.to_string());
+            }
+            ui.label("Total A-C (KgCO2e)");
+            ui.label("Δ (KgCO2e)");
+            ui.label("Δ (%)");
+            ui.label("Changed components");
+            ui.end_row();
+            for (i, result) in results.iter().enumerate() {
+                ui.label(&result.name);
+                for module in Module::ALL {
+                    ui.label(format!("{:.2}", result.modules.get(module)));
+                }
+                ui.strong(format!("{:.2}", result.total));
+                if i == 0 {
+                    ui.label("");
+                    ui.label("");
+                    ui.label("");
+                    ui.end_row();
+                    continue;
+                }
+                let color = match result.delta {
+                    d if d < 0. => Color32::LIGHT_GREEN,
+                    d if d > 0. => Color32::LIGHT_RED,
+                    _ => Color32::GRAY,
+                };
+                ui.label(RichText::new(format!("{:+.2}", result.delta)).color(color));
+                ui.label(
+                    RichText::new(
+                        result
+                            .delta_percent
+                            .map_or("-".to_string(), |p| format!("{p:+.1} %")),
+                    )
+                    .color(color),
+                );
+                ui.label(result.changes.to_string());
+                ui.end_row();
+            }
+        });
+
+    let width = 0.8 / results.len() as f64;
+    let charts: Vec<BarChart> = results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            let offset = (i as f64 - (results.len() - 1) as f64 / 2.) * width;
+            let bars = Module::ALL
+                .into_iter()
+                .enumerate()
+                .map(|(x, module)| {
+                    Bar::new(x as f64 + offset, result.modules.get(module))
+                        .width(width)
+                        .name(format!("{}, {module}", result.name))
+                })
+                .collect();
+            BarChart::new(bars).name(&result.name)
+        })
+        .collect();
+    Plot::new("scenarios-plot")
+        .height(260.)
+        .legend(Legend::default())
+        .x_axis_formatter(|mark, _, _| {
+            let index =
+                (mark.value >= 0. && mark.value.fract() == 0.).then_some(mark.value as usize);
+            match index.and_then(|i| Module::ALL.get(i)) {
+                Some(module) => module.to_string(),
+                None => String::new(),
+            }
+        })
+        .show(ui, |plot_ui| {
+            for chart in charts {
+                plot_ui.bar_chart(chart);
+            }
+        });
+}
+
+/// Percentile rank of a component in its category, coloured by the project thresholds.
+/// Returns true when the rank of a component worse than its category is clicked.
+fn benchmark_label(ui: &mut egui::Ui, comp: &Component, thresholds: &Thresholds) -> bool {
+    let gwp = comp.material.get_gwp().value;
+    let (text, hover) = match (&comp.benchmark, comp.percentile_rank()) {
+        (Some(benchmark), Some(rank)) => (format!("P{rank:.0}"), benchmark.describe(gwp)),
+        _ => (
+            String::new(),
+            "Compared to the category average, the category has no materials in the local database"
+                .to_string(),
+        ),
+    };
+    let (arrow, color) = match comp.cmp_to_benchmark(thresholds) {
+        CmpResult::Smaller => ("↓", Color32::LIGHT_GREEN),
+        CmpResult::Greater => ("↑", Color32::LIGHT_RED),
+        CmpResult::AlmostEqual => ("=", Color32::LIGHT_YELLOW),
+    };
+    let label = egui::Label::new(RichText::new(format!("{arrow} {text}")).color(color));
+    match comp.cmp_to_benchmark(thresholds) {
+        CmpResult::Greater => ui
+            .add(label.sense(egui::Sense::click()))
+            .on_hover_text(format!("{hover}\nClick for lower-carbon alternatives"))
+            .clicked(),
+        _ => {
+            ui.add(label).on_hover_text(hover);
+            false
+        }
+    }
+}
+
+/// Thresholds and country used to benchmark the components of the project
+fn benchmark_settings(project: &mut Project, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        let thresholds = &mut project.thresholds;
+        ui.label("Better than category below");
+        ui.add(
+            DragValue::new(&mut thresholds.better)
+                .clamp_range(0.0..=thresholds.worse)
+                .prefix("P"),
+        );
+        ui.label("worse above");
+        ui.add(
+            DragValue::new(&mut thresholds.worse)
+                .clamp_range(thresholds.better..=100.0)
+                .prefix("P"),
+        );
+        ui.separator();
+        ui.label("Benchmark country:");
+        let mut country = project.benchmark_country.clone().unwrap_or_default();
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut country)
+                .desired_width(40.)
+                .hint_text("all"),
+        );
+        if response.changed() {
+            let country = country.trim();
+            project.benchmark_country = (!country.is_empty()).then(|| country.to_uppercase());
+        }
+        if response.lost_focus() {
+            project.refresh_benchmarks();
+        }
+        response.on_hover_text(format!(
+            "Country code of the manufacturers to compare with. Categories with fewer than {} materials in the country are compared with all countries",
+            shared::benchmark::MIN_SAMPLE
+        ));
+    });
+}
+
+/// Side panel to edit the conversion factors and life-cycle assumptions of the selected component
+fn component_panel(state: &mut State, ui: &mut egui::Ui) {
+    let project = state.project.as_mut().unwrap();
+    let Some(index) = state
+        .selected_component
+        .filter(|i| *i < project.components.len())
+    else {
+        state.selected_component = None;
+        return;
+    };
+    // listed once per component, changes of its quantity or life cycle only mark them as
+    // out of date until they are refreshed
+    if !matches!(&state.alternatives, Some(a) if a.is_for(project, index)) {
+        let same_country = state.alternatives.as_ref().is_some_and(|a| a.same_country);
+        state.alternatives = Some(Alternatives::load(project, index, same_country));
+    }
+    let comp = &mut project.components[index];
+    let mut changed = false;
+    let mut close = false;
+    let mut swap = None;
+    let mut same_country = None;
+    let mut refresh = false;
+    egui::SidePanel::right("life-cycle-panel")
+        .resizable(true)
+        .show_inside(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.strong(comp.material.get_name());
+                close = ui.small_button("x").clicked();
+            });
+            ui.add_space(4.);
+            if let (Some(benchmark), Some(rank)) = (&comp.benchmark, comp.percentile_rank()) {
+                ui.label(format!("Percentile rank: P{rank:.0}"));
+                ui.add(egui::ProgressBar::new(rank as f32 / 100.).desired_width(160.));
+                ui.weak(benchmark.describe(comp.material.get_gwp().value));
+                ui.separator();
+            }
+            if let Some(alternatives) = &state.alternatives {
+                egui::CollapsingHeader::new("Lower-carbon alternatives")
+                    .default_open(matches!(
+                        comp.cmp_to_benchmark(&project.thresholds),
+                        CmpResult::Greater
+                    ))
+                    .show(ui, |ui| {
+                        if let Some(country) = &alternatives.country {
+                            let mut checked = alternatives.same_country;
+                            if ui
+                                .checkbox(&mut checked, format!("Only manufacturers in {country}"))
+                                .changed()
+                            {
+                                same_country = Some(checked);
+                            }
+                        }
+                        let outdated = alternatives.calculated != comp.calculated;
+                        if outdated {
+                            ui.horizontal(|ui| {
+                                ui.weak("The component changed since these were listed");
+                                refresh = ui.small_button("Refresh").clicked();
+                            });
+                        }
+                        if alternatives.recommendations.is_empty() {
+                            ui.weak("No material of the category with the same declared unit saves emissions");
+                        }
+                        let swap_hover = match (&state.active_scenario, outdated) {
+                            (Some(_), _) => "Swapping changes the baseline, switch to it or use ⇄ to change the material in this scenario",
+                            (None, true) => "Refresh the alternatives to swap",
+                            (None, false) => "Use this material, keeping the quantity",
+                        };
+                        let can_swap = state.active_scenario.is_none() && !outdated;
+                        egui::Grid::new("alternatives-grid")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (i, r) in alternatives.recommendations.iter().enumerate() {
+                                    ui.label(fit_to_width(&r.material.name, 30)).on_hover_text(
+                                        format!(
+                                            "{}\n{} ({})\n{:.2} KgCO2e/{:?}",
+                                            r.material.name,
+                                            r.material.manufacturer.name,
+                                            r.material.manufacturer.country.as_deref().unwrap_or("-"),
+                                            r.material.gwp.value,
+                                            r.material.declared_unit.unit,
+                                        ),
+                                    );
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "-{:.2} KgCO2e ({:.0} %)",
+                                            r.savings,
+                                            r.percent()
+                                        ))
+                                        .color(Color32::LIGHT_GREEN),
+                                    );
+                                    if ui
+                                        .add_enabled(can_swap, egui::Button::new("Swap").small())
+                                        .on_hover_text(swap_hover)
+                                        .on_disabled_hover_text(swap_hover)
+                                        .clicked()
+                                    {
+                                        swap = Some(i);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                ui.separator();
+            }
+            ui.horizontal(|ui| {
+                ui.label("GWP uncertainty");
+                match comp.gwp_distribution(project.uncertainty.epd_range) {
+                    GwpDistribution::Empirical(distribution) => {
+                        ui.weak(format!("distribution of {}", distribution.scope()));
+                    }
+                    GwpDistribution::Range { .. } => {
+                        ui.label("±");
+                        optional_value(ui, &mut comp.gwp_range, " %");
+                        if comp.gwp_range.is_none() {
+                            ui.weak(format!(
+                                "project default ±{} %",
+                                project.uncertainty.epd_range
+                            ));
+                        }
+                    }
+                }
+            });
+            ui.separator();
+            ui.label("Conversion factors");
+            let factors = &mut comp.factors;
+            egui::Grid::new("conversion-grid")
+                .num_columns(2)
+                .spacing([16.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Density");
+                    changed |= optional_value(ui, &mut factors.density, " kg/m³");
+                    ui.end_row();
+                    ui.label("Thickness");
+                    changed |= optional_value(ui, &mut factors.thickness, " m");
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                let mut save_as = None;
+                if let Some(id) = comp.material.get_id() {
+                    if ui.button("Save for material").clicked() {
+                        save_as = Some(id.to_string());
+                    }
+                }
+                if ui.button("Save for category").clicked() {
+                    save_as = Some(comp.material.get_category().to_string());
+                }
+                if let Some(key) = save_as {
+                    match shared::material_db::set_conversion_factors(&key, &comp.factors) {
+                        Ok(()) => toast_done(state.toasts.success("Conversion factors saved")),
+                        Err(e) => toast_done(
+                            state
+                                .toasts
+                                .error(format!("Could not save conversion factors: {e}")),
+                        ),
+                    };
+                }
+            });
+            ui.separator();
+            ui.label("Life cycle");
+            let unit = format!("{:?}", comp.material.get_unit().unit);
+            let lc = &mut comp.life_cycle;
+            egui::Grid::new("life-cycle-grid")
+                .num_columns(2)
+                .spacing([16.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("A4 transport distance");
+                    changed |= ui
+                        .add(DragValue::new(&mut lc.transport_km).suffix(" km"))
+                        .changed();
+                    ui.end_row();
+                    ui.label("A4 transport factor");
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut lc.transport_factor)
+                                .speed(0.001)
+                                .suffix(format!(" KgCO2e/{unit}/km")),
+                        )
+                        .changed();
+                    ui.end_row();
+                    ui.label("A5 waste rate");
+                    let mut waste = lc.waste_rate * 100.;
+                    if ui
+                        .add(
+                            DragValue::new(&mut waste)
+                                .clamp_range(0.0..=100.0)
+                                .suffix(" %"),
+                        )
+                        .changed()
+                    {
+                        lc.waste_rate = waste / 100.;
+                        changed = true;
+                    }
+                    ui.end_row();
+                    ui.label("B4 service life");
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut lc.service_life)
+                                .clamp_range(0.0..=200.0)
+                                .suffix(" years"),
+                        )
+                        .on_hover_text("0 means the component lasts the whole study period")
+                        .changed();
+                    ui.end_row();
+                    ui.label("C1-C4 end of life");
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut lc.end_of_life)
+                                .speed(0.01)
+                                .suffix(format!(" KgCO2e/{unit}")),
+                        )
+                        .changed();
+                    ui.end_row();
+                    ui.label("D beyond system boundary");
+                    changed |= ui
+                        .add(
+                            DragValue::new(&mut lc.beyond_boundary)
+                                .speed(0.01)
+                                .suffix(format!(" KgCO2e/{unit}")),
+                        )
+                        .changed();
+                    ui.end_row();
+                });
+        });
+    if changed {
+        project.calculate();
+    }
+    if refresh || same_country.is_some() {
+        let same_country = same_country
+            .or(state.alternatives.as_ref().map(|a| a.same_country))
+            .unwrap_or_default();
+        state.alternatives = Some(Alternatives::load(project, index, same_country));
+    }
+    if let Some(i) = swap {
+        let recommendation = state
+            .alternatives
+            .take()
+            .unwrap()
+            .recommendations
+            .swap_remove(i);
+        toast_done(state.toasts.success(format!(
+            "Swapped to {}, saving {:.2} KgCO2e",
+            recommendation.material.name, recommendation.savings
+        )));
+        project.swap_material(index, recommendation);
+    }
+    if close {
+        state.selected_component = None;
+    }
+}
+
+/// Edits a value which is unset while zero, returns true when it changed
+fn optional_value(ui: &mut egui::Ui, value: &mut Option<f64>, suffix: &str) -> bool {
+    let mut v = value.unwrap_or(0.);
+    let changed = ui
+        .add(
+            DragValue::new(&mut v)
+                .clamp_range(0.0..=f64::MAX)
+                .speed(0.01)
+                .suffix(suffix),
+        )
+        .on_hover_text("0 means not set")
+        .changed();
+    if changed {
+        *value = (v > 0.).then_some(v);
+    }
+    changed
+}
+
+/// Renders the GWP of each component as bars stacked by life-cycle module.
+/// Module D is drawn on its own since it is usually negative.
+fn render_modules_chart(project: &Project, ui: &mut egui::Ui) {
+    let colors = [
+        Color32::from_rgb(90, 129, 255),
+        Color32::from_rgb(120, 200, 160),
+        Color32::from_rgb(240, 190, 90),
+        Color32::from_rgb(230, 120, 110),
+        Color32::from_rgb(170, 130, 220),
+        Color32::GRAY,
+    ];
+    let mut charts: Vec<BarChart> = Vec::new();
+    for (module, color) in Module::ALL.into_iter().zip(colors) {
+        let bars = project
+            .all_components()
+            .enumerate()
+            .map(|(i, (assembly, comp))| {
+                let name = match assembly {
+                    Some(assembly) => format!("{assembly}: {}", comp.material.get_name()),
+                    None => comp.material.get_name().to_string(),
+                };
+                Bar::new(i as f64, comp.modules.get(module))
+                    .name(name)
+                    .fill(color)
+            })
+            .collect();
+        let chart = BarChart::new(bars).name(module.to_string()).color(color);
+        let chart = match module {
+            Module::D => chart,
+            _ => chart.stack_on(&charts.iter().collect::<Vec<_>>()),
+        };
+        charts.push(chart);
+    }
+    Plot::new("modules-plot")
+        .height(260.)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            for chart in charts {
+                plot_ui.bar_chart(chart);
+            }
         });
 }
 
@@ -268,7 +2054,7 @@ fn render_selected_material(state: &mut State, ui: &mut egui::Ui) {
             }
         });
 
-        let avg_stat = state.category_stats.unwrap_or(0.);
+        let avg_stat = state.category_stats.map_or(0., |stats| stats.mean);
         let cat_avg = RichText::new(format!(
             "Category average: {avg_stat:.2} {unit:?}",
             unit = selected.gwp.unit
@@ -284,6 +2070,30 @@ fn render_selected_material(state: &mut State, ui: &mut egui::Ui) {
             false => Color32::LIGHT_RED,
         };
         ui.label(gwp.color(color));
+        if let Some(benchmark) = &state.selected_benchmark {
+            ui.label(format!(
+                "P{rank:.0}: {description}",
+                rank = benchmark.rank(selected.gwp.value),
+                description = benchmark.describe(selected.gwp.value)
+            ));
+        }
+        if let Some(library) = &mut state.assembly_library {
+            if ui.button("Add to assembly").clicked() {
+                library.draft.layers.push(Layer {
+                    material: LayerMaterial::Ec3 {
+                        id: selected.id.clone(),
+                        name: selected.name.clone(),
+                    },
+                    amount: LayerAmount::default(),
+                });
+                library.calculate(
+                    state
+                        .project
+                        .as_ref()
+                        .map_or(shared::lifecycle::STUDY_PERIOD, |p| p.study_period),
+                );
+            }
+        }
         if ui.button("Add to project →").clicked() {
             state.active_tab = shared::Tabs::Calculate;
 
@@ -297,6 +2107,7 @@ fn render_selected_material(state: &mut State, ui: &mut egui::Ui) {
             }
         }
     });
+    render_history(&state.selected_history, ui);
     ui.separator();
     ScrollArea::vertical().show(ui, |ui| {
         ui.indent("more", |ui| {
@@ -308,6 +2119,98 @@ fn render_selected_material(state: &mut State, ui: &mut egui::Ui) {
     });
 }
 
+/// Plots the GWP of the selected material after each database update which changed it
+fn render_history(history: &[shared::material_db::HistoryPoint], ui: &mut egui::Ui) {
+    use shared::material_db::format_date;
+    const DAY: f64 = 86_400.;
+    match history {
+        [] => {}
+        [only] => {
+            ui.indent("history", |ui| {
+                ui.weak(format!(
+                    "GWP unchanged since {}",
+                    format_date(only.recorded_at)
+                ));
+            });
+        }
+        _ => {
+            let points: Vec<[f64; 2]> = history
+                .iter()
+                .map(|p| [p.recorded_at as f64 / DAY, p.gwp])
+                .collect();
+            // the last value holds until today
+            let mut steps: Vec<[f64; 2]> = points
+                .windows(2)
+                .flat_map(|w| [w[0], [w[1][0], w[0][1]]])
+                .collect();
+            steps.extend(points.last());
+            steps.push([
+                shared::material_db::now() as f64 / DAY,
+                points.last().map_or(0., |p| p[1]),
+            ]);
+            ui.label("GWP history");
+            Plot::new("gwp-history")
+                .height(120.)
+                .allow_scroll(false)
+                .x_axis_formatter(|mark, _, _| format_date((mark.value * DAY) as i64))
+                .label_formatter(|_, point| {
+                    format!("{}\n{:.2}", format_date((point.x * DAY) as i64), point.y)
+                })
+                .show(ui, |plot_ui| {
+                    plot_ui.line(Line::new(PlotPoints::from(steps)));
+                    plot_ui.points(Points::new(PlotPoints::from(points)).radius(3.));
+                });
+        }
+    }
+}
+
+/// Lists the materials whose GWP changed by more than the threshold in the last updates
+fn gwp_changes_window(ctx: &egui::Context, state: &mut State) {
+    let Some(changes) = &state.gwp_changes else {
+        return;
+    };
+    let mut open = true;
+    egui::Window::new(format!("GWP changes above {}%", state.change_threshold))
+        .open(&mut open)
+        .default_height(400.)
+        .show(ctx, |ui| {
+            if changes.is_empty() {
+                ui.label("No material changed by more than the threshold.");
+                return;
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("gwp-changes")
+                    .striped(true)
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        ui.strong("Material");
+                        ui.strong("Previous");
+                        ui.strong("Current");
+                        ui.strong("Change");
+                        ui.strong("Date");
+                        ui.end_row();
+                        for change in changes {
+                            ui.label(&change.name).on_hover_text(&change.category);
+                            ui.label(format!("{:.2}", change.previous));
+                            ui.label(format!("{:.2} / {}", change.current, change.declared_unit));
+                            let color = match change.current > change.previous {
+                                true => Color32::LIGHT_RED,
+                                false => Color32::LIGHT_GREEN,
+                            };
+                            ui.label(
+                                RichText::new(format!("{:+.1}%", change.percent())).color(color),
+                            );
+                            ui.label(shared::material_db::format_date(change.changed_at));
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    if !open {
+        state.gwp_changes = None;
+    }
+}
+
 fn chart_page(state: &mut State, ui: &mut egui::Ui) {
     add_filtering(ui, state);
     ui.separator();
@@ -345,106 +2248,259 @@ fn search_section(state: &mut State, ui: &mut egui::Ui) {
         .num_columns(2)
         .spacing([40.0, 4.0]);
 
+    let parsed = state.search_query();
+    let mut submitted = false;
     grid.show(ui, |ui| {
-        ui.label("Material Name:");
-        ui.text_edit_singleline(&mut state.fetch_input);
+        ui.label("Search:");
+        let input = ui.add(
+            egui::TextEdit::singleline(&mut state.fetch_input)
+                .hint_text("concrete gwp<250 country:DE sort:gwp")
+                .desired_width(320.),
+        );
+        submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
         ui.end_row();
 
+        if let Err(e) = &parsed {
+            ui.label("");
+            render_query_error(ui, &state.fetch_input, e);
+            ui.end_row();
+        }
+
         ui.label("Country:");
         ui.text_edit_singleline(&mut state.country);
         ui.end_row();
+
+        ui.label("Manufacturer:");
+        ui.text_edit_singleline(&mut state.manufacturer);
+        ui.end_row();
+
+        ui.label("Category:");
+        ui.add(
+            egui::TextEdit::singleline(&mut state.search_category)
+                .hint_text("includes subcategories"),
+        );
+        ui.end_row();
+
+        ui.label("GWP:");
+        ui.horizontal(|ui| {
+            optional_value(ui, &mut state.gwp_min, " min");
+            ui.label("-");
+            optional_value(ui, &mut state.gwp_max, " max");
+        });
+        ui.end_row();
     });
 
-    if ui
-        .button("Search")
-        .on_hover_text("Type a material name to search in EC3")
+    let valid = parsed.is_ok_and(|query| !query.is_empty());
+    if (ui
+        .add_enabled(valid, egui::Button::new("Search"))
+        .on_hover_text(
+            "Search the local copy of EC3. Filter with country:, manufacturer:, category:, unit:, gwp<, gwp> and sort:",
+        )
         .clicked()
-        && !state.fetch_input.is_empty()
+        || submitted)
+        && valid
     {
         state.fetch_materials_from_input();
         state.active_tab = shared::Tabs::List;
     }
     ui.separator();
     ui.end_row();
-    if ui.button("Update db")
-            .on_hover_text("This is a lengthy operation which downloads a new copy of EC3 materials locally for searching")
+    ui.horizontal(|ui| {
+        let options = &mut state.update_options;
+        let mut hours = options.max_age.as_secs() / 3600;
+        ui.label("Skip categories updated within");
+        ui.add_enabled(
+            !options.force,
+            DragValue::new(&mut hours)
+                .clamp_range(0..=24 * 365)
+                .suffix(" h"),
+        );
+        options.max_age = Duration::from_secs(hours * 3600);
+        ui.checkbox(&mut options.force, "Force")
+            .on_hover_text("Download every category, even if it was updated recently");
+    });
+    if ui
+            .add_enabled(state.update_job.is_none(), egui::Button::new("Update db"))
+            .on_hover_text("This is a lengthy operation which downloads a new copy of EC3 materials locally for searching. Interrupted categories are downloaded again")
             .clicked() {
                 if let Some(api_key) = &state.api_key {
-                    match shared::jobs::Runner::update_db(api_key) {
-                        Ok(rx) => state.job_rx = Some(rx),
+                    match shared::jobs::Runner::update_db(api_key, state.update_options.clone()) {
+                        Ok(job) => state.update_job = Some(job),
                         Err(_) => cb(state.toasts.error("Could not update db")),
                     };
-                    state.toasts.info("Db update in progress").set_duration(None);
                 }
                 else {
                     cb(state.toasts.error("Can't update db without API key!"));
                 }
         }
+    ui.end_row();
+    ui.horizontal(|ui| {
+        ui.label("GWP changes above");
+        ui.add(
+            DragValue::new(&mut state.change_threshold)
+                .clamp_range(0.0..=1000.0)
+                .suffix(" %"),
+        );
+        if ui
+            .button("Show")
+            .on_hover_text("Materials whose GWP per declared unit changed the last time the database was updated")
+            .clicked()
+        {
+            state.load_gwp_changes();
+        }
+    });
+}
+
+/// Shows the query with the offending token highlighted and the error message below
+fn render_query_error(ui: &mut egui::Ui, input: &str, error: &shared::query::ParseError) {
+    let font = egui::FontId::monospace(14.);
+    let normal = egui::TextFormat {
+        font_id: font.clone(),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let highlighted = egui::TextFormat {
+        font_id: font,
+        color: Color32::WHITE,
+        background: Color32::from_rgb(180, 60, 60),
+        ..Default::default()
+    };
+    let span = error.span.start.min(input.len())..error.span.end.min(input.len());
+    let mut job = egui::text::LayoutJob::default();
+    job.append(&input[..span.start], 0., normal.clone());
+    job.append(&input[span.clone()], 0., highlighted);
+    job.append(&input[span.end..], 0., normal);
+    ui.vertical(|ui| {
+        ui.label(job);
+        ui.label(RichText::new(&error.message).color(Color32::LIGHT_RED));
+    });
+}
+
+/// Renders a search snippet, highlighting the terms that matched the query
+fn render_snippet(ui: &mut egui::Ui, snippet: &str) {
+    use shared::material_db::{HIGHLIGHT_END, HIGHLIGHT_START};
+    let normal = egui::TextFormat {
+        color: ui.visuals().weak_text_color(),
+        ..Default::default()
+    };
+    let highlighted = egui::TextFormat {
+        color: ui.visuals().strong_text_color(),
+        background: ui.visuals().selection.bg_fill.gamma_multiply(0.4),
+        ..Default::default()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    // segments alternate between text outside and inside the highlight markers
+    for (i, segment) in snippet.split([HIGHLIGHT_START, HIGHLIGHT_END]).enumerate() {
+        let format = if i % 2 == 0 { &normal } else { &highlighted };
+        job.append(segment, 0., format.clone());
+    }
+    job.wrap.max_width = ui.available_width();
+    ui.label(job);
+}
+
+/// Number of materials of a category shown next to it in the tree
+fn count_label(ui: &mut egui::Ui, state: &State, name: &str) {
+    let count = state.category_counts.get(name).copied().unwrap_or_default();
+    ui.label(RichText::new(count.to_string()).weak().small())
+        .on_hover_text(format!("{count} materials in the database"));
 }
 
 /// Render recursively nodes in [shared::CategoriesTree]
 fn render_tree(ui: &mut egui::Ui, tree: &shared::CategoriesTree, state: &mut State) {
-    if let Some(subcategories) = &tree.children {
-        if subcategories.is_empty() {
+    if tree.children.is_empty() {
+        ui.horizontal(|ui| {
+            if ui
+                .selectable_label(state.selected_category == tree.name, &tree.name)
+                .on_hover_text("Show category details")
+                .clicked()
+            {
+                state.select_category(&tree.name);
+                state.active_tab = Tabs::Category;
+            }
+            count_label(ui, state, &tree.name);
+            if ui
+                .small_button("→")
+                .on_hover_text(format!("Search {}", tree.name))
+                .clicked()
+            {
+                // use the callback function here
+                state.load_by_category(&tree.name);
+                state.active_tab = shared::Tabs::List;
+            };
+        });
+    } else {
+        for v in &tree.children {
+            let name = &v.name.clone();
             ui.horizontal(|ui| {
-                ui.label(&tree.value.name);
+                let coll = ui.collapsing(name, |ui| {
+                    render_tree(ui, v, state);
+                });
+                count_label(ui, state, name);
                 if ui
-                    .small_button("→")
-                    .on_hover_text(format!("Search {}", tree.value.name))
+                    .small_button("ℹ")
+                    .on_hover_text(format!("Show details of {name}"))
                     .clicked()
+                {
+                    state.select_category(name);
+                    state.active_tab = Tabs::Category;
+                }
+                if !coll.fully_open()
+                    && ui
+                        .small_button("→")
+                        .on_hover_text(format!("Search {name} and its subcategories"))
+                        .clicked()
                 {
                     // use the callback function here
-                    state.load_by_category(&tree.value.name);
+                    state.load_by_category(name);
                     state.active_tab = shared::Tabs::List;
-                };
+                }
             });
-        } else {
-            for v in subcategories {
-                let name = &v.value.name.clone();
-                ui.horizontal(|ui| {
-                    let coll = ui.collapsing(name, |ui| {
-                        render_tree(ui, v, state);
-                    });
-                    if !coll.fully_open()
-                        && ui
-                            .small_button("→")
-                            .on_hover_text(format!("Search {name}"))
-                            .clicked()
-                    {
-                        // use the callback function here
-                        state.load_by_category(name);
-                        state.active_tab = shared::Tabs::List;
-                    }
-                });
-            }
         }
     }
 }
 
 /// Lazy loads and renders [shared::CategoriesTree]
 fn show_categories_tree(state: &mut State, ui: &mut egui::Ui) {
-    if state.preload_categories() {
-        ui.vertical_centered_justified(|ui| {
-            ui.label("Loading...");
-            // ui.spinner();
-        });
-        return;
+    let loading = state.preload_categories();
+    ui.horizontal(|ui| {
+        ui.label("Search materials from a category");
+        if loading {
+            ui.spinner();
+        } else if ui
+            .add_enabled(state.api_key.is_some(), egui::Button::new("⟳").small())
+            .on_hover_text("Refresh from EC3")
+            .on_disabled_hover_text("Refreshing requires an API key")
+            .clicked()
+        {
+            state.fetch_categories();
+        }
+    });
+    if let Some(e) = &state.categories_error {
+        ui.label(RichText::new(format!("⚠ Refresh failed: {e}")).color(Color32::LIGHT_RED));
     }
 
-    if let Some(categories) = state.categories.clone() {
-        ui.label("Search materials from a category");
-        ScrollArea::vertical()
-            .auto_shrink([false; 2])
-            .show(ui, |ui| {
-                render_tree(ui, &categories, state);
+    match state.categories.clone() {
+        Some(categories) if !categories.children.is_empty() => {
+            ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .show(ui, |ui| {
+                    render_tree(ui, &categories, state);
+                });
+        }
+        _ if loading => {
+            ui.vertical_centered_justified(|ui| {
+                ui.label("Loading...");
             });
-    } else {
-        state.fetch_categories();
+        }
+        _ => {
+            ui.label("No categories stored yet. Refresh them from EC3 or update the database.");
+        }
     }
 }
 
 /// Renders the materials available in the [State] state as a list view
 fn render_material_cards(state: &mut State, ui: &mut eframe::egui::Ui, filter: &str) {
+    let mut selected = None;
     for m in state
         .materials
         .iter()
@@ -462,9 +2518,10 @@ fn render_material_cards(state: &mut State, ui: &mut eframe::egui::Ui, filter: &
             .selectable_label(false, RichText::new(&m.name).heading())
             .clicked()
         {
-            // calculate category stats
-            state.category_stats = shared::material_db::get_category_stats(&m.category).ok();
-            state.selected = Some(m.clone());
+            selected = Some(m.clone());
+        }
+        if let Some(snippet) = state.snippets.get(&m.id) {
+            render_snippet(ui, snippet);
         }
         ui.monospace(&m.gwp.as_str());
         if let Some(c) = &m.manufacturer.country {
@@ -476,6 +2533,9 @@ fn render_material_cards(state: &mut State, ui: &mut eframe::egui::Ui, filter: &
         ui.add_space(2.);
         ui.separator();
     }
+    if let Some(material) = selected {
+        state.select_material(material);
+    }
 }
 
 /// Short way of adding a tab that is connected to a [Tabs] enum in [State]